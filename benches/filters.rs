@@ -0,0 +1,117 @@
+//! Times the spatial filters on 640×480 frames on a single core.
+//!
+//! Run with `cargo bench --bench filters`. It fails if a filter takes longer than a frame at 30
+//! frames per second, as it couldn't keep up with a device.
+use freenectrs::freenect::{DepthFrame, FreenectDepthFormat, Result, VideoFrame};
+use std::time::{Duration, Instant};
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 30);
+const RUNS: usize = 10;
+
+/// A room in millimeters with a box in front, noise and holes, the same on every run
+fn depth_frame() -> DepthFrame {
+    let mut seed: u32 = 1;
+    let mut random = move || {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        seed >> 16
+    };
+    let data = (0..WIDTH * HEIGHT)
+        .map(|i| {
+            let (x, y) = (i % WIDTH, i / WIDTH);
+            if random() % 20 == 0 {
+                return 0;
+            }
+            let depth = if (200..400).contains(&x) && (150..350).contains(&y) {
+                1200
+            } else {
+                2500 + x
+            };
+            (depth + random() % 16) as u16
+        })
+        .collect();
+    DepthFrame {
+        data,
+        timestamp: 0,
+        width: WIDTH,
+        height: HEIGHT,
+    }
+}
+
+fn video_frame() -> VideoFrame {
+    let data = (0..WIDTH * HEIGHT)
+        .flat_map(|i| {
+            let value = if (200..400).contains(&(i % WIDTH)) {
+                40
+            } else {
+                200
+            };
+            [value, value, value]
+        })
+        .collect();
+    VideoFrame {
+        data,
+        timestamp: 0,
+        width: WIDTH,
+        height: HEIGHT,
+    }
+}
+
+/// Prints the median time of `filter` over a few runs, and returns whether it keeps up with 30
+/// frames per second
+fn bench(name: &str, mut filter: impl FnMut() -> Result<DepthFrame>) -> bool {
+    let mut times: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            filter().unwrap();
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    let time = times[RUNS / 2];
+    let mark = if time > FRAME_TIME {
+        "  slower than 30 fps"
+    } else {
+        ""
+    };
+    println!(
+        "{:<28}{:>8.1} ms{}",
+        name,
+        time.as_secs_f64() * 1000.0,
+        mark
+    );
+    time <= FRAME_TIME
+}
+
+fn main() {
+    let format = FreenectDepthFormat::MM;
+    let depth = depth_frame();
+    let guide = video_frame();
+    let mut fast = true;
+    for radius in 1..=3 {
+        fast &= bench(&format!("median({})", radius), || {
+            depth.median(format, radius)
+        });
+    }
+    for radius in 1..=3 {
+        fast &= bench(&format!("bilateral({})", radius), || {
+            depth.bilateral(format, radius, radius as f32, 30.0)
+        });
+    }
+    for radius in 1..=2 {
+        fast &= bench(&format!("joint_bilateral({})", radius), || {
+            depth.joint_bilateral(format, &guide.as_frame_ref(), radius, radius as f32, 10.0)
+        });
+    }
+    for radius in 1..=3 {
+        fast &= bench(&format!("fill_holes({})", radius), || {
+            depth.fill_holes(format, radius)
+        });
+    }
+    fast &= bench("inpaint_nearest()", || depth.inpaint_nearest(format));
+    if !fast {
+        eprintln!("Some filters are slower than 30 frames per second");
+        std::process::exit(1);
+    }
+}
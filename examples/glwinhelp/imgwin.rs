@@ -100,7 +100,7 @@ impl ImgWindow {
     fn new<T: Into<String>>(title: T, main_loop: &EventLoop<()>) -> ImgWindow {
         let wb = WindowBuilder::new().with_title(title.into());
         let cb = ContextBuilder::new().with_vsync(true);
-        let display = glium::Display::new(wb, cb, &main_loop).unwrap();
+        let display = glium::Display::new(wb, cb, main_loop).unwrap();
 
         // vertex for a rect for drawing an image to the whole window
         let vertex_buffer = glium::VertexBuffer::new(
@@ -126,7 +126,7 @@ impl ImgWindow {
         )
         .unwrap();
         let index_buffer =
-            glium::IndexBuffer::new(&display, PrimitiveType::TriangleStrip, &[1 as u16, 2, 0, 3])
+            glium::IndexBuffer::new(&display, PrimitiveType::TriangleStrip, &[1u16, 2, 0, 3])
                 .unwrap();
         // just enough shader for drawing images
         let program = program!(&display, 
@@ -159,9 +159,9 @@ impl ImgWindow {
         ImgWindow {
             texture: None,
             facade: display,
-            vertex_buffer: vertex_buffer,
-            index_buffer: index_buffer,
-            program: program,
+            vertex_buffer,
+            index_buffer,
+            program,
         }
     }
 
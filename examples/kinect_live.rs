@@ -5,39 +5,14 @@ use glwinhelp::{imgwin, VirtualKeyCode};
 use crate::glwinhelp::imgwin::ImgWindow;
 use freenectrs::freenect;
 use freenectrs::freenect::{
-    FreenectContext, FreenectDepthStream, FreenectError, FreenectVideoStream,
+    DeliveryPolicy, DepthColorizer, FreenectContext, FreenectDepthStream, FreenectError,
+    FreenectVideoStream,
 };
 use std::error::Error;
 
-#[inline]
-fn depth_to_img(data: &[u16]) -> image::RgbaImage {
-    image::RgbaImage::from_fn(640, 480, |x, y| {
-        let idx = y * 640 + x;
-        // the depth value for the current pixel
-        let depth_value = data[idx as usize];
-
-        // we start at a value of 600 for depth
-        let depth_value = if depth_value > 600 {
-            depth_value - 600
-        } else {
-            0
-        };
-        // scale the value down
-        let depth_value = depth_value / 2;
-        // and use this value as a gray value by clipping everything above the maximal
-        // allowed value 255.
-        let gray_value = if depth_value > 255 { 255 } else { depth_value };
-        let gray_value = gray_value as u8;
-        image::Rgba([gray_value, gray_value, gray_value, 255])
-    })
-}
-
 pub fn main() -> Result<(), Box<dyn Error>> {
     // we init the device with support for depth, video and motor
-    let ctx = Box::new(freenect::FreenectContext::init_with_video_motor()?);
-    // We create a 'static lifetime by leaking. Doing so we can fulfill the requirement of glium's
-    // mainloop run (called in Application::run)
-    let ctx = Box::leak(ctx);
+    let ctx = freenect::FreenectContext::init_with_video_motor()?;
 
     // check if a kinect device is available
     let dev_count = ctx.num_devices()?;
@@ -48,11 +23,7 @@ pub fn main() -> Result<(), Box<dyn Error>> {
         println!("Found {} devices, use first", dev_count);
     }
     // For simplification we always take the fist available device
-    let device = Box::new(ctx.open_device(0)?);
-    // Same as above.
-    // We create a 'static lifetime by leaking. Doing so we can fulfill the requirement of glium's
-    // mainloop run (called in Application::run)
-    let device = Box::leak(device);
+    let device = ctx.open_device(0)?;
     // init the depth and video mode
     device.set_depth_mode(
         freenect::FreenectResolution::Medium,
@@ -69,9 +40,9 @@ pub fn main() -> Result<(), Box<dyn Error>> {
     let dwin = app.new_window("Live Depth");
     let vwin = app.new_window("Live RGB");
 
-    // getting the streams
-    let dstream = device.depth_stream()?;
-    let vstream = device.video_stream()?;
+    // getting the streams, for live display we only care about the latest frame
+    let dstream = device.depth_stream_with_policy(DeliveryPolicy::KeepLatest)?;
+    let vstream = device.video_stream_with_policy(DeliveryPolicy::KeepLatest)?;
 
     // the image on which we draw the rgb and depth information
     let dimg = image::RgbaImage::new(640, 480);
@@ -85,6 +56,7 @@ pub fn main() -> Result<(), Box<dyn Error>> {
         is_closed: false,
         vstream,
         dstream,
+        colors: DepthColorizer::default(),
         dimg,
         vimg,
         dwin,
@@ -96,15 +68,17 @@ pub fn main() -> Result<(), Box<dyn Error>> {
 }
 
 /// Handler for the main loop
-struct InputHandler<'a, 'b: 'a> {
+struct InputHandler {
     /// freenect device we actually use
-    device: &'a freenect::FreenectDevice<'a, 'b>,
+    device: freenect::FreenectDevice,
     /// indicates if a windows get close (-> exit app)
     is_closed: bool,
     /// the rgb bytes stream from kinect
-    vstream: FreenectVideoStream<'a, 'b>,
+    vstream: FreenectVideoStream,
     /// the depth bytes from kinect
-    dstream: FreenectDepthStream<'a, 'b>,
+    dstream: FreenectDepthStream,
+    /// draws the depth bytes with a colour map
+    colors: DepthColorizer,
     /// the image we create from the depth bytes
     dimg: image::RgbaImage,
     /// the image we create from the rgb bytes
@@ -114,10 +88,10 @@ struct InputHandler<'a, 'b: 'a> {
     /// the window on which we draw the rgb image
     vwin: ImgWindow,
     /// the freenect context so we can stop its main loop
-    ctx: &'a FreenectContext,
+    ctx: FreenectContext,
 }
 
-impl<'a, 'b> imgwin::MainloopHandler for InputHandler<'a, 'b> {
+impl imgwin::MainloopHandler for InputHandler {
     fn close_event(&mut self) {
         self.is_closed = true;
     }
@@ -152,17 +126,17 @@ impl<'a, 'b> imgwin::MainloopHandler for InputHandler<'a, 'b> {
 
     fn next_frame(&mut self) {
         // get and render the depth bytes to an image
-        if let Ok((data, _ /* timestamp */)) = self.dstream.receiver.try_recv() {
-            self.dimg = depth_to_img(&*data);
+        if let Ok(frame) = self.dstream.receiver.try_recv() {
+            if let Some(img) = self.colors.colorize_image(&frame.as_frame_ref()) {
+                self.dimg = image::DynamicImage::ImageRgb8(img).to_rgba8();
+            }
         }
 
         // get and create an image from the rgb bytes
-        if let Ok((data, _ /* timestamp */)) = self.vstream.receiver.try_recv() {
-            self.vimg = image::RgbaImage::from_fn(640, 480, |x, y| {
-                let idx = 3 * (y * 640 + x) as usize;
-                let (r, g, b) = (data[idx], data[idx + 1], data[idx + 2]);
-                image::Rgba([r, g, b, 255])
-            });
+        if let Ok(frame) = self.vstream.receiver.try_recv() {
+            if let Some(img) = frame.into_rgb_image() {
+                self.vimg = image::DynamicImage::ImageRgb8(img).to_rgba8();
+            }
         }
 
         // and draw it to the windows
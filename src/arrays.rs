@@ -0,0 +1,164 @@
+//! Views of frames as `ndarray` arrays, enabled by the `ndarray` feature.
+//!
+//! Arrays are indexed by row first: depth as `[[y, x]]` and video as `[[y, x, channel]]`.
+use crate::freenect::{
+    DepthFrame, DepthFrameRef, FreenectError, Result, VideoFrame, VideoFrameRef,
+};
+use ndarray::{Array2, Array3, Array4, ArrayView2, ArrayView3, Axis};
+
+/// Returns the number of bytes per pixel of a video frame, if `len` holds whole pixels
+fn channels(len: usize, width: u32, height: u32) -> Option<usize> {
+    let pixels = width as usize * height as usize;
+    if pixels == 0 || len % pixels != 0 {
+        None
+    } else {
+        Some(len / pixels)
+    }
+}
+
+impl DepthFrame {
+    /// Views the frame as a height × width array. Returns `None` if the size of `data` doesn't
+    /// match `width` and `height`.
+    pub fn as_array(&self) -> Option<ArrayView2<'_, u16>> {
+        self.as_frame_ref().as_array()
+    }
+
+    /// Turns the frame into a height × width array without copying. Returns `None` if the size
+    /// of `data` doesn't match `width` and `height`.
+    pub fn into_array(self) -> Option<Array2<u16>> {
+        Array2::from_shape_vec((self.height as usize, self.width as usize), self.data).ok()
+    }
+
+    /// Creates a frame from a height × width array. The timestamp is 0.
+    pub fn from_array(array: Array2<u16>) -> DepthFrame {
+        let (height, width) = array.dim();
+        DepthFrame {
+            data: array.into_iter().collect(),
+            timestamp: 0,
+            width: width as u32,
+            height: height as u32,
+        }
+    }
+}
+
+impl<'a> DepthFrameRef<'a> {
+    /// Views the frame as a height × width array. Returns `None` if the size of `data` doesn't
+    /// match `width` and `height`.
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::DepthFrameRef;
+    ///
+    /// let data = [1, 2, 3, 4, 5, 6];
+    /// let frame = DepthFrameRef { data: &data, timestamp: 0, width: 3, height: 2 };
+    /// let depth = frame.as_array().unwrap();
+    /// assert_eq!(depth.dim(), (2, 3));
+    /// // The value at x = 2, y = 1
+    /// assert_eq!(depth[[1, 2]], 6);
+    /// ```
+    pub fn as_array(&self) -> Option<ArrayView2<'a, u16>> {
+        ArrayView2::from_shape((self.height as usize, self.width as usize), self.data).ok()
+    }
+}
+
+impl VideoFrame {
+    /// Views the frame as a height × width × channel array, with one channel per byte of a
+    /// pixel: 3 for rgb and 1 for 8-bit infrared. Returns `None` if `data` doesn't hold whole pixels.
+    pub fn as_array(&self) -> Option<ArrayView3<'_, u8>> {
+        self.as_frame_ref().as_array()
+    }
+
+    /// Turns the frame into a height × width × channel array without copying, see
+    /// [`as_array()`](#method.as_array). Returns `None` if `data` doesn't hold whole pixels.
+    pub fn into_array(self) -> Option<Array3<u8>> {
+        let channels = channels(self.data.len(), self.width, self.height)?;
+        Array3::from_shape_vec(
+            (self.height as usize, self.width as usize, channels),
+            self.data,
+        )
+        .ok()
+    }
+
+    /// Creates a frame from a height × width × channel array. The timestamp is 0.
+    pub fn from_array(array: Array3<u8>) -> VideoFrame {
+        let (height, width, _) = array.dim();
+        VideoFrame {
+            data: array.into_iter().collect(),
+            timestamp: 0,
+            width: width as u32,
+            height: height as u32,
+        }
+    }
+}
+
+impl<'a> VideoFrameRef<'a> {
+    /// Views the frame as a height × width × channel array, see
+    /// [`VideoFrame::as_array()`](struct.VideoFrame.html#method.as_array)
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::VideoFrameRef;
+    ///
+    /// let data = [10, 20, 30, 40, 50, 60];
+    /// let frame = VideoFrameRef { data: &data, timestamp: 0, width: 2, height: 1 };
+    /// let rgb = frame.as_array().unwrap();
+    /// assert_eq!(rgb.dim(), (1, 2, 3));
+    /// // The green value of the pixel at x = 1, y = 0
+    /// assert_eq!(rgb[[0, 1, 1]], 50);
+    /// ```
+    pub fn as_array(&self) -> Option<ArrayView3<'a, u8>> {
+        let channels = channels(self.data.len(), self.width, self.height)?;
+        ArrayView3::from_shape(
+            (self.height as usize, self.width as usize, channels),
+            self.data,
+        )
+        .ok()
+    }
+}
+
+/// Stacks depth frames into a frame × height × width array, for statistics over time.
+/// Fails if there is no frame or if the frames differ in size.
+/// # Examples
+/// ```rust
+/// use freenectrs::freenect::{stack_depth_frames, DepthFrame};
+/// use ndarray::Axis;
+///
+/// let frames: Vec<DepthFrame> = (0..4u16)
+///     .map(|i| DepthFrame { data: vec![1000 + i, 2000 - i], timestamp: i as u32, width: 2, height: 1 })
+///     .collect();
+/// let stack = stack_depth_frames(&frames).unwrap();
+/// assert_eq!(stack.dim(), (4, 1, 2));
+///
+/// // The mean and the standard deviation of each pixel
+/// let stack = stack.mapv(f64::from);
+/// let mean = stack.mean_axis(Axis(0)).unwrap();
+/// let deviation = stack.std_axis(Axis(0), 0.0);
+/// assert_eq!(mean[[0, 0]], 1001.5);
+/// assert_eq!(mean[[0, 1]], 1998.5);
+/// assert!((deviation[[0, 0]] - 1.25f64.sqrt()).abs() < 1e-9);
+/// ```
+pub fn stack_depth_frames(frames: &[DepthFrame]) -> Result<Array3<u16>> {
+    let views = frames
+        .iter()
+        .map(|frame| {
+            frame
+                .as_array()
+                .ok_or_else(|| FreenectError::new("Depth frame has the wrong size"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ndarray::stack(Axis(0), &views)
+        .map_err(|err| FreenectError::new(format!("Unable to stack depth frames: {}", err)))
+}
+
+/// Stacks video frames into a frame × height × width × channel array.
+/// Fails if there is no frame or if the frames differ in size or format.
+pub fn stack_video_frames(frames: &[VideoFrame]) -> Result<Array4<u8>> {
+    let views = frames
+        .iter()
+        .map(|frame| {
+            frame
+                .as_array()
+                .ok_or_else(|| FreenectError::new("Video frame has the wrong size"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ndarray::stack(Axis(0), &views)
+        .map_err(|err| FreenectError::new(format!("Unable to stack video frames: {}", err)))
+}
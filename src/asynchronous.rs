@@ -0,0 +1,115 @@
+//! `futures_core::Stream` support for depth and video streams, enabled by the `async` feature.
+use crate::freenect::{
+    DepthFrame, FreenectDepthStream, FreenectVideoStream, TemporalDepthStream, VideoFrame,
+};
+use futures_core::Stream;
+use std::future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+impl Stream for FreenectDepthStream {
+    type Item = DepthFrame;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<DepthFrame>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl Stream for TemporalDepthStream {
+    type Item = DepthFrame;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<DepthFrame>> {
+        let this = self.get_mut();
+        this.stream
+            .receiver
+            .poll_recv(cx)
+            .map(|frame| frame.map(|frame| this.filter.filter(&frame.as_frame_ref())))
+    }
+}
+
+impl Stream for FreenectVideoStream {
+    type Item = VideoFrame;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<VideoFrame>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// Combines a depth and a video stream to fetch depth and rgb frames taken at about the same time.
+/// # Examples
+/// ```rust,ignore
+/// let dstream = device.depth_stream_with_policy(DeliveryPolicy::KeepLatest)?;
+/// let vstream = device.video_stream_with_policy(DeliveryPolicy::KeepLatest)?;
+/// let mut synced = FreenectSyncedStreams::new(dstream, vstream);
+/// while let Some((depth, video)) = synced.next_synced_pair().await {
+///     // ... handle both frames
+/// }
+/// ```
+pub struct FreenectSyncedStreams {
+    pub depth: FreenectDepthStream,
+    pub video: FreenectVideoStream,
+    max_offset: u32,
+    pending_depth: Option<DepthFrame>,
+    pending_video: Option<VideoFrame>,
+}
+
+// libfreenect's timestamps count ticks of the device's clock, which runs at about 60 MHz
+const CLOCK_TICKS_PER_SECOND: u32 = 60_000_000;
+
+// The time between two frames at 30 frames per second, in clock ticks
+const FRAME_PERIOD: u32 = CLOCK_TICKS_PER_SECOND / 30;
+
+impl FreenectSyncedStreams {
+    /// The timestamp difference a pair may have if created with [`new()`](#method.new), in ticks
+    /// of the device's clock. It is half the time between two frames at 30 frames per second, so
+    /// each frame pairs with the frame of the other stream nearest to it and with no other one.
+    pub const DEFAULT_MAX_OFFSET: u32 = FRAME_PERIOD / 2;
+
+    pub fn new(depth: FreenectDepthStream, video: FreenectVideoStream) -> Self {
+        FreenectSyncedStreams::with_max_offset(depth, video, Self::DEFAULT_MAX_OFFSET)
+    }
+
+    /// Creates a synced stream which only pairs frames whose timestamps differ by at most
+    /// `max_offset` ticks of the device's clock
+    pub fn with_max_offset(
+        depth: FreenectDepthStream,
+        video: FreenectVideoStream,
+        max_offset: u32,
+    ) -> Self {
+        FreenectSyncedStreams {
+            depth,
+            video,
+            max_offset,
+            pending_depth: None,
+            pending_video: None,
+        }
+    }
+
+    /// Waits for the next depth and video frame which belong together.
+    /// Frames without a partner are dropped. Returns `None` if one of the streams has ended.
+    pub async fn next_synced_pair(&mut self) -> Option<(DepthFrame, VideoFrame)> {
+        loop {
+            if self.pending_depth.is_none() {
+                let depth = &self.depth;
+                self.pending_depth =
+                    Some(future::poll_fn(|cx| depth.receiver.poll_recv(cx)).await?);
+            }
+            if self.pending_video.is_none() {
+                let video = &self.video;
+                self.pending_video =
+                    Some(future::poll_fn(|cx| video.receiver.poll_recv(cx)).await?);
+            }
+            let depth_ts = self.pending_depth.as_ref()?.timestamp;
+            let video_ts = self.pending_video.as_ref()?.timestamp;
+            // Timestamps wrap around, so their order is given by the sign of the difference
+            let diff = depth_ts.wrapping_sub(video_ts) as i32;
+            if diff.unsigned_abs() <= self.max_offset {
+                return Some((self.pending_depth.take()?, self.pending_video.take()?));
+            } else if diff < 0 {
+                self.pending_depth = None;
+            } else {
+                self.pending_video = None;
+            }
+        }
+    }
+}
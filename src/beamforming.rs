@@ -0,0 +1,282 @@
+//! Direction of arrival estimation and beamforming for the microphone array.
+use std::f64::consts::PI;
+
+/// Speed of sound in air in m/s
+const SPEED_OF_SOUND: f64 = 343.0;
+
+/// Estimated direction of a sound source
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Direction {
+    /// Angle in degrees. 0 is straight ahead, positive angles point toward microphones with larger positions.
+    pub angle: f64,
+    /// How well the channels agree on this angle, from 0 (not at all) to 1 (perfectly)
+    pub confidence: f64,
+}
+
+/// A linear microphone array, used to locate sound sources and to steer toward them.
+///
+/// Sound sources are assumed to be far away compared to the size of the array,
+/// so their sound reaches the array as plane wave.
+/// # Examples
+/// ```rust
+/// use freenectrs::freenect::MicArray;
+/// use std::io::Cursor;
+///
+/// // A noise source 40° off-axis, recorded as four-channel WAV
+/// let array = MicArray::kinect();
+/// let delays = array.delays(40.0);
+/// let mut seed = 1u32;
+/// let noise: Vec<i32> = (0..2048)
+///     .map(|_| {
+///         seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+///         (seed >> 8) as i32 - (1 << 23)
+///     })
+///     .collect();
+/// let spec = hound::WavSpec {
+///     channels: 4,
+///     sample_rate: 16_000,
+///     bits_per_sample: 32,
+///     sample_format: hound::SampleFormat::Int,
+/// };
+/// let mut wav = Cursor::new(Vec::new());
+/// let mut writer = hound::WavWriter::new(&mut wav, spec).unwrap();
+/// for t in 100..1900 {
+///     for delay in &delays {
+///         writer.write_sample(noise[t - delay.round() as usize]).unwrap();
+///     }
+/// }
+/// writer.finalize().unwrap();
+///
+/// wav.set_position(0);
+/// let samples: Vec<i32> = hound::WavReader::new(wav)
+///     .unwrap()
+///     .samples()
+///     .map(|s| s.unwrap())
+///     .collect();
+/// let direction = array.estimate_direction(&samples).unwrap();
+/// assert!((direction.angle - 40.0).abs() < 5.0);
+///
+/// // Steering toward the source keeps the noise, steering away attenuates it
+/// let power = |signal: Vec<f32>| signal.iter().map(|&s| (s as f64).powi(2)).sum::<f64>();
+/// assert!(power(array.beamform(&samples, 40.0)) > 1.5 * power(array.beamform(&samples, -40.0)));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct MicArray {
+    positions: [f64; 4],
+    sample_rate: f64,
+}
+
+impl MicArray {
+    /// Positions of Kinect's microphones in metres along its x axis, relative to the center of the sensor
+    pub const KINECT_POSITIONS: [f64; 4] = [-0.113, 0.036, 0.076, 0.113];
+    /// Kinect's sample rate in Hz
+    pub const KINECT_SAMPLE_RATE: f64 = 16_000.0;
+
+    /// Creates an array whose microphones are placed at `positions`, given in metres along a line
+    /// in the order of the channels
+    pub fn new(positions: [f64; 4], sample_rate: f64) -> MicArray {
+        MicArray {
+            positions,
+            sample_rate,
+        }
+    }
+
+    /// The array of Kinect, with the channels in the order of
+    /// [`AudioFrame::mics`](struct.AudioFrame.html#structfield.mics)
+    pub fn kinect() -> MicArray {
+        MicArray::new(MicArray::KINECT_POSITIONS, MicArray::KINECT_SAMPLE_RATE)
+    }
+
+    /// Returns for each microphone how many samples later than the first one it receives a sound
+    /// coming from `angle` degrees. The delays are fractional and at least 0.
+    pub fn delays(&self, angle: f64) -> [f64; 4] {
+        let arrivals = self.arrivals(angle);
+        let first = arrivals.iter().cloned().fold(f64::INFINITY, f64::min);
+        [
+            arrivals[0] - first,
+            arrivals[1] - first,
+            arrivals[2] - first,
+            arrivals[3] - first,
+        ]
+    }
+
+    /// Arrival times in samples relative to the center of the array
+    fn arrivals(&self, angle: f64) -> [f64; 4] {
+        let factor = -angle.to_radians().sin() / SPEED_OF_SOUND * self.sample_rate;
+        [
+            self.positions[0] * factor,
+            self.positions[1] * factor,
+            self.positions[2] * factor,
+            self.positions[3] * factor,
+        ]
+    }
+
+    /// Estimates the direction of the dominant sound source within interleaved four-channel samples,
+    /// like those returned by [`AudioFrame::interleaved()`](struct.AudioFrame.html#method.interleaved).
+    ///
+    /// The cross-correlation of each pair of microphones is computed with phase transform (GCC-PHAT)
+    /// and the angle from -90 to 90 degrees is chosen which fits all pairs best.
+    /// Returns `None` if there are less than two samples per channel.
+    pub fn estimate_direction(&self, interleaved: &[i32]) -> Option<Direction> {
+        let len = interleaved.len() / 4;
+        if len < 2 {
+            return None;
+        }
+        // Padding to twice the length avoids that the correlation wraps around
+        let size = (2 * len).next_power_of_two();
+        let spectra: Vec<Vec<Complex>> = (0..4)
+            .map(|mic| {
+                let mut buffer: Vec<Complex> = (0..size)
+                    .map(|i| {
+                        let sample = if i < len {
+                            interleaved[4 * i + mic] as f64
+                        } else {
+                            0.0
+                        };
+                        Complex::new(sample, 0.0)
+                    })
+                    .collect();
+                fft(&mut buffer);
+                buffer
+            })
+            .collect();
+        // Phase transform: only the phase of the cross spectrum is kept
+        let mut pairs = Vec::with_capacity(6);
+        for i in 0..4 {
+            for j in i + 1..4 {
+                let cross: Vec<Complex> = (1..size / 2)
+                    .map(|k| {
+                        let c = spectra[i][k].mul(spectra[j][k].conj());
+                        let norm = c.abs();
+                        if norm > 1e-12 {
+                            c.scale(1.0 / norm)
+                        } else {
+                            Complex::new(0.0, 0.0)
+                        }
+                    })
+                    .collect();
+                pairs.push((i, j, cross));
+            }
+        }
+        let mut best: Option<Direction> = None;
+        for step in -180..=180 {
+            let angle = step as f64 * 0.5;
+            let arrivals = self.arrivals(angle);
+            let mut score = 0.0;
+            for &(i, j, ref cross) in &pairs {
+                score += gcc_at(cross, size, arrivals[i] - arrivals[j]);
+            }
+            let confidence = (score / pairs.len() as f64).max(0.0);
+            if best.map_or(true, |b| confidence > b.confidence) {
+                best = Some(Direction { angle, confidence });
+            }
+        }
+        best
+    }
+
+    /// Steers the array toward `angle` degrees and returns the average of the aligned channels
+    /// (delay-and-sum), which amplifies sound from this direction compared to other directions.
+    /// Takes interleaved four-channel samples and returns one sample per frame.
+    pub fn beamform(&self, interleaved: &[i32], angle: f64) -> Vec<f32> {
+        let len = interleaved.len() / 4;
+        let delays = self.delays(angle);
+        (0..len)
+            .map(|t| {
+                let sum: f64 = (0..4)
+                    .map(|mic| {
+                        // The sound reaches this microphone `delays[mic]` samples later
+                        let pos = t as f64 + delays[mic];
+                        let index = pos.floor() as usize;
+                        let frac = pos - pos.floor();
+                        let at = |i: usize| {
+                            if i < len {
+                                interleaved[4 * i + mic] as f64
+                            } else {
+                                0.0
+                            }
+                        };
+                        at(index) * (1.0 - frac) + at(index + 1) * frac
+                    })
+                    .sum();
+                (sum / 4.0) as f32
+            })
+            .collect()
+    }
+}
+
+/// Evaluates the normalized cross-correlation given by its spectrum `cross` at the fractional `lag`
+fn gcc_at(cross: &[Complex], size: usize, lag: f64) -> f64 {
+    let sum: f64 = cross
+        .iter()
+        .enumerate()
+        .map(|(k, c)| {
+            let phase = 2.0 * PI * (k + 1) as f64 * lag / size as f64;
+            c.re * phase.cos() - c.im * phase.sin()
+        })
+        .sum();
+    sum / cross.len() as f64
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+
+    fn conj(self) -> Complex {
+        Complex::new(self.re, -self.im)
+    }
+
+    fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    fn scale(self, factor: f64) -> Complex {
+        Complex::new(self.re * factor, self.im * factor)
+    }
+}
+
+/// In-place radix-2 FFT. The length of `buffer` must be a power of two.
+fn fft(buffer: &mut [Complex]) {
+    let n = buffer.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buffer.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        let root = Complex::new(angle.cos(), angle.sin());
+        for start in (0..n).step_by(len) {
+            let mut w = Complex::new(1.0, 0.0);
+            for k in 0..len / 2 {
+                let a = buffer[start + k];
+                let b = buffer[start + k + len / 2].mul(w);
+                buffer[start + k] = Complex::new(a.re + b.re, a.im + b.im);
+                buffer[start + k + len / 2] = Complex::new(a.re - b.re, a.im - b.im);
+                w = w.mul(root);
+            }
+        }
+        len <<= 1;
+    }
+}
@@ -0,0 +1,495 @@
+//! Command-line tool to inspect and control Kinect devices and to capture their frames.
+//!
+//! Run `kinect help` for the available commands.
+use freenectrs::freenect::{
+    DeliveryPolicy, DepthFrame, DeviceGroup, DumpEntryKind, FakenectDump, FakenectWriter, Frame,
+    FreenectContext, FreenectDepthFormat, FreenectDevice, FreenectLed, GroupStream, VideoFrame,
+};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+type CliResult = Result<(), Box<dyn Error>>;
+
+const USAGE: &str = "Usage: kinect <command> [options]
+
+Commands:
+  list                      List devices, their serials and the supported modes
+  info                      Show current modes, tilt and accelerometer
+  tilt <degrees>            Tilt the device
+  led <color>               Set the LED: off, green, red, yellow, blink-green, blink-red-yellow
+  snapshot                  Write one depth and one rgb frame
+      --out <prefix>        File name prefix (default: snapshot)
+      --format <pgm|png>    Format of the depth map (default: png)
+  record <dir>              Record frames as a fakenect dump
+      --frames <n>          Stop after n frames
+      --seconds <s>         Stop after s seconds (default: 10 without --frames)
+  play <dir>                Print the frames of a dump at the pace they were recorded at
+      --fast                Don't wait between frames
+      --depth-format <f>    Depth format of the dump, for the share of valid readings
+                            (default: 11bit, which libfreenect's record tool writes)
+  stats                     Print frame rates and dropped frames
+      --seconds <s>         Stop after s seconds (default: run until interrupted)
+  view                      Show depth and rgb in the terminal, if built with the tui feature
+
+Options:
+  --device <n>              Use the device with this number (default: 0)
+  --serial <serial>         Use the device with this serial
+  --dump <dir>              Read frames from a fakenect dump instead of a device
+                            (info, snapshot, record, stats and view)";
+
+const VALUE_OPTIONS: &[&str] = &[
+    "device",
+    "serial",
+    "dump",
+    "out",
+    "format",
+    "frames",
+    "seconds",
+    "depth-format",
+];
+const SWITCHES: &[&str] = &["fast"];
+// How long to wait for the first frame of a device before giving up
+const FRAME_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The parsed command line
+struct Args {
+    command: String,
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, Box<dyn Error>> {
+        let command = args.next().unwrap_or_else(|| "help".to_owned());
+        let mut parsed = Args {
+            command,
+            positional: Vec::new(),
+            options: HashMap::new(),
+        };
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if VALUE_OPTIONS.contains(&name) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("Missing value for --{}", name))?;
+                    parsed.options.insert(name.to_owned(), value);
+                }
+                Some(name) if SWITCHES.contains(&name) => {
+                    parsed.options.insert(name.to_owned(), String::new());
+                }
+                Some(name) => return Err(format!("Unknown option --{}", name).into()),
+                None => parsed.positional.push(arg),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn positional(&self, nr: usize, name: &str) -> Result<&str, Box<dyn Error>> {
+        self.positional
+            .get(nr)
+            .map(String::as_str)
+            .ok_or_else(|| format!("Missing argument <{}>", name).into())
+    }
+
+    fn option<T: std::str::FromStr>(&self, name: &str) -> Result<Option<T>, Box<dyn Error>> {
+        match self.options.get(name) {
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("Invalid value for --{}: {}", name, value).into()),
+            None => Ok(None),
+        }
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+}
+
+/// Opens the device selected on the command line
+fn open_device(args: &Args) -> Result<(FreenectContext, FreenectDevice), Box<dyn Error>> {
+    let ctx = FreenectContext::init_with_video_motor()?;
+    let device = match args.options.get("serial") {
+        Some(serial) => ctx.open_device_by_serial(serial)?,
+        None => ctx.open_device(args.option("device")?.unwrap_or(0))?,
+    };
+    Ok((ctx, device))
+}
+
+/// Where frames come from: a device or a recording
+enum Source {
+    Device {
+        group: DeviceGroup,
+        stream: GroupStream,
+        // Dropped last, after the streams are stopped
+        _ctx: FreenectContext,
+    },
+    Dump {
+        dump: FakenectDump,
+        next: usize,
+    },
+}
+
+impl Source {
+    /// Opens the dump or device selected on the command line. Frames of a device are delivered
+    /// with `policy`.
+    fn open(args: &Args, policy: DeliveryPolicy) -> Result<Source, Box<dyn Error>> {
+        if let Some(dir) = args.options.get("dump") {
+            return Ok(Source::Dump {
+                dump: FakenectDump::open(dir)?,
+                next: 0,
+            });
+        }
+        let (ctx, device) = open_device(args)?;
+        let group = DeviceGroup::from_devices(&ctx, vec![device]);
+        let stream = group.start(true, true, policy)?;
+        Ok(Source::Device {
+            group,
+            stream,
+            _ctx: ctx,
+        })
+    }
+
+    /// Returns the next frame, or `None` once a recording ends
+    fn next_frame(&mut self) -> Option<Result<Frame, Box<dyn Error>>> {
+        match *self {
+            Source::Device { ref stream, .. } => Some(
+                stream
+                    .receiver
+                    .recv_timeout(FRAME_TIMEOUT)
+                    .map(|tagged| tagged.frame)
+                    .map_err(|_| "No frame received from the device".into()),
+            ),
+            Source::Dump {
+                ref dump,
+                ref mut next,
+            } => {
+                let entries = dump.entries();
+                while entries.get(*next)?.kind == DumpEntryKind::Accelerometer {
+                    *next += 1;
+                }
+                *next += 1;
+                Some(dump.read(&entries[*next - 1]).map_err(Into::into))
+            }
+        }
+    }
+
+    /// Reads frames until there is one depth and one rgb frame
+    fn snapshot(&mut self) -> Result<(DepthFrame, VideoFrame), Box<dyn Error>> {
+        let (mut depth, mut video) = (None, None);
+        while depth.is_none() || video.is_none() {
+            match self.next_frame() {
+                Some(frame) => match frame? {
+                    Frame::Depth(frame) => depth = depth.or(Some(frame)),
+                    Frame::Video(frame) => video = video.or(Some(frame)),
+                },
+                None => return Err("The dump lacks a depth or rgb frame".into()),
+            }
+        }
+        Ok((depth.unwrap(), video.unwrap()))
+    }
+}
+
+fn list() -> CliResult {
+    let ctx = FreenectContext::init()?;
+    let serials = ctx.device_serials()?;
+    println!("Devices:");
+    for nr in 0..ctx.num_devices()? {
+        let serial = serials.get(nr as usize).map(String::as_str);
+        println!("  {}\t{}", nr, serial.unwrap_or("unknown serial"));
+    }
+    println!("Depth modes:");
+    for mode in FreenectContext::supported_depth_modes() {
+        println!(
+            "  {}\t{}\t{}x{}\t{} fps",
+            mode.resolution, mode.format, mode.width, mode.height, mode.framerate
+        );
+    }
+    println!("Video modes:");
+    for mode in FreenectContext::supported_video_modes() {
+        println!(
+            "  {}\t{}\t{}x{}\t{} fps",
+            mode.resolution, mode.format, mode.width, mode.height, mode.framerate
+        );
+    }
+    Ok(())
+}
+
+fn info(args: &Args) -> CliResult {
+    if let Some(dir) = args.options.get("dump") {
+        let dump = FakenectDump::open(dir)?;
+        let entries = dump.entries();
+        let count = |kind| entries.iter().filter(|entry| entry.kind == kind).count();
+        println!("Dump:          {}", dump.dir().display());
+        println!("Depth frames:  {}", count(DumpEntryKind::Depth));
+        println!("Rgb frames:    {}", count(DumpEntryKind::Video));
+        if let (Some(first), Some(last)) = (entries.first(), entries.last()) {
+            println!("Duration:      {:.2} s", last.time - first.time);
+        }
+        for frame in dump.frames().take(2) {
+            match frame?.1 {
+                Frame::Depth(frame) => println!("Depth size:    {}x{}", frame.width, frame.height),
+                Frame::Video(frame) => println!("Rgb size:      {}x{}", frame.width, frame.height),
+            }
+        }
+        return Ok(());
+    }
+    let (_ctx, device) = open_device(args)?;
+    println!("Serial:        {}", device.serial().unwrap_or("unknown"));
+    match device.current_depth_mode() {
+        Some(mode) => println!(
+            "Depth mode:    {} {} {}x{}",
+            mode.resolution, mode.format, mode.width, mode.height
+        ),
+        None => println!("Depth mode:    unknown"),
+    }
+    match device.current_video_mode() {
+        Some(mode) => println!(
+            "Video mode:    {} {} {}x{}",
+            mode.resolution, mode.format, mode.width, mode.height
+        ),
+        None => println!("Video mode:    unknown"),
+    }
+    let state = device.get_tilt_state()?;
+    let [x, y, z] = state.accelerometer;
+    println!("Tilt:          {:.1}° ({})", state.degree, state.status);
+    println!("Accelerometer: {:.2} {:.2} {:.2} m/s²", x, y, z);
+    Ok(())
+}
+
+fn tilt(args: &Args) -> CliResult {
+    let degrees: f64 = args
+        .positional(0, "degrees")?
+        .parse()
+        .map_err(|_| "Invalid tilt degrees")?;
+    let (_ctx, device) = open_device(args)?;
+    device.set_tilt_degree(degrees)?;
+    Ok(())
+}
+
+fn led(args: &Args) -> CliResult {
+    let led: FreenectLed = args.positional(0, "color")?.parse()?;
+    let (_ctx, device) = open_device(args)?;
+    device.set_led(led)?;
+    Ok(())
+}
+
+fn snapshot(args: &Args) -> CliResult {
+    let prefix = args
+        .options
+        .get("out")
+        .map(String::as_str)
+        .unwrap_or("snapshot");
+    let format = args
+        .options
+        .get("format")
+        .map(String::as_str)
+        .unwrap_or("png");
+    let (depth, video) = Source::open(args, DeliveryPolicy::default())?.snapshot()?;
+
+    let depth_path = format!("{}-depth.{}", prefix, format);
+    match format {
+        "png" => depth.save_png(&depth_path)?,
+        "pgm" => {
+            // 16-bit PGM stores its values big-endian
+            let mut contents = format!("P5 {} {} 65535\n", depth.width, depth.height).into_bytes();
+            for value in &depth.data {
+                contents.extend_from_slice(&value.to_be_bytes());
+            }
+            fs::write(&depth_path, contents)?;
+        }
+        _ => return Err(format!("Unknown depth format {}", format).into()),
+    }
+    println!("{}", depth_path);
+
+    let video_path = format!("{}-rgb.png", prefix);
+    video
+        .into_rgb_image()
+        .ok_or("Only rgb frames can be saved")?
+        .save(&video_path)?;
+    println!("{}", video_path);
+    Ok(())
+}
+
+fn record(args: &Args) -> CliResult {
+    let dir = PathBuf::from(args.positional(0, "dir")?);
+    let frames: Option<u64> = args.option("frames")?;
+    let seconds: Option<f64> = args.option("seconds")?;
+    let seconds = match (frames, seconds) {
+        (None, None) => Some(10.0),
+        (_, seconds) => seconds,
+    };
+    // A recording waits for the writer rather than losing frames
+    let mut source = Source::open(args, DeliveryPolicy::Block { capacity: 8 })?;
+    let mut writer = FakenectWriter::create(&dir)?;
+    let start = Instant::now();
+    let mut written = 0;
+    while frames.map_or(true, |frames| written < frames)
+        && seconds.map_or(true, |seconds| start.elapsed().as_secs_f64() < seconds)
+    {
+        match source.next_frame() {
+            Some(frame) => match frame? {
+                Frame::Depth(frame) => writer.write_depth(&frame.as_frame_ref())?,
+                Frame::Video(frame) => writer.write_video(&frame.as_frame_ref())?,
+            },
+            None => break,
+        }
+        written += 1;
+    }
+    println!("Recorded {} frames to {}", written, dir.display());
+    Ok(())
+}
+
+fn play(args: &Args) -> CliResult {
+    let dump = FakenectDump::open(args.positional(0, "dir")?)?;
+    let realtime = !args.switch("fast");
+    let format: FreenectDepthFormat = args
+        .option("depth-format")?
+        .unwrap_or(FreenectDepthFormat::Bit11);
+    let mut previous: Option<f64> = None;
+    for frame in dump.frames() {
+        let (time, frame) = frame?;
+        if let (true, Some(previous)) = (realtime, previous) {
+            if time > previous {
+                thread::sleep(Duration::from_secs_f64(time - previous));
+            }
+        }
+        previous = Some(time);
+        match frame {
+            Frame::Depth(frame) => {
+                let valid = frame
+                    .data
+                    .iter()
+                    .filter(|&&value| format.is_reading(value))
+                    .count();
+                println!(
+                    "{:.6}\tdepth\t{}\t{}x{}\t{:.1}% valid",
+                    time,
+                    frame.timestamp,
+                    frame.width,
+                    frame.height,
+                    100.0 * valid as f64 / frame.data.len().max(1) as f64
+                );
+            }
+            Frame::Video(frame) => println!(
+                "{:.6}\trgb\t{}\t{}x{}",
+                time, frame.timestamp, frame.width, frame.height
+            ),
+        }
+    }
+    Ok(())
+}
+
+fn stats(args: &Args) -> CliResult {
+    let seconds: Option<f64> = args.option("seconds")?;
+    match Source::open(args, DeliveryPolicy::default())? {
+        Source::Device { group, stream, .. } => {
+            let start = Instant::now();
+            let mut report = Instant::now();
+            while seconds.map_or(true, |seconds| start.elapsed().as_secs_f64() < seconds) {
+                // Frames are only counted as dropped if they aren't received fast enough
+                let _ = stream.receiver.recv_timeout(Duration::from_millis(100));
+                if report.elapsed() >= Duration::from_secs(1) {
+                    report = Instant::now();
+                    for stats in group.stats() {
+                        println!(
+                            "depth {:.1} fps\trgb {:.1} fps\tdropped {}",
+                            stats.depth_fps(),
+                            stats.video_fps(),
+                            stats.dropped_frames
+                        );
+                    }
+                }
+            }
+        }
+        Source::Dump { dump, .. } => {
+            for (kind, name) in [
+                (DumpEntryKind::Depth, "depth"),
+                (DumpEntryKind::Video, "rgb"),
+            ] {
+                let times: Vec<f64> = dump
+                    .entries()
+                    .iter()
+                    .filter(|entry| entry.kind == kind)
+                    .map(|entry| entry.time)
+                    .collect();
+                let (fps, dropped) = recorded_rate(&times);
+                println!(
+                    "{} {:.1} fps\t{} frames\tdropped {}",
+                    name,
+                    fps,
+                    times.len(),
+                    dropped
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "tui")]
+fn view(args: &Args) -> CliResult {
+    use freenectrs::freenect::{TerminalViewer, ViewerConfig};
+
+    let viewer = TerminalViewer::new(ViewerConfig::default());
+    if let Some(dir) = args.options.get("dump") {
+        viewer.run_dump(&FakenectDump::open(dir)?)?;
+    } else {
+        let (ctx, device) = open_device(args)?;
+        viewer.run_device(&ctx, &device)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "tui"))]
+fn view(_args: &Args) -> CliResult {
+    Err("kinect was built without the tui feature".into())
+}
+
+/// Returns the frame rate of a recording and the number of frames missing from it.
+/// A gap of several typical frame intervals counts as that many frames minus one.
+fn recorded_rate(times: &[f64]) -> (f64, u64) {
+    let mut intervals: Vec<f64> = times.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    if intervals.is_empty() {
+        return (0.0, 0);
+    }
+    let duration: f64 = intervals.iter().sum();
+    intervals.sort_by(|a, b| a.total_cmp(b));
+    let typical = intervals[intervals.len() / 2];
+    if typical <= 0.0 {
+        return (0.0, 0);
+    }
+    let dropped = intervals
+        .iter()
+        .map(|interval| (interval / typical).round().max(1.0) as u64 - 1)
+        .sum();
+    (intervals.len() as f64 / duration, dropped)
+}
+
+fn main() {
+    let result =
+        Args::parse(std::env::args().skip(1)).and_then(|args| match args.command.as_str() {
+            "list" => list(),
+            "info" => info(&args),
+            "tilt" => tilt(&args),
+            "led" => led(&args),
+            "snapshot" => snapshot(&args),
+            "record" => record(&args),
+            "play" => play(&args),
+            "stats" => stats(&args),
+            "view" => view(&args),
+            "help" | "--help" | "-h" => {
+                println!("{}", USAGE);
+                Ok(())
+            }
+            command => Err(format!("Unknown command {}\n\n{}", command, USAGE).into()),
+        });
+    if let Err(err) = result {
+        eprintln!("kinect: {}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,245 @@
+//! Caller-owned memory which frames are written into without copying.
+use crate::freenect::{FreenectError, Result};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, Weak};
+
+/// Memory libfreenect can write frames into, for example pinned or memory-mapped buffers.
+///
+/// # Safety
+/// [`as_mut_ptr()`](#tymethod.as_mut_ptr) must return the same address for as long as the value
+/// lives, even if the value is moved, and [`byte_len()`](#tymethod.byte_len) bytes starting at this
+/// address must be writable.
+///
+/// Depth frames are read as `u16`, so buffers of depth streams must also be aligned to 2 bytes.
+/// Buffer streams check the size and the alignment of each buffer before libfreenect writes into
+/// it, and drop buffers which don't fit.
+pub unsafe trait FrameBuffer: Send + 'static {
+    fn as_mut_ptr(&mut self) -> *mut u8;
+    /// Returns the size of the buffer in bytes
+    fn byte_len(&self) -> usize;
+}
+
+unsafe impl FrameBuffer for Vec<u8> {
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.as_mut_slice().as_mut_ptr()
+    }
+
+    fn byte_len(&self) -> usize {
+        self.len()
+    }
+}
+
+unsafe impl FrameBuffer for Vec<u16> {
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.as_mut_slice().as_mut_ptr() as *mut u8
+    }
+
+    fn byte_len(&self) -> usize {
+        self.len() * 2
+    }
+}
+
+unsafe impl FrameBuffer for Box<[u8]> {
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.as_mut().as_mut_ptr()
+    }
+
+    fn byte_len(&self) -> usize {
+        self.len()
+    }
+}
+
+unsafe impl FrameBuffer for Box<[u16]> {
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.as_mut().as_mut_ptr() as *mut u8
+    }
+
+    fn byte_len(&self) -> usize {
+        self.len() * 2
+    }
+}
+
+/// Rotates frames through a fixed set of buffers.
+///
+/// One buffer is the current one, which the next frame is written into. [`rotate()`](#method.rotate)
+/// hands it out once it is filled and makes a free buffer the current one. Buffers return to the
+/// ring when their [`FilledBuffer`](struct.FilledBuffer.html) is dropped, so a buffer is never
+/// written into while it is handed out.
+///
+/// [`depth_buffer_stream()`][depth] and [`video_buffer_stream()`][video] use it to deliver frames.
+///
+/// [depth]: struct.FreenectDevice.html#method.depth_buffer_stream
+/// [video]: struct.FreenectDevice.html#method.video_buffer_stream
+/// # Examples
+/// ```rust
+/// use freenectrs::freenect::BufferRing;
+///
+/// let mut ring = BufferRing::new(vec![vec![0u8; 4]; 3]).unwrap();
+/// ring.current()[0] = 1;
+/// let first = ring.rotate(10, 2, 2).unwrap();
+/// assert_eq!((first[0], first.timestamp), (1, 10));
+///
+/// // The current buffer can't be handed out once no free buffer is left to take its place
+/// ring.current()[0] = 2;
+/// let second = ring.rotate(11, 2, 2).unwrap();
+/// assert_eq!(ring.available(), 0);
+/// assert!(ring.rotate(12, 2, 2).is_none());
+///
+/// // Dropping a filled buffer returns it to the ring
+/// drop(first);
+/// assert_eq!(ring.available(), 1);
+/// assert!(ring.rotate(12, 2, 2).is_some());
+/// assert_eq!(second.into_inner(), vec![2, 0, 0, 0]);
+/// ```
+pub struct BufferRing<B> {
+    current: B,
+    free: Arc<Mutex<Vec<B>>>,
+    // The size and alignment each buffer needs before it becomes the current one
+    min_byte_len: usize,
+    align: usize,
+}
+
+impl<B: FrameBuffer> BufferRing<B> {
+    /// Creates a ring of the given buffers. Fails if there are less than two buffers.
+    pub fn new(mut buffers: Vec<B>) -> Result<BufferRing<B>> {
+        if buffers.len() < 2 {
+            return Err(FreenectError::new(
+                "A buffer ring needs at least two buffers",
+            ));
+        }
+        let current = buffers.remove(0);
+        Ok(BufferRing {
+            current,
+            free: Arc::new(Mutex::new(buffers)),
+            min_byte_len: 0,
+            align: 1,
+        })
+    }
+
+    /// Returns the buffer the next frame is written into
+    pub fn current(&mut self) -> &mut B {
+        &mut self.current
+    }
+
+    /// Returns the number of buffers which are neither current nor handed out
+    pub fn available(&self) -> usize {
+        self.free.lock().unwrap().len()
+    }
+
+    /// Returns whether `buffer` has the size and alignment the ring requires
+    fn fits(buffer: &mut B, min_byte_len: usize, align: usize) -> bool {
+        buffer.byte_len() >= min_byte_len && (buffer.as_mut_ptr() as usize) % align == 0
+    }
+
+    /// Requires each buffer to hold at least `min_byte_len` bytes at an address aligned to `align`.
+    /// Fails if a buffer of the ring doesn't, later buffers which don't are dropped by [`rotate()`](#method.rotate).
+    pub(crate) fn require(&mut self, min_byte_len: usize, align: usize) -> Result<()> {
+        self.min_byte_len = min_byte_len;
+        self.align = align;
+        let fits = Self::fits(&mut self.current, min_byte_len, align)
+            && self
+                .free
+                .lock()
+                .unwrap()
+                .iter_mut()
+                .all(|buffer| Self::fits(buffer, min_byte_len, align));
+        if !fits {
+            return Err(FreenectError::new(format!(
+                "Buffers must hold at least {} bytes aligned to {} bytes",
+                min_byte_len, align
+            )));
+        }
+        Ok(())
+    }
+
+    /// Hands out the current buffer as a frame of the given properties and makes a free buffer the current one.
+    /// Free buffers which no longer have the size or alignment the stream needs are dropped.
+    /// Returns `None` and keeps the current buffer if there is no free buffer left.
+    pub fn rotate(&mut self, timestamp: u32, width: u32, height: u32) -> Option<FilledBuffer<B>> {
+        let next = loop {
+            let mut next = self.free.lock().unwrap().pop()?;
+            if Self::fits(&mut next, self.min_byte_len, self.align) {
+                break next;
+            }
+        };
+        let filled = std::mem::replace(&mut self.current, next);
+        Some(FilledBuffer {
+            buffer: Some(filled),
+            ring: Arc::downgrade(&self.free),
+            timestamp,
+            width,
+            height,
+        })
+    }
+}
+
+/// A buffer holding a frame, handed out by a [`BufferRing`](struct.BufferRing.html).
+/// It dereferences to the buffer and returns to the ring when dropped. A buffer which was shrunk
+/// or replaced meanwhile is only written into again if it still fits the stream.
+#[derive(Debug)]
+pub struct FilledBuffer<B> {
+    // Only taken by into_inner() and drop()
+    buffer: Option<B>,
+    ring: Weak<Mutex<Vec<B>>>,
+    /// The timestamp libfreenect assigned to this frame
+    pub timestamp: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl<B> FilledBuffer<B> {
+    /// Takes the buffer out of its ring for good
+    pub fn into_inner(mut self) -> B {
+        self.buffer.take().unwrap()
+    }
+}
+
+impl<B> Deref for FilledBuffer<B> {
+    type Target = B;
+
+    fn deref(&self) -> &B {
+        self.buffer.as_ref().unwrap()
+    }
+}
+
+impl<B> DerefMut for FilledBuffer<B> {
+    fn deref_mut(&mut self) -> &mut B {
+        self.buffer.as_mut().unwrap()
+    }
+}
+
+impl<B> Drop for FilledBuffer<B> {
+    fn drop(&mut self) {
+        if let (Some(buffer), Some(ring)) = (self.buffer.take(), self.ring.upgrade()) {
+            ring.lock().unwrap().push(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_which_no_longer_fit_are_dropped() {
+        let mut ring = BufferRing::new(vec![vec![0u8; 4]; 3]).unwrap();
+        ring.require(4, 1).unwrap();
+        let mut filled = ring.rotate(0, 2, 2).unwrap();
+        filled.truncate(1);
+        drop(filled);
+        assert_eq!(ring.available(), 2);
+        // The shrunk buffer is skipped, the other one becomes current
+        let _second = ring.rotate(1, 2, 2).unwrap();
+        assert_eq!(ring.current().len(), 4);
+        assert_eq!(ring.available(), 0);
+        assert!(ring.rotate(2, 2, 2).is_none());
+    }
+
+    #[test]
+    fn small_or_misaligned_buffers_are_refused() {
+        let mut ring = BufferRing::new(vec![vec![0u8; 4], vec![0u8; 2]]).unwrap();
+        assert!(ring.require(4, 1).is_err());
+        let mut ring = BufferRing::new(vec![vec![0u16; 2]; 2]).unwrap();
+        assert!(ring.require(4, 2).is_ok());
+    }
+}
@@ -0,0 +1,474 @@
+//! The channel which hands frames from libfreenect's callbacks over to the receiving side of a stream.
+use crate::freenect::{self, FreenectError};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{RecvError, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+/// Decides what happens with a new frame if the receiver of a stream can't keep up.
+///
+/// Pass it to [`depth_stream_with_policy()`][depth] or [`video_stream_with_policy()`][video].
+///
+/// [depth]: struct.FreenectDevice.html#method.depth_stream_with_policy
+/// [video]: struct.FreenectDevice.html#method.video_stream_with_policy
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeliveryPolicy {
+    /// Only the most recent frame is kept. A new frame overwrites the one not yet received.
+    /// Use this for live display.
+    KeepLatest,
+    /// Up to `capacity` frames are queued. If the queue is full, `overflow` decides which frame gets dropped.
+    /// The capacity must be at least 1, streams with a capacity of 0 fail to start.
+    Queue {
+        capacity: usize,
+        overflow: OverflowStrategy,
+    },
+    /// Up to `capacity` frames are queued. If the queue is full, libfreenect's callback waits until
+    /// the receiver has taken a frame. No frame gets lost, but the process thread stalls meanwhile.
+    /// Use this for recording. The capacity must be at least 1, streams with a capacity of 0 fail to start.
+    Block { capacity: usize },
+}
+
+/// Which frame to drop if a [`DeliveryPolicy::Queue`](enum.DeliveryPolicy.html#variant.Queue) is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowStrategy {
+    /// Removes the oldest queued frame to make room for the new one.
+    DropOldest,
+    /// Discards the new frame and keeps the queue as it is.
+    DropNewest,
+}
+
+impl Default for DeliveryPolicy {
+    /// A queue of two frames which drops the newest frame when full.
+    fn default() -> DeliveryPolicy {
+        DeliveryPolicy::Queue {
+            capacity: 2,
+            overflow: OverflowStrategy::DropNewest,
+        }
+    }
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    dropped: u64,
+    sender_alive: bool,
+    receiver_alive: bool,
+    // Set while an async receiver waits for the next frame
+    waker: Option<Waker>,
+}
+
+impl<T> State<T> {
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+struct Shared<T> {
+    policy: DeliveryPolicy,
+    state: Mutex<State<T>>,
+    available: Condvar,
+    space: Condvar,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap()
+    }
+}
+
+// How often a blocked sender checks whether its thread was asked to stop
+const INTERRUPT_POLL: Duration = Duration::from_millis(20);
+
+thread_local! {
+    // Set on the process thread, tells blocked senders that the thread is asked to stop
+    static INTERRUPT: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// Lets senders of a `Block` channel on the current thread give up waiting once `flag` is set.
+/// They drop the new frame then, so a thread blocked by a slow receiver can still be joined.
+pub(crate) fn set_interrupt(flag: Arc<AtomicBool>) {
+    INTERRUPT.with(|interrupt| *interrupt.borrow_mut() = Some(flag));
+}
+
+fn interrupted() -> bool {
+    INTERRUPT.with(|interrupt| {
+        interrupt
+            .borrow()
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::SeqCst))
+    })
+}
+
+/// Fails for queues without room for a single frame
+pub(crate) fn check_policy(policy: DeliveryPolicy) -> freenect::Result<()> {
+    match policy {
+        DeliveryPolicy::Queue { capacity: 0, .. } | DeliveryPolicy::Block { capacity: 0 } => Err(
+            FreenectError::new("Delivery policy needs a capacity of at least 1"),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Creates a connected sender and receiver which deliver frames according to `policy`.
+/// Fails for queues without room for a single frame.
+pub(crate) fn frame_channel<T>(
+    policy: DeliveryPolicy,
+) -> freenect::Result<(FrameSender<T>, FrameReceiver<T>)> {
+    check_policy(policy)?;
+    let shared = Arc::new(Shared {
+        policy,
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            dropped: 0,
+            sender_alive: true,
+            receiver_alive: true,
+            waker: None,
+        }),
+        available: Condvar::new(),
+        space: Condvar::new(),
+    });
+    Ok((
+        FrameSender {
+            shared: shared.clone(),
+        },
+        FrameReceiver { shared },
+    ))
+}
+
+/// The sending half, used within libfreenect's callbacks.
+pub(crate) struct FrameSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> FrameSender<T> {
+    /// Delivers `frame` according to the channel's policy.
+    /// Returns the frame the policy dropped, if any, or the given frame as error if the receiver has been dropped.
+    /// A `Block` sender also drops the new frame if its thread is interrupted while waiting, see `set_interrupt`.
+    pub fn send(&self, frame: T) -> Result<Option<T>, T> {
+        let mut state = self.shared.lock();
+        if !state.receiver_alive {
+            return Err(frame);
+        }
+        let dropped = match self.shared.policy {
+            DeliveryPolicy::KeepLatest => state.queue.pop_front(),
+            DeliveryPolicy::Queue { capacity, overflow } => {
+                if state.queue.len() < capacity {
+                    None
+                } else {
+                    match overflow {
+                        OverflowStrategy::DropOldest => state.queue.pop_front(),
+                        OverflowStrategy::DropNewest => {
+                            state.dropped += 1;
+                            return Ok(Some(frame));
+                        }
+                    }
+                }
+            }
+            DeliveryPolicy::Block { capacity } => {
+                while state.receiver_alive && state.queue.len() >= capacity {
+                    if interrupted() {
+                        state.dropped += 1;
+                        return Ok(Some(frame));
+                    }
+                    state = self
+                        .shared
+                        .space
+                        .wait_timeout(state, INTERRUPT_POLL)
+                        .unwrap()
+                        .0;
+                }
+                if !state.receiver_alive {
+                    return Err(frame);
+                }
+                None
+            }
+        };
+        if dropped.is_some() {
+            state.dropped += 1;
+        }
+        state.queue.push_back(frame);
+        state.wake();
+        self.shared.available.notify_one();
+        Ok(dropped)
+    }
+}
+
+impl<T> Drop for FrameSender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.sender_alive = false;
+        state.wake();
+        self.shared.available.notify_all();
+    }
+}
+
+/// The receiving half of a depth or video stream.
+///
+/// Its methods mirror those of `std::sync::mpsc::Receiver`.
+pub struct FrameReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> FrameReceiver<T> {
+    fn take(&self, state: &mut State<T>) -> Option<T> {
+        let frame = state.queue.pop_front();
+        if frame.is_some() {
+            self.shared.space.notify_one();
+        }
+        frame
+    }
+
+    /// Waits for the next frame.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(frame) = self.take(&mut state) {
+                return Ok(frame);
+            }
+            if !state.sender_alive {
+                return Err(RecvError);
+            }
+            state = self.shared.available.wait(state).unwrap();
+        }
+    }
+
+    /// Returns the next frame if one is available without waiting.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+        match self.take(&mut state) {
+            Some(frame) => Ok(frame),
+            None if state.sender_alive => Err(TryRecvError::Empty),
+            None => Err(TryRecvError::Disconnected),
+        }
+    }
+
+    /// Waits at most `timeout` for the next frame.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(frame) = self.take(&mut state) {
+                return Ok(frame);
+            }
+            if !state.sender_alive {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self
+                .shared
+                .available
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Returns the next frame if one is available. Otherwise `cx` is woken up as soon as a frame arrives.
+    /// `None` is returned if no frame will arrive anymore.
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.shared.lock();
+        if let Some(frame) = self.take(&mut state) {
+            return Poll::Ready(Some(frame));
+        }
+        if !state.sender_alive {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Returns the number of frames the policy has dropped so far.
+    pub fn dropped_frames(&self) -> u64 {
+        self.shared.lock().dropped
+    }
+
+    /// Returns the policy this receiver was created with.
+    pub fn policy(&self) -> DeliveryPolicy {
+        self.shared.policy
+    }
+
+    /// Stops accepting frames and wakes up a sender waiting for space.
+    pub(crate) fn close(&self) {
+        let mut state = self.shared.lock();
+        state.receiver_alive = false;
+        state.queue.clear();
+        self.shared.space.notify_all();
+    }
+}
+
+impl<T> Drop for FrameReceiver<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T> futures_core::Stream for FrameReceiver<T> {
+    type Item = T;
+
+    fn poll_next(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::task::Wake;
+    use std::thread;
+
+    fn queue(capacity: usize, overflow: OverflowStrategy) -> DeliveryPolicy {
+        DeliveryPolicy::Queue { capacity, overflow }
+    }
+
+    #[test]
+    fn keep_latest_overwrites_the_unreceived_frame() {
+        let (sender, receiver) = frame_channel(DeliveryPolicy::KeepLatest).unwrap();
+        assert_eq!(sender.send(1), Ok(None));
+        assert_eq!(sender.send(2), Ok(Some(1)));
+        assert_eq!(sender.send(3), Ok(Some(2)));
+        assert_eq!(receiver.try_recv(), Ok(3));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(receiver.dropped_frames(), 2);
+    }
+
+    #[test]
+    fn drop_oldest_makes_room_for_the_new_frame() {
+        let (sender, receiver) = frame_channel(queue(2, OverflowStrategy::DropOldest)).unwrap();
+        for frame in 1..=4 {
+            sender.send(frame).unwrap();
+        }
+        assert_eq!(receiver.try_recv(), Ok(3));
+        assert_eq!(receiver.try_recv(), Ok(4));
+        assert_eq!(receiver.dropped_frames(), 2);
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_queue() {
+        let (sender, receiver) = frame_channel(queue(2, OverflowStrategy::DropNewest)).unwrap();
+        assert_eq!(sender.send(1), Ok(None));
+        assert_eq!(sender.send(2), Ok(None));
+        assert_eq!(sender.send(3), Ok(Some(3)));
+        assert_eq!(receiver.try_recv(), Ok(1));
+        assert_eq!(receiver.try_recv(), Ok(2));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(receiver.dropped_frames(), 1);
+    }
+
+    #[test]
+    fn block_waits_for_space_without_dropping() {
+        let (sender, receiver) = frame_channel(DeliveryPolicy::Block { capacity: 1 }).unwrap();
+        let sending = thread::spawn(move || {
+            for frame in 0..100 {
+                assert_eq!(sender.send(frame), Ok(None));
+            }
+        });
+        for frame in 0..100 {
+            assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(frame));
+        }
+        sending.join().unwrap();
+        assert_eq!(receiver.recv(), Err(RecvError));
+        assert_eq!(receiver.dropped_frames(), 0);
+    }
+
+    #[test]
+    fn block_releases_the_sender_when_the_receiver_is_dropped() {
+        let (sender, receiver) = frame_channel(DeliveryPolicy::Block { capacity: 1 }).unwrap();
+        sender.send(1).unwrap();
+        let sending = thread::spawn(move || sender.send(2));
+        thread::sleep(Duration::from_millis(50));
+        drop(receiver);
+        assert_eq!(sending.join().unwrap(), Err(2));
+    }
+
+    #[test]
+    fn block_drops_the_frame_when_the_thread_is_interrupted() {
+        let (sender, receiver) = frame_channel(DeliveryPolicy::Block { capacity: 1 }).unwrap();
+        let flag = Arc::new(AtomicBool::new(false));
+        let interrupt = flag.clone();
+        let sending = thread::spawn(move || {
+            set_interrupt(interrupt);
+            sender.send(1).unwrap();
+            sender.send(2)
+        });
+        thread::sleep(Duration::from_millis(100));
+        assert!(!sending.is_finished());
+        flag.store(true, Ordering::SeqCst);
+        assert_eq!(sending.join().unwrap(), Ok(Some(2)));
+        assert_eq!(receiver.recv(), Ok(1));
+        assert_eq!(receiver.dropped_frames(), 1);
+    }
+
+    #[test]
+    fn queued_frames_outlive_the_sender() {
+        let (sender, receiver) = frame_channel(DeliveryPolicy::default()).unwrap();
+        sender.send(1).unwrap();
+        drop(sender);
+        assert_eq!(receiver.recv(), Ok(1));
+        assert_eq!(receiver.recv(), Err(RecvError));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn sending_fails_after_the_receiver_is_dropped() {
+        let (sender, receiver) = frame_channel(DeliveryPolicy::KeepLatest).unwrap();
+        drop(receiver);
+        assert_eq!(sender.send(1), Err(1));
+    }
+
+    #[test]
+    fn zero_capacity_is_rejected() {
+        for &policy in &[
+            queue(0, OverflowStrategy::DropOldest),
+            queue(0, OverflowStrategy::DropNewest),
+            DeliveryPolicy::Block { capacity: 0 },
+        ] {
+            assert!(frame_channel::<u32>(policy).is_err());
+        }
+    }
+
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn poll(receiver: &FrameReceiver<u32>, flag: &Arc<Flag>) -> Poll<Option<u32>> {
+        let waker = Waker::from(flag.clone());
+        receiver.poll_recv(&mut Context::from_waker(&waker))
+    }
+
+    #[test]
+    fn waker_is_woken_by_a_frame() {
+        let (sender, receiver) = frame_channel(DeliveryPolicy::KeepLatest).unwrap();
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        assert_eq!(poll(&receiver, &flag), Poll::Pending);
+        sender.send(1).unwrap();
+        assert!(flag.0.load(Ordering::SeqCst));
+        assert_eq!(poll(&receiver, &flag), Poll::Ready(Some(1)));
+    }
+
+    #[test]
+    fn waker_is_woken_when_the_sender_is_dropped() {
+        let (sender, receiver) = frame_channel(DeliveryPolicy::KeepLatest).unwrap();
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        assert_eq!(poll(&receiver, &flag), Poll::Pending);
+        drop(sender);
+        assert!(flag.0.load(Ordering::SeqCst));
+        assert_eq!(poll(&receiver, &flag), Poll::Ready(None));
+    }
+}
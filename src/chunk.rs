@@ -0,0 +1,311 @@
+//! Handlers which assemble frames from the USB packets sent by the device.
+
+use std::os;
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+use std::sync::Mutex;
+
+// The payload sizes of depth and video packets, which libfreenect's `stream_process` places at
+// multiples of them within the frame
+pub(crate) const DEPTH_PACKET_SIZE: usize = 1748;
+pub(crate) const VIDEO_PACKET_SIZE: usize = 1908;
+
+/// A USB packet which carries a part of a frame
+#[derive(Clone, Copy, Debug)]
+pub struct Chunk<'a> {
+    /// The position of the packet within the frame, starting at 0
+    pub packet: usize,
+    /// The payload size of the packets of this stream, 1748 bytes for depth and 1908 for video.
+    /// The packet starts at byte `packet * packet_size` of the frame. Only the last packet of a
+    /// frame may be shorter.
+    pub packet_size: usize,
+    /// The payload of the packet
+    pub data: &'a [u8],
+}
+
+/// Decides where the packets of a depth or video frame end up.
+///
+/// Register it with [`set_depth_chunk_handler()`][depth] or [`set_video_chunk_handler()`][video].
+/// The handler runs within the thread processing libfreenect's events for every packet, so it
+/// should return quickly.
+///
+/// [depth]: struct.FreenectDevice.html#method.set_depth_chunk_handler
+/// [video]: struct.FreenectDevice.html#method.set_video_chunk_handler
+/// # Examples
+/// Unpacking 11-bit depth into an own buffer while the packets arrive:
+/// ```rust
+/// use freenectrs::freenect::{Chunk, ChunkHandler};
+///
+/// struct Unpack11Bit {
+///     depth: Vec<u16>,
+/// }
+///
+/// impl ChunkHandler for Unpack11Bit {
+///     fn handle(&mut self, _frame: &mut [u8], chunk: &Chunk) {
+///         if chunk.packet == 0 {
+///             self.depth.iter_mut().for_each(|value| *value = 0);
+///         }
+///         // 8 pixels are packed into 11 bytes, big-endian, and pixels may span two packets
+///         let first_bit = chunk.packet * chunk.packet_size * 8;
+///         for (i, &byte) in chunk.data.iter().enumerate() {
+///             let bit = first_bit + i * 8;
+///             let (pixel, offset) = (bit / 11, bit % 11);
+///             let byte = byte as u16;
+///             if offset <= 3 {
+///                 if let Some(value) = self.depth.get_mut(pixel) {
+///                     *value |= byte << (3 - offset);
+///                 }
+///             } else {
+///                 if let Some(value) = self.depth.get_mut(pixel) {
+///                     *value |= byte >> (offset - 3);
+///                 }
+///                 if let Some(value) = self.depth.get_mut(pixel + 1) {
+///                     *value |= (byte << (14 - offset)) & 0x7ff;
+///                 }
+///             }
+///         }
+///     }
+/// }
+///
+/// // The values 0..16 sent in packets of 9 bytes, the last one shorter
+/// let packed = [
+///     0, 0, 4, 1, 0, 48, 8, 1, 64, 48, 7, 1, 0, 36, 5, 0, 176, 24, 3, 64, 112, 15,
+/// ];
+/// let mut handler = Unpack11Bit { depth: vec![0; 16] };
+/// for (packet, data) in packed.chunks(9).enumerate() {
+///     handler.handle(&mut [], &Chunk { packet, packet_size: 9, data });
+/// }
+/// assert_eq!(handler.depth, (0..16).collect::<Vec<u16>>());
+/// ```
+pub trait ChunkHandler: Send {
+    /// Handles one packet. `frame` is libfreenect's buffer for the packed frame, which is decoded
+    /// and handed to the depth or video stream once the frame is complete.
+    fn handle(&mut self, frame: &mut [u8], chunk: &Chunk);
+}
+
+/// Copies each packet into libfreenect's frame buffer, like libfreenect does without a chunk handler.
+/// Packets which don't fit into the buffer are cut off.
+/// # Examples
+/// ```rust
+/// use freenectrs::freenect::{Chunk, ChunkHandler, RawCopy};
+///
+/// // A frame of 11-bit depth, whose last packet is shorter
+/// let packed: Vec<u8> = (0..422_400).map(|i| (i % 251) as u8).collect();
+/// let mut frame = vec![0; packed.len()];
+/// for (packet, data) in packed.chunks(1748).enumerate() {
+///     RawCopy.handle(&mut frame, &Chunk { packet, packet_size: 1748, data });
+/// }
+/// assert_eq!(packed.len() % 1748, 1132);
+/// assert_eq!(frame, packed);
+///
+/// // Packets beyond the frame are cut off
+/// let mut frame = vec![0; 8];
+/// for (packet, data) in [1, 2, 3, 4, 5, 6, 7, 8, 9].chunks(3).enumerate() {
+///     RawCopy.handle(&mut frame, &Chunk { packet, packet_size: 3, data });
+/// }
+/// assert_eq!(frame, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RawCopy;
+
+impl ChunkHandler for RawCopy {
+    fn handle(&mut self, frame: &mut [u8], chunk: &Chunk) {
+        let start = (chunk.packet * chunk.packet_size).min(frame.len());
+        let end = (start + chunk.data.len()).min(frame.len());
+        frame[start..end].copy_from_slice(&chunk.data[..end - start]);
+    }
+}
+
+impl<F> ChunkHandler for F
+where
+    F: FnMut(&mut [u8], &Chunk) + Send,
+{
+    fn handle(&mut self, frame: &mut [u8], chunk: &Chunk) {
+        self(frame, chunk)
+    }
+}
+
+/// Hands a packet to the chunk handler stored in `handler`
+pub(crate) unsafe fn dispatch_chunk(
+    handler: &Mutex<Option<Box<dyn ChunkHandler>>>,
+    frame: *mut os::raw::c_void,
+    frame_len: usize,
+    packet_size: usize,
+    pkt_data: *mut os::raw::c_void,
+    pkt_num: os::raw::c_int,
+    datalen: os::raw::c_int,
+) {
+    let frame = slice::from_raw_parts_mut(frame as *mut u8, frame_len);
+    let chunk = Chunk {
+        packet: pkt_num.max(0) as usize,
+        packet_size,
+        data: slice::from_raw_parts(pkt_data as *const u8, datalen.max(0) as usize),
+    };
+    if let Some(handler) = handler.lock().unwrap().as_mut() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| handler.handle(frame, &chunk)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    // The packed sizes of a medium resolution frame of 11-bit depth and of Bayer video
+    const DEPTH_FRAME_BYTES: usize = 640 * 480 * 11 / 8;
+    const VIDEO_FRAME_BYTES: usize = 640 * 480;
+
+    /// Packs 11-bit values like the device does, 8 values into 11 bytes, big-endian
+    fn pack_11bit(values: &[u16]) -> Vec<u8> {
+        let mut packed = vec![0u8; values.len() * 11 / 8];
+        for (i, &value) in values.iter().enumerate() {
+            for bit in 0..11 {
+                if value & (1 << (10 - bit)) != 0 {
+                    let at = i * 11 + bit;
+                    packed[at / 8] |= 0x80 >> (at % 8);
+                }
+            }
+        }
+        packed
+    }
+
+    /// Splits a frame into the packets the device would send, as (packet number, payload)
+    fn record(frame: &[u8], packet_size: usize) -> Vec<(os::raw::c_int, Vec<u8>)> {
+        frame
+            .chunks(packet_size)
+            .enumerate()
+            .map(|(packet, data)| (packet as os::raw::c_int, data.to_vec()))
+            .collect()
+    }
+
+    /// Replays packets through the handler into a zeroed frame of `frame_len` bytes
+    fn replay(
+        handler: Box<dyn ChunkHandler>,
+        frame_len: usize,
+        packet_size: usize,
+        packets: &[(os::raw::c_int, Vec<u8>)],
+    ) -> Vec<u8> {
+        let handler = Mutex::new(Some(handler));
+        let mut frame = vec![0u8; frame_len];
+        for (packet, data) in packets {
+            let mut data = data.clone();
+            unsafe {
+                dispatch_chunk(
+                    &handler,
+                    frame.as_mut_ptr() as *mut os::raw::c_void,
+                    frame.len(),
+                    packet_size,
+                    data.as_mut_ptr() as *mut os::raw::c_void,
+                    *packet,
+                    data.len() as os::raw::c_int,
+                );
+            }
+        }
+        frame
+    }
+
+    /// A handler which copies the packets and records their number, packet size and length
+    fn recording(seen: Arc<Mutex<Vec<(usize, usize, usize)>>>) -> Box<dyn ChunkHandler> {
+        Box::new(move |frame: &mut [u8], chunk: &Chunk| {
+            seen.lock()
+                .unwrap()
+                .push((chunk.packet, chunk.packet_size, chunk.data.len()));
+            RawCopy.handle(frame, chunk);
+        })
+    }
+
+    #[test]
+    fn depth_packets_assemble_the_frame() {
+        let values: Vec<u16> = (0..640 * 480).map(|i| (i % 2048) as u16).collect();
+        let packed = pack_11bit(&values);
+        assert_eq!(packed.len(), DEPTH_FRAME_BYTES);
+        let packets = record(&packed, DEPTH_PACKET_SIZE);
+        assert_eq!(packets.len(), 242);
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let frame = replay(
+            recording(seen.clone()),
+            DEPTH_FRAME_BYTES,
+            DEPTH_PACKET_SIZE,
+            &packets,
+        );
+        assert_eq!(frame, packed);
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 242);
+        for (i, &(packet, packet_size, len)) in seen.iter().enumerate() {
+            assert_eq!((packet, packet_size), (i, 1748));
+            assert_eq!(len, if i == 241 { 1132 } else { 1748 });
+        }
+    }
+
+    #[test]
+    fn video_packets_which_are_lost_or_beyond_the_frame_are_handled() {
+        let bayer: Vec<u8> = (0..VIDEO_FRAME_BYTES)
+            .map(|i| (i % 251) as u8 | 1)
+            .collect();
+        let mut packets = record(&bayer, VIDEO_PACKET_SIZE);
+        assert_eq!(packets.len(), 162);
+        assert_eq!(packets[161].1.len(), 12);
+
+        // Packet 5 is lost, packet 7 is repeated, and a stray packet lies beyond the frame
+        packets.remove(5);
+        let repeated = packets[6].clone();
+        packets.insert(7, repeated);
+        packets.push((170, vec![0xff; VIDEO_PACKET_SIZE]));
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let frame = replay(
+            recording(seen.clone()),
+            VIDEO_FRAME_BYTES,
+            VIDEO_PACKET_SIZE,
+            &packets,
+        );
+        let lost = 5 * VIDEO_PACKET_SIZE..6 * VIDEO_PACKET_SIZE;
+        assert!(frame[lost.clone()].iter().all(|&byte| byte == 0));
+        assert_eq!(frame[..lost.start], bayer[..lost.start]);
+        assert_eq!(frame[lost.end..], bayer[lost.end..]);
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 163);
+        assert!(seen.iter().all(|&(_, packet_size, _)| packet_size == 1908));
+        assert_eq!(seen[6].0, 7);
+        assert_eq!(seen[7].0, 7);
+        assert_eq!(seen[162], (170, 1908, 1908));
+    }
+
+    #[test]
+    fn negative_packet_numbers_and_lengths_are_clamped() {
+        let packets = vec![(-1, vec![1, 2, 3])];
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let frame = replay(recording(seen.clone()), 8, 3, &packets);
+        assert_eq!(frame, vec![1, 2, 3, 0, 0, 0, 0, 0]);
+        assert_eq!(*seen.lock().unwrap(), vec![(0, 3, 3)]);
+
+        let handler = Mutex::new(Some(recording(seen.clone())));
+        let mut frame = vec![0u8; 8];
+        let mut data = vec![9u8; 3];
+        unsafe {
+            dispatch_chunk(
+                &handler,
+                frame.as_mut_ptr() as *mut os::raw::c_void,
+                frame.len(),
+                3,
+                data.as_mut_ptr() as *mut os::raw::c_void,
+                1,
+                -4,
+            );
+        }
+        assert_eq!(frame, vec![0; 8]);
+        assert_eq!(seen.lock().unwrap()[1], (1, 3, 0));
+    }
+
+    #[test]
+    fn a_panicking_handler_does_not_unwind_into_libfreenect() {
+        let handler: Box<dyn ChunkHandler> =
+            Box::new(|_: &mut [u8], _: &Chunk| panic!("handler failed"));
+        let packets = record(&[1, 2, 3, 4], 2);
+        let frame = replay(handler, 4, 2, &packets);
+        assert_eq!(frame, vec![0; 4]);
+    }
+}
@@ -0,0 +1,251 @@
+//! Reading and writing recordings in the dump format of libfreenect's `fakenect`.
+//!
+//! A dump is a directory with an `INDEX.txt` listing its files in recording order. The files are named
+//! `<type>-<time>-<timestamp>.<extension>`: depth frames are `d-….pgm` with 16-bit values in
+//! little-endian byte order, rgb frames are `r-….ppm` and accelerometer states are `a-….dump`.
+use crate::freenect::{
+    DepthFrame, DepthFrameRef, FreenectError, Result, VideoFrame, VideoFrameRef,
+};
+use crate::group::Frame;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What a file of a dump holds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DumpEntryKind {
+    Depth,
+    Video,
+    Accelerometer,
+}
+
+/// A file listed in the index of a dump
+#[derive(Clone, Debug, PartialEq)]
+pub struct DumpEntry {
+    pub kind: DumpEntryKind,
+    /// Seconds since the Unix epoch at which the file was recorded
+    pub time: f64,
+    /// The timestamp libfreenect assigned to the frame
+    pub timestamp: u32,
+    pub path: PathBuf,
+}
+
+impl DumpEntry {
+    /// Parses a file name like `d-1294097516.452163-2462700286.pgm`
+    fn parse(dir: &Path, name: &str) -> Option<DumpEntry> {
+        let kind = match name.chars().next()? {
+            'd' => DumpEntryKind::Depth,
+            'r' => DumpEntryKind::Video,
+            'a' => DumpEntryKind::Accelerometer,
+            _ => return None,
+        };
+        let stem = name.get(2..)?.rsplit_once('.')?.0;
+        let (time, timestamp) = stem.rsplit_once('-')?;
+        Some(DumpEntry {
+            kind,
+            time: time.parse().ok()?,
+            timestamp: timestamp.parse().ok()?,
+            path: dir.join(name),
+        })
+    }
+}
+
+/// A recording made with `fakenect-record` or a [`FakenectWriter`](struct.FakenectWriter.html).
+/// # Examples
+/// ```rust
+/// use freenectrs::freenect::{DepthFrameRef, FakenectDump, FakenectWriter, Frame, VideoFrameRef};
+///
+/// let dir = std::env::temp_dir().join(format!("freenectrs-dump-{}", std::process::id()));
+/// let mut writer = FakenectWriter::create(&dir).unwrap();
+/// writer.write_depth(&DepthFrameRef { data: &[1, 2, 3, 4], timestamp: 7, width: 2, height: 2 }).unwrap();
+/// writer.write_video(&VideoFrameRef { data: &[9; 12], timestamp: 8, width: 2, height: 2 }).unwrap();
+///
+/// let dump = FakenectDump::open(&dir).unwrap();
+/// assert_eq!(dump.entries().len(), 2);
+/// let frames: Vec<Frame> = dump.frames().map(|frame| frame.unwrap().1).collect();
+/// match &frames[0] {
+///     Frame::Depth(depth) => assert_eq!((depth.timestamp, &depth.data[..]), (7, &[1, 2, 3, 4][..])),
+///     _ => panic!("expected a depth frame"),
+/// }
+/// match &frames[1] {
+///     Frame::Video(video) => assert_eq!((video.width, video.data.len()), (2, 12)),
+///     _ => panic!("expected a video frame"),
+/// }
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct FakenectDump {
+    dir: PathBuf,
+    entries: Vec<DumpEntry>,
+}
+
+impl FakenectDump {
+    /// Reads the index of the dump in `dir`
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<FakenectDump> {
+        let dir = dir.as_ref().to_owned();
+        let index = File::open(dir.join("INDEX.txt")).map_err(|err| {
+            FreenectError::new(format!("Unable to open dump {}: {}", dir.display(), err))
+        })?;
+        let mut entries = Vec::new();
+        for line in BufReader::new(index).lines() {
+            let line = line
+                .map_err(|err| FreenectError::new(format!("Unable to read dump index: {}", err)))?;
+            let name = line.trim();
+            if name.is_empty() {
+                continue;
+            }
+            let entry = DumpEntry::parse(&dir, name).ok_or_else(|| {
+                FreenectError::new(format!("Invalid file name in dump index: {}", name))
+            })?;
+            entries.push(entry);
+        }
+        Ok(FakenectDump { dir, entries })
+    }
+
+    /// Returns the directory of the dump
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the files of the dump in recording order
+    pub fn entries(&self) -> &[DumpEntry] {
+        &self.entries
+    }
+
+    /// Reads a depth or rgb frame. Fails for accelerometer entries.
+    pub fn read(&self, entry: &DumpEntry) -> Result<Frame> {
+        let bytes = fs::read(&entry.path).map_err(|err| {
+            FreenectError::new(format!("Unable to read {}: {}", entry.path.display(), err))
+        })?;
+        let (magic, width, height, data) = parse_netpbm(&bytes)
+            .ok_or_else(|| FreenectError::new(format!("Invalid image {}", entry.path.display())))?;
+        let pixels = width as usize * height as usize;
+        match (entry.kind, magic) {
+            (DumpEntryKind::Depth, "P5") if data.len() >= 2 * pixels => {
+                Ok(Frame::Depth(DepthFrame {
+                    data: data[..2 * pixels]
+                        .chunks_exact(2)
+                        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                        .collect(),
+                    timestamp: entry.timestamp,
+                    width,
+                    height,
+                }))
+            }
+            (DumpEntryKind::Video, "P6") if data.len() >= 3 * pixels => {
+                Ok(Frame::Video(VideoFrame {
+                    data: data[..3 * pixels].to_vec(),
+                    timestamp: entry.timestamp,
+                    width,
+                    height,
+                }))
+            }
+            _ => Err(FreenectError::new(format!(
+                "{} is no depth or rgb frame",
+                entry.path.display()
+            ))),
+        }
+    }
+
+    /// Reads all depth and rgb frames in recording order, together with the time they were recorded at
+    pub fn frames(&self) -> impl Iterator<Item = Result<(f64, Frame)>> + '_ {
+        self.entries
+            .iter()
+            .filter(|entry| entry.kind != DumpEntryKind::Accelerometer)
+            .map(move |entry| self.read(entry).map(|frame| (entry.time, frame)))
+    }
+}
+
+/// Splits a binary PGM or PPM file into magic, width, height and pixel data
+fn parse_netpbm(bytes: &[u8]) -> Option<(&'static str, u32, u32, &[u8])> {
+    let mut fields = Vec::with_capacity(4);
+    let mut pos = 0;
+    while fields.len() < 4 {
+        while bytes.get(pos)?.is_ascii_whitespace() {
+            pos += 1;
+        }
+        let start = pos;
+        while !bytes.get(pos)?.is_ascii_whitespace() {
+            pos += 1;
+        }
+        fields.push(std::str::from_utf8(&bytes[start..pos]).ok()?);
+    }
+    // A single whitespace separates the header from the data
+    let data = bytes.get(pos + 1..)?;
+    let magic = match fields[0] {
+        "P5" => "P5",
+        "P6" => "P6",
+        _ => return None,
+    };
+    Some((
+        magic,
+        fields[1].parse().ok()?,
+        fields[2].parse().ok()?,
+        data,
+    ))
+}
+
+/// Records frames as a dump which [`FakenectDump`](struct.FakenectDump.html) and libfreenect's
+/// `fakenect` can play back.
+pub struct FakenectWriter {
+    dir: PathBuf,
+    index: File,
+}
+
+impl FakenectWriter {
+    /// Creates the directory `dir` if needed and appends to its index
+    pub fn create<P: AsRef<Path>>(dir: P) -> Result<FakenectWriter> {
+        let dir = dir.as_ref().to_owned();
+        let io_error = |err: std::io::Error| {
+            FreenectError::new(format!("Unable to create dump {}: {}", dir.display(), err))
+        };
+        fs::create_dir_all(&dir).map_err(io_error)?;
+        let index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join("INDEX.txt"))
+            .map_err(io_error)?;
+        Ok(FakenectWriter { dir, index })
+    }
+
+    fn write(
+        &mut self,
+        kind: char,
+        timestamp: u32,
+        extension: &str,
+        contents: &[u8],
+    ) -> Result<()> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|t| t.as_secs_f64())
+            .unwrap_or(0.0);
+        let name = format!("{}-{:.6}-{}.{}", kind, time, timestamp, extension);
+        let io_error =
+            |err: std::io::Error| FreenectError::new(format!("Unable to write {}: {}", name, err));
+        fs::write(self.dir.join(&name), contents).map_err(io_error)?;
+        writeln!(self.index, "{}", name).map_err(io_error)
+    }
+
+    /// Appends a depth frame
+    pub fn write_depth(&mut self, frame: &DepthFrameRef) -> Result<()> {
+        let mut contents = format!("P5 {} {} 65535\n", frame.width, frame.height).into_bytes();
+        for value in frame.data {
+            contents.extend_from_slice(&value.to_le_bytes());
+        }
+        self.write('d', frame.timestamp, "pgm", &contents)
+    }
+
+    /// Appends an rgb frame. Fails for other video formats.
+    pub fn write_video(&mut self, frame: &VideoFrameRef) -> Result<()> {
+        let pixels = frame.width as usize * frame.height as usize;
+        if frame.data.len() != 3 * pixels {
+            return Err(FreenectError::new(
+                "Only rgb frames can be written to a dump",
+            ));
+        }
+        let mut contents = format!("P6 {} {} 255\n", frame.width, frame.height).into_bytes();
+        contents.extend_from_slice(frame.data);
+        self.write('r', frame.timestamp, "ppm", &contents)
+    }
+}
@@ -0,0 +1,983 @@
+//! Spatial filters which smooth depth frames and fill pixels without a reading.
+//!
+//! Each filter returns a new frame with the timestamp and size of the original. Filters take the
+//! frame's format to tell readings apart from pixels without one. Pixels a filter can't fill keep
+//! their original value, so frames keep their format's marker for missing readings.
+//!
+//! The median and bilateral filters work on 16 pixels of a row at once, which the compiler turns
+//! into vector instructions. The median sorts windows up to a radius of 2 with a sorting network,
+//! and slides a histogram along each row for larger ones, whose cost grows with the radius. The
+//! bilateral filters weigh every pixel of their window, so their cost grows with the square of the
+//! radius. On 640×480 frames on a single core, the median and the bilateral filter keep up with
+//! 30 frames per second up to a radius of 3, and the joint bilateral filter up to a radius of 2.
+//! `cargo bench --bench filters` checks this and fails if a filter falls behind.
+use crate::freenect::{
+    DepthFrame, DepthFrameRef, FreenectDepthFormat, FreenectError, Result, VideoFrameRef,
+};
+use std::convert::TryInto;
+
+/// Returns width and height of a frame whose data matches its size
+fn size(frame: &DepthFrameRef) -> Result<(usize, usize)> {
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    if frame.data.len() == width * height {
+        Ok((width, height))
+    } else {
+        Err(FreenectError::new("Depth frame has the wrong size"))
+    }
+}
+
+fn filtered(frame: &DepthFrameRef, data: Vec<u16>) -> DepthFrame {
+    DepthFrame {
+        data,
+        timestamp: frame.timestamp,
+        width: frame.width,
+        height: frame.height,
+    }
+}
+
+/// Returns the first and last index of a window of `radius` around `i` within `0..len`
+fn window(i: usize, radius: usize, len: usize) -> (usize, usize) {
+    (i.saturating_sub(radius), (i + radius).min(len - 1))
+}
+
+/// Returns the factor which turns a squared difference `d²` into the `d² / 2σ²` of
+/// [`bell()`](fn.bell.html)
+fn bell_scale(sigma: f32) -> f32 {
+    let sigma = sigma.max(f32::EPSILON);
+    1.0 / (2.0 * sigma * sigma)
+}
+
+/// Returns `(1 - x / 32)^32` for `x = d² / 2σ²`, which is within 0.01 of the Gaussian
+/// `exp(-d² / 2σ²)`, and 0 for `d` beyond 3σ. Unlike `exp()` or a lookup in a table of weights,
+/// it compiles to vector instructions across the pixels of a row.
+#[inline]
+fn bell(x: f32) -> f32 {
+    // Comparisons rather than `f32::max()`, which keeps the vector instructions
+    let x = if x <= 4.5 { x } else { 32.0 };
+    let mut weight = 1.0 - x / 32.0;
+    for _ in 0..5 {
+        weight *= weight;
+    }
+    weight
+}
+
+/// Returns the weights of a square window of `radius` by the distance to its center
+fn spatial_weights(radius: usize, sigma: f32) -> Vec<f32> {
+    let sigma = sigma.max(f32::EPSILON);
+    let side = 2 * radius + 1;
+    (0..side * side)
+        .map(|i| {
+            let (dx, dy) = (
+                (i % side) as f32 - radius as f32,
+                (i / side) as f32 - radius as f32,
+            );
+            (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect()
+}
+
+// The pixels the median and bilateral filters work on together, which the compiler turns into
+// vector instructions
+const LANES: usize = 16;
+
+// Stands in for pixels without a reading in the bilateral filter. It is so far from every reading
+// that these pixels weigh 0.
+const HOLE: f32 = 1e9;
+
+/// A frame with a border of `radius` pixels, and rows long enough for whole `LANES`, so windows
+/// never need clipping
+struct Padded<T> {
+    data: Vec<T>,
+    width: usize,
+}
+
+impl<T: Copy> Padded<T> {
+    /// Returns a frame of `width` × `height` pixels with its border, all `value`
+    fn filled(value: T, (width, height): (usize, usize), radius: usize) -> Padded<T> {
+        let padded_width = width.div_ceil(LANES) * LANES + 2 * radius;
+        Padded {
+            data: vec![value; padded_width * (height + 2 * radius)],
+            width: padded_width,
+        }
+    }
+
+    /// Maps the pixels of a frame, with the border and the end of each row `border`
+    fn new<U>(
+        data: impl Iterator<Item = U>,
+        (width, height): (usize, usize),
+        radius: usize,
+        border: T,
+        map: impl Fn(U) -> T,
+    ) -> Padded<T> {
+        let mut padded = Padded::filled(border, (width, height), radius);
+        let mut data = data;
+        for row in padded
+            .data
+            .chunks_exact_mut(padded.width)
+            .skip(radius)
+            .take(height)
+        {
+            for (padded, value) in row[radius..radius + width].iter_mut().zip(&mut data) {
+                *padded = map(value);
+            }
+        }
+        padded
+    }
+
+    /// Returns `LANES` pixels of a row, starting at column `x`
+    fn lanes(&self, x: usize, y: usize) -> &[T; LANES] {
+        let start = y * self.width + x;
+        self.data[start..start + LANES].try_into().unwrap()
+    }
+
+    /// Returns `LANES` pixels of a row to change, starting at column `x`
+    fn lanes_mut(&mut self, x: usize, y: usize) -> &mut [T; LANES] {
+        let start = y * self.width + x;
+        (&mut self.data[start..start + LANES]).try_into().unwrap()
+    }
+
+    /// Returns the pixels of row `y` of the frame, without the border
+    fn row(&self, y: usize, radius: usize) -> &[T] {
+        &self.data[(y + radius) * self.width + radius..(y + radius + 1) * self.width - radius]
+    }
+}
+
+/// The maximum of the readings within `radius` along rows, then along columns. Pixels without a
+/// reading around them are 0.
+fn max_filter(
+    format: FreenectDepthFormat,
+    data: &[u16],
+    width: usize,
+    height: usize,
+    radius: usize,
+) -> Vec<u16> {
+    let mut rows = vec![0; data.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let (first, last) = window(x, radius, width);
+            rows[y * width + x] = data[y * width + first..=y * width + last]
+                .iter()
+                .copied()
+                .filter(|&value| format.is_reading(value))
+                .max()
+                .unwrap_or(0);
+        }
+    }
+    let mut max = vec![0; data.len()];
+    for y in 0..height {
+        let (first, last) = window(y, radius, height);
+        for x in 0..width {
+            max[y * width + x] = (first..=last)
+                .map(|y| rows[y * width + x])
+                .max()
+                .unwrap_or(0);
+        }
+    }
+    max
+}
+
+/// Whether all pixels within `radius` are set, along rows, then along columns
+fn erode(mask: &[bool], width: usize, height: usize, radius: usize) -> Vec<bool> {
+    let mut rows = vec![false; mask.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let (first, last) = window(x, radius, width);
+            rows[y * width + x] = mask[y * width + first..=y * width + last]
+                .iter()
+                .all(|&set| set);
+        }
+    }
+    let mut eroded = vec![false; mask.len()];
+    for y in 0..height {
+        let (first, last) = window(y, radius, height);
+        for x in 0..width {
+            eroded[y * width + x] = (first..=last).all(|y| rows[y * width + x]);
+        }
+    }
+    eroded
+}
+
+/// Returns the comparators of Batcher's odd-even merge sort of `n` values, each of which puts the
+/// smaller of two values first
+fn sorting_network(n: usize) -> Vec<(usize, usize)> {
+    let mut comparators = Vec::new();
+    let mut p = 1;
+    while p < n {
+        let mut k = p;
+        while k >= 1 {
+            for j in (k % p..n.saturating_sub(k)).step_by(2 * k) {
+                for i in 0..k.min(n - j - k) {
+                    if (i + j) / (2 * p) == (i + j + k) / (2 * p) {
+                        comparators.push((i + j, i + j + k));
+                    }
+                }
+            }
+            k /= 2;
+        }
+        p *= 2;
+    }
+    comparators
+}
+
+/// Whether the median replaces the pixel at `i`, given the number of readings and of pixels in its
+/// window
+fn fills(format: FreenectDepthFormat, data: &[u16], i: usize, count: usize, area: usize) -> bool {
+    count > 0 && (format.is_reading(data[i]) || 2 * count > area)
+}
+
+// The values a `Histogram` sums at once to skip stretches without readings
+const BLOCK: usize = 16;
+
+// The bin of a `Histogram` which counts pixels without a reading. It is above all readings, so the
+// median never reaches it, and counting these pixels there spares checking for them.
+const NO_READING: u32 = 1 << 16;
+
+/// Counts the readings of a window sliding along a row and tracks their upper median. Moving the
+/// window only touches the columns entering and leaving it, and the median walks from where it
+/// was, skipping empty stretches block by block. Blocks are summed when needed rather than
+/// counted along, which halves the work of moving the window.
+struct Histogram {
+    counts: Vec<u32>,
+    total: usize,
+    median: usize,
+    /// The number of readings below `median`
+    below: usize,
+}
+
+impl Histogram {
+    fn new() -> Histogram {
+        Histogram {
+            counts: vec![0; NO_READING as usize + BLOCK],
+            total: 0,
+            median: 0,
+            below: 0,
+        }
+    }
+
+    /// Adds or removes the pixels of column `x` within `rows`, given the bins of all pixels
+    fn update(&mut self, bins: &[u32], width: usize, rows: (usize, usize), x: usize, add: bool) {
+        for row in rows.0..=rows.1 {
+            let bin = bins[row * width + x];
+            let reading = (bin != NO_READING) as usize;
+            let bin = bin as usize;
+            let below = (bin < self.median) as usize;
+            if add {
+                self.counts[bin] += 1;
+                self.total += reading;
+                self.below += below;
+            } else {
+                self.counts[bin] -= 1;
+                self.total -= reading;
+                self.below -= below;
+            }
+        }
+    }
+
+    /// Returns the number of readings in `block`
+    fn block(&self, block: usize) -> usize {
+        self.counts[block * BLOCK..(block + 1) * BLOCK]
+            .iter()
+            .sum::<u32>() as usize
+    }
+
+    /// Returns the upper median of the readings, there must be at least one
+    fn median(&mut self) -> u16 {
+        let middle = self.total / 2;
+        while self.below > middle {
+            let block = self.median / BLOCK;
+            if self.median % BLOCK == 0 && self.below - self.block(block - 1) > middle {
+                self.below -= self.block(block - 1);
+                self.median -= BLOCK;
+            } else {
+                self.median -= 1;
+                self.below -= self.counts[self.median] as usize;
+            }
+        }
+        while self.below + self.counts[self.median] as usize <= middle {
+            let block = self.median / BLOCK;
+            if self.median % BLOCK == 0 && self.below + self.block(block) <= middle {
+                self.below += self.block(block);
+                self.median += BLOCK;
+            } else {
+                self.below += self.counts[self.median] as usize;
+                self.median += 1;
+            }
+        }
+        self.median as u16
+    }
+}
+
+impl<'a> DepthFrameRef<'a> {
+    /// Replaces each pixel by the median of the readings within `radius` pixels, which removes
+    /// speckles while keeping edges. Pixels without a reading are filled if most of the window has
+    /// readings.
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::{DepthFrameRef, FreenectDepthFormat};
+    ///
+    /// let data = [
+    ///     1000, 1000, 1000,
+    ///     1000, 9000, 1000,
+    ///     1000,    0, 1000,
+    /// ];
+    /// let frame = DepthFrameRef { data: &data, timestamp: 7, width: 3, height: 3 };
+    /// let filtered = frame.median(FreenectDepthFormat::MM, 1).unwrap();
+    /// // The speckle is gone and the hole is filled
+    /// assert_eq!(filtered.data, vec![1000; 9]);
+    /// assert_eq!(filtered.timestamp, 7);
+    /// ```
+    pub fn median(&self, format: FreenectDepthFormat, radius: u32) -> Result<DepthFrame> {
+        let (width, height) = size(self)?;
+        let data = match radius as usize {
+            radius @ 0..=2 => self.network_median(format, (width, height), radius),
+            radius => self.histogram_median(format, (width, height), radius),
+        };
+        Ok(filtered(self, data))
+    }
+
+    /// Sorts the windows of `LANES` pixels at once with a sorting network, which takes few enough
+    /// steps for small windows. Pixels without a reading sort last.
+    fn network_median(
+        &self,
+        format: FreenectDepthFormat,
+        (width, height): (usize, usize),
+        radius: usize,
+    ) -> Vec<u16> {
+        let side = 2 * radius + 1;
+        let network = sorting_network(side * side);
+        let reading = |&value: &u16| format.is_reading(value);
+        // Values flip to `i16` in the same order, whose minimum and maximum take one instruction
+        let values = Padded::new(
+            self.data.iter(),
+            (width, height),
+            radius,
+            i16::MAX,
+            |value| {
+                if reading(value) {
+                    (value ^ 0x8000) as i16
+                } else {
+                    i16::MAX
+                }
+            },
+        );
+        let readings = Padded::new(self.data.iter(), (width, height), radius, 0, |value| {
+            reading(value) as u16
+        });
+        let mut windows = vec![[0; LANES]; side * side];
+        let mut data = self.data.to_vec();
+        for y in 0..height {
+            let (top, bottom) = window(y, radius, height);
+            for x in (0..width).step_by(LANES) {
+                let mut counts = [0; LANES];
+                for (i, window) in windows.iter_mut().enumerate() {
+                    let (nx, ny) = (x + i % side, y + i / side);
+                    *window = *values.lanes(nx, ny);
+                    let readings = readings.lanes(nx, ny);
+                    for lane in 0..LANES {
+                        counts[lane] += readings[lane];
+                    }
+                }
+                for &(first, second) in &network {
+                    let (low, high) = windows.split_at_mut(second);
+                    let (low, high) = (&mut low[first], &mut high[0]);
+                    for lane in 0..LANES {
+                        let (a, b) = (low[lane], high[lane]);
+                        low[lane] = a.min(b);
+                        high[lane] = a.max(b);
+                    }
+                }
+                for lane in 0..LANES.min(width - x) {
+                    let (left, right) = window(x + lane, radius, width);
+                    let count = counts[lane] as usize;
+                    let area = (bottom - top + 1) * (right - left + 1);
+                    let i = y * width + x + lane;
+                    if fills(format, self.data, i, count, area) {
+                        data[i] = windows[count / 2][lane] as u16 ^ 0x8000;
+                    }
+                }
+            }
+        }
+        data
+    }
+
+    /// Slides a histogram along each row, whose cost grows with the radius rather than its square
+    fn histogram_median(
+        &self,
+        format: FreenectDepthFormat,
+        (width, height): (usize, usize),
+        radius: usize,
+    ) -> Vec<u16> {
+        let bins: Vec<u32> = self
+            .data
+            .iter()
+            .map(|&value| {
+                if format.is_reading(value) {
+                    value as u32
+                } else {
+                    NO_READING
+                }
+            })
+            .collect();
+        let mut histogram = Histogram::new();
+        let mut data = self.data.to_vec();
+        for y in 0..height {
+            let rows = window(y, radius, height);
+            for x in 0..radius.min(width) {
+                histogram.update(&bins, width, rows, x, true);
+            }
+            for x in 0..width {
+                if x + radius < width {
+                    histogram.update(&bins, width, rows, x + radius, true);
+                }
+                if x > radius {
+                    histogram.update(&bins, width, rows, x - radius - 1, false);
+                }
+                let (left, right) = window(x, radius, width);
+                let area = (rows.1 - rows.0 + 1) * (right - left + 1);
+                let i = y * width + x;
+                if fills(format, self.data, i, histogram.total, area) {
+                    data[i] = histogram.median();
+                }
+            }
+            // Empties the histogram for the next row
+            for x in width.saturating_sub(radius + 1)..width {
+                histogram.update(&bins, width, rows, x, false);
+            }
+        }
+        data
+    }
+
+    /// Smooths surfaces without blurring edges: each reading becomes the mean of the readings
+    /// within `radius`, weighted by their distance in pixels with `sigma_space` and by their
+    /// difference in depth with `sigma_depth`, in the units of the frame. Neighbours more than
+    /// 3 × `sigma_depth` away don't count, so objects in front stay apart from the background.
+    /// Pixels without a reading stay empty.
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::{DepthFrameRef, FreenectDepthFormat};
+    ///
+    /// let data = [1000, 1010, 1000, 3000, 3010, 3000];
+    /// let frame = DepthFrameRef { data: &data, timestamp: 0, width: 6, height: 1 };
+    /// let filtered = frame.bilateral(FreenectDepthFormat::MM, 2, 2.0, 20.0).unwrap();
+    /// // Noise is smoothed but the step stays sharp
+    /// assert!(filtered.data[..3].iter().all(|&v| v > 1000 && v < 1010));
+    /// assert!(filtered.data[3..].iter().all(|&v| v > 3000 && v < 3010));
+    /// ```
+    pub fn bilateral(
+        &self,
+        format: FreenectDepthFormat,
+        radius: u32,
+        sigma_space: f32,
+        sigma_depth: f32,
+    ) -> Result<DepthFrame> {
+        let (width, height) = size(self)?;
+        let radius = radius as usize;
+        let side = 2 * radius + 1;
+        let spatial = spatial_weights(radius, sigma_space);
+        let scale = bell_scale(sigma_depth);
+        let values = Padded::new(self.data.iter(), (width, height), radius, HOLE, |&value| {
+            if format.is_reading(value) {
+                value as f32
+            } else {
+                HOLE
+            }
+        });
+        let mut data = self.data.to_vec();
+        for y in 0..height {
+            for x in (0..width).step_by(LANES) {
+                let centers = values.lanes(x + radius, y + radius);
+                let (mut sums, mut weights) = ([0.0; LANES], [0.0; LANES]);
+                for (i, &near) in spatial.iter().enumerate() {
+                    let neighbours = values.lanes(x + i % side, y + i / side);
+                    for lane in 0..LANES {
+                        // Readings too different and pixels without one weigh 0
+                        let difference = neighbours[lane] - centers[lane];
+                        let weight = near * bell(difference * difference * scale);
+                        sums[lane] += weight * neighbours[lane];
+                        weights[lane] += weight;
+                    }
+                }
+                for lane in 0..LANES.min(width - x) {
+                    if centers[lane] != HOLE {
+                        data[y * width + x + lane] = (sums[lane] / weights[lane]).round() as u16;
+                    }
+                }
+            }
+        }
+        Ok(filtered(self, data))
+    }
+
+    /// Smooths depth along the edges of the rgb or 8-bit infrared frame `guide`: each pixel becomes
+    /// the mean of the readings within `radius`, weighted by their distance in pixels with
+    /// `sigma_space` and by the difference of their colours with `sigma_color`, from 0 to 255.
+    /// Pixels without a reading are filled from neighbours of the same colour.
+    ///
+    /// Depth has to be registered to the guide, for example with
+    /// [`FreenectDepthFormat::Registered`](enum.FreenectDepthFormat.html), and both frames must
+    /// have the same size.
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::{DepthFrameRef, FreenectDepthFormat, VideoFrameRef};
+    ///
+    /// // A dark object in front of a bright wall, with a hole in each
+    /// let depth = [800, 0, 800, 2000, 0, 2000];
+    /// let rgb = [
+    ///     10, 10, 10, 10, 10, 10, 10, 10, 10,
+    ///     200, 200, 200, 200, 200, 200, 200, 200, 200,
+    /// ];
+    /// let frame = DepthFrameRef { data: &depth, timestamp: 0, width: 6, height: 1 };
+    /// let guide = VideoFrameRef { data: &rgb, timestamp: 0, width: 6, height: 1 };
+    /// let filtered = frame.joint_bilateral(FreenectDepthFormat::Registered, &guide, 2, 2.0, 10.0).unwrap();
+    /// assert_eq!(filtered.data, vec![800, 800, 800, 2000, 2000, 2000]);
+    /// ```
+    pub fn joint_bilateral(
+        &self,
+        format: FreenectDepthFormat,
+        guide: &VideoFrameRef,
+        radius: u32,
+        sigma_space: f32,
+        sigma_color: f32,
+    ) -> Result<DepthFrame> {
+        let (width, height) = size(self)?;
+        let pixels = width * height;
+        let channels = match guide.data.len() {
+            _ if (guide.width, guide.height) != (self.width, self.height) => None,
+            len if len == pixels => Some(1),
+            len if len == 3 * pixels => Some(3),
+            _ => None,
+        };
+        let channels = channels.ok_or_else(|| {
+            FreenectError::new(
+                "The guide has to be an rgb or 8-bit infrared frame of the size of the depth frame",
+            )
+        })?;
+        let radius = radius as usize;
+        let side = 2 * radius + 1;
+        let spatial = spatial_weights(radius, sigma_space);
+        // Pixels without a reading are 0, so they add nothing to the sums
+        let values = Padded::new(self.data.iter(), (width, height), radius, 0.0, |&value| {
+            if format.is_reading(value) {
+                value as f32
+            } else {
+                0.0
+            }
+        });
+        let readings = Padded::new(self.data.iter(), (width, height), radius, 0.0, |&value| {
+            format.is_reading(value) as u8 as f32
+        });
+        // Three planes for either guide, which keeps channels out of the loop. An infrared guide
+        // fills all three, so for both the mean difference is a third of the sum.
+        let scale = bell_scale(sigma_color) / 9.0;
+        let plane = |channel: usize| {
+            let channel = guide.data[channel % channels..].iter().step_by(channels);
+            Padded::new(channel, (width, height), radius, 0.0, |&value| value as f32)
+        };
+        let (red, green, blue) = (plane(0), plane(1), plane(2));
+        // Two pixels weigh the same for each other, so each pair is weighed once, from the later
+        // pixel of the two, and counts for both
+        let mut sums = Padded::filled(0.0, (width, height), radius);
+        let mut weights = Padded::filled(0.0, (width, height), radius);
+        let half = spatial.len() / 2;
+        for y in 0..height {
+            for x in (0..width).step_by(LANES) {
+                let (cx, cy) = (x + radius, y + radius);
+                let (r, g, b) = (red.lanes(cx, cy), green.lanes(cx, cy), blue.lanes(cx, cy));
+                // Copies, which the compiler knows the sums don't overwrite
+                let (value, reading) = (*values.lanes(cx, cy), *readings.lanes(cx, cy));
+                let (mut sum, mut weight) = ([0.0; LANES], [0.0; LANES]);
+                for lane in 0..LANES {
+                    sum[lane] = spatial[half] * value[lane];
+                    weight[lane] = spatial[half] * reading[lane];
+                }
+                for (i, &near) in spatial[..half].iter().enumerate() {
+                    let (nx, ny) = (x + i % side, y + i / side);
+                    let (nr, ng, nb) = (red.lanes(nx, ny), green.lanes(nx, ny), blue.lanes(nx, ny));
+                    let (neighbour, neighbour_reading) =
+                        (values.lanes(nx, ny), readings.lanes(nx, ny));
+                    let mut pair = [0.0; LANES];
+                    for lane in 0..LANES {
+                        let difference = (nr[lane] - r[lane]).abs()
+                            + (ng[lane] - g[lane]).abs()
+                            + (nb[lane] - b[lane]).abs();
+                        pair[lane] = near * bell(difference * difference * scale);
+                        sum[lane] += pair[lane] * neighbour[lane];
+                        weight[lane] += pair[lane] * neighbour_reading[lane];
+                    }
+                    let neighbour_sum = sums.lanes_mut(nx, ny);
+                    for lane in 0..LANES {
+                        neighbour_sum[lane] += pair[lane] * value[lane];
+                    }
+                    let neighbour_weight = weights.lanes_mut(nx, ny);
+                    for lane in 0..LANES {
+                        neighbour_weight[lane] += pair[lane] * reading[lane];
+                    }
+                }
+                let total = sums.lanes_mut(cx, cy);
+                for lane in 0..LANES {
+                    total[lane] += sum[lane];
+                }
+                let total = weights.lanes_mut(cx, cy);
+                for lane in 0..LANES {
+                    total[lane] += weight[lane];
+                }
+            }
+        }
+        let mut data = self.data.to_vec();
+        for (y, row) in data.chunks_exact_mut(width).enumerate() {
+            let (sums, weights) = (sums.row(y, radius), weights.row(y, radius));
+            for ((value, &sum), &weight) in row.iter_mut().zip(sums).zip(weights) {
+                if weight > 0.0 {
+                    *value = (sum / weight).round() as u16;
+                }
+            }
+        }
+        Ok(filtered(self, data))
+    }
+
+    /// Fills holes up to 2 × `radius` + 1 pixels wide by morphological closing, leaving larger
+    /// areas without readings alone. Holes take the farthest reading around them, so objects in
+    /// front don't grow into the background.
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::{DepthFrameRef, FreenectDepthFormat};
+    ///
+    /// let data = [1000, 0, 1200, 0, 0, 0, 0, 1000];
+    /// let frame = DepthFrameRef { data: &data, timestamp: 0, width: 8, height: 1 };
+    /// let filled = frame.fill_holes(FreenectDepthFormat::MM, 1).unwrap();
+    /// // The narrow hole is filled, the wide one is not
+    /// assert_eq!(filled.data, vec![1000, 1200, 1200, 0, 0, 0, 0, 1000]);
+    /// ```
+    pub fn fill_holes(&self, format: FreenectDepthFormat, radius: u32) -> Result<DepthFrame> {
+        let (width, height) = size(self)?;
+        let radius = radius as usize;
+        let max = max_filter(format, self.data, width, height, radius);
+        let dilated: Vec<bool> = max.iter().map(|&value| value != 0).collect();
+        let closed = erode(&dilated, width, height, radius);
+        let data = self
+            .data
+            .iter()
+            .zip(&max)
+            .zip(&closed)
+            .map(|((&value, &max), &closed)| {
+                if !format.is_reading(value) && closed {
+                    max
+                } else {
+                    value
+                }
+            })
+            .collect();
+        Ok(filtered(self, data))
+    }
+
+    /// Fills every pixel without a reading with the nearest reading, so frames have no holes
+    /// left, for example for mesh reconstruction. Frames without any reading stay unchanged.
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::{DepthFrameRef, FreenectDepthFormat};
+    ///
+    /// let data = [
+    ///     1000,    0,    0,
+    ///        0,    0,    0,
+    ///        0,    0, 2000,
+    /// ];
+    /// let frame = DepthFrameRef { data: &data, timestamp: 0, width: 3, height: 3 };
+    /// let filled = frame.inpaint_nearest(FreenectDepthFormat::MM).unwrap();
+    /// assert_eq!(filled.data, vec![
+    ///     1000, 1000, 1000,
+    ///     1000, 1000, 2000,
+    ///     1000, 2000, 2000,
+    /// ]);
+    /// ```
+    pub fn inpaint_nearest(&self, format: FreenectDepthFormat) -> Result<DepthFrame> {
+        let (width, height) = size(self)?;
+        // The position of the nearest reading found so far and its squared distance, propagated
+        // by two sweeps over the frame
+        let mut nearest = vec![(0, 0); width * height];
+        let mut distances: Vec<usize> = self
+            .data
+            .iter()
+            .map(|&value| {
+                if format.is_reading(value) {
+                    0
+                } else {
+                    usize::MAX
+                }
+            })
+            .collect();
+        for (i, position) in nearest.iter_mut().enumerate() {
+            *position = (i % width, i / width);
+        }
+        let mut visit = |x: usize, y: usize, neighbours: &[(isize, isize)]| {
+            let i = y * width + x;
+            if distances[i] == 0 {
+                return;
+            }
+            for &(dx, dy) in neighbours {
+                let (nx, ny) = (x as isize + dx, y as isize + dy);
+                if nx < 0 || ny < 0 || nx >= width as isize || ny >= height as isize {
+                    continue;
+                }
+                let neighbour = ny as usize * width + nx as usize;
+                if distances[neighbour] == usize::MAX {
+                    continue;
+                }
+                let (sx, sy) = nearest[neighbour];
+                let distance = sx.abs_diff(x).pow(2) + sy.abs_diff(y).pow(2);
+                if distance < distances[i] {
+                    distances[i] = distance;
+                    nearest[i] = (sx, sy);
+                }
+            }
+        };
+        for y in 0..height {
+            for x in 0..width {
+                visit(x, y, &[(-1, 0), (-1, -1), (0, -1), (1, -1)]);
+            }
+        }
+        for y in (0..height).rev() {
+            for x in (0..width).rev() {
+                visit(x, y, &[(1, 0), (1, 1), (0, 1), (-1, 1)]);
+            }
+        }
+        let data = nearest
+            .iter()
+            .zip(&distances)
+            .zip(self.data)
+            .map(|((&(x, y), &distance), &value)| {
+                if distance == usize::MAX {
+                    value
+                } else {
+                    self.data[y * width + x]
+                }
+            })
+            .collect();
+        Ok(filtered(self, data))
+    }
+}
+
+impl DepthFrame {
+    /// Filters the frame with a median, see [`DepthFrameRef::median()`](struct.DepthFrameRef.html#method.median)
+    pub fn median(&self, format: FreenectDepthFormat, radius: u32) -> Result<DepthFrame> {
+        self.as_frame_ref().median(format, radius)
+    }
+
+    /// Filters the frame bilaterally, see [`DepthFrameRef::bilateral()`](struct.DepthFrameRef.html#method.bilateral)
+    pub fn bilateral(
+        &self,
+        format: FreenectDepthFormat,
+        radius: u32,
+        sigma_space: f32,
+        sigma_depth: f32,
+    ) -> Result<DepthFrame> {
+        self.as_frame_ref()
+            .bilateral(format, radius, sigma_space, sigma_depth)
+    }
+
+    /// Filters the frame along the edges of an rgb frame, see
+    /// [`DepthFrameRef::joint_bilateral()`](struct.DepthFrameRef.html#method.joint_bilateral)
+    pub fn joint_bilateral(
+        &self,
+        format: FreenectDepthFormat,
+        guide: &VideoFrameRef,
+        radius: u32,
+        sigma_space: f32,
+        sigma_color: f32,
+    ) -> Result<DepthFrame> {
+        self.as_frame_ref()
+            .joint_bilateral(format, guide, radius, sigma_space, sigma_color)
+    }
+
+    /// Fills narrow holes, see [`DepthFrameRef::fill_holes()`](struct.DepthFrameRef.html#method.fill_holes)
+    pub fn fill_holes(&self, format: FreenectDepthFormat, radius: u32) -> Result<DepthFrame> {
+        self.as_frame_ref().fill_holes(format, radius)
+    }
+
+    /// Fills all holes with the nearest reading, see
+    /// [`DepthFrameRef::inpaint_nearest()`](struct.DepthFrameRef.html#method.inpaint_nearest)
+    pub fn inpaint_nearest(&self, format: FreenectDepthFormat) -> Result<DepthFrame> {
+        self.as_frame_ref().inpaint_nearest(format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The upper median of the readings of each window, by sorting them
+    fn sorted_median(
+        frame: &DepthFrameRef,
+        format: FreenectDepthFormat,
+        radius: usize,
+    ) -> Vec<u16> {
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        let mut data = frame.data.to_vec();
+        for y in 0..height {
+            let (top, bottom) = window(y, radius, height);
+            for x in 0..width {
+                let (left, right) = window(x, radius, width);
+                let mut values: Vec<u16> = (top..=bottom)
+                    .flat_map(|row| &frame.data[row * width + left..=row * width + right])
+                    .copied()
+                    .filter(|&value| format.is_reading(value))
+                    .collect();
+                values.sort_unstable();
+                let area = (bottom - top + 1) * (right - left + 1);
+                let i = y * width + x;
+                if values.is_empty()
+                    || (!format.is_reading(frame.data[i]) && 2 * values.len() <= area)
+                {
+                    continue;
+                }
+                data[i] = values[values.len() / 2];
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn median_matches_sorting() {
+        let mut seed: u32 = 7;
+        let mut random = move || {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            seed >> 16
+        };
+        let (width, height) = (23, 17);
+        // Holes, jumps across many blocks of the histogram, 2047 and the extremes
+        let data: Vec<u16> = (0..width * height)
+            .map(|_| match random() % 8 {
+                0 => 0,
+                1 => 2047,
+                2 => u16::MAX,
+                3 => 1,
+                _ => 1000 + (random() % 3000) as u16,
+            })
+            .collect();
+        let frame = DepthFrameRef {
+            data: &data,
+            timestamp: 0,
+            width,
+            height,
+        };
+        for &format in &[FreenectDepthFormat::Bit11, FreenectDepthFormat::MM] {
+            for radius in 0..=25 {
+                assert_eq!(
+                    frame.median(format, radius).unwrap().data,
+                    sorted_median(&frame, format, radius as usize),
+                    "radius {} in {:?}",
+                    radius,
+                    format
+                );
+            }
+        }
+    }
+
+    /// The weighted mean of each window, pixel by pixel, with the weight of a neighbour by its
+    /// index in the frame, or `None` if it doesn't count
+    fn weighed(
+        frame: &DepthFrameRef,
+        radius: usize,
+        sigma_space: f32,
+        weight: impl Fn(usize, usize) -> Option<f32>,
+    ) -> Vec<Option<u16>> {
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        let side = 2 * radius + 1;
+        let spatial = spatial_weights(radius, sigma_space);
+        (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                let ((top, bottom), (left, right)) =
+                    (window(y, radius, height), window(x, radius, width));
+                let (mut sum, mut weights) = (0.0, 0.0);
+                for row in top..=bottom {
+                    for column in left..=right {
+                        let j = row * width + column;
+                        if let Some(weight) = weight(i, j) {
+                            let near = spatial[(row + radius - y) * side + column + radius - x];
+                            sum += near * weight * frame.data[j] as f32;
+                            weights += near * weight;
+                        }
+                    }
+                }
+                if weights > 0.0 {
+                    Some((sum / weights).round() as u16)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bilateral_filters_match_weighing_each_window() {
+        let mut seed: u32 = 11;
+        let mut random = move || {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            seed >> 16
+        };
+        // Neither side a multiple of the lanes, with holes, steps and colour edges
+        let (width, height) = (37, 23);
+        let depth: Vec<u16> = (0..width * height)
+            .map(|i| match random() % 10 {
+                0 => 0,
+                _ => 1000 + 500 * (i % width / 9) as u16 + (random() % 40) as u16,
+            })
+            .collect();
+        let rgb: Vec<u8> = (0..width * height * 3)
+            .map(|i| (i / 3 % width * 7 + random() % 8) as u8)
+            .collect();
+        let frame = DepthFrameRef {
+            data: &depth,
+            timestamp: 0,
+            width,
+            height,
+        };
+        let format = FreenectDepthFormat::MM;
+        let close = |filtered: Vec<u16>, expected: Vec<Option<u16>>| {
+            for (i, (&value, expected)) in filtered.iter().zip(expected).enumerate() {
+                let expected = expected.unwrap_or(depth[i]);
+                assert!(value.abs_diff(expected) <= 1, "{} at {}", value, i);
+            }
+        };
+        for radius in 0..=4 {
+            let scale = bell_scale(10.0);
+            let expected = weighed(&frame, radius, 2.0, |i, j| {
+                let difference = depth[j] as f32 - depth[i] as f32;
+                match (depth[i], depth[j]) {
+                    (0, _) | (_, 0) => None,
+                    _ => Some(bell(difference * difference * scale)),
+                }
+            });
+            let filtered = frame.bilateral(format, radius as u32, 2.0, 10.0).unwrap();
+            close(filtered.data, expected);
+
+            let scale = bell_scale(20.0) / 9.0;
+            for &channels in &[1, 3] {
+                let guide = VideoFrameRef {
+                    data: &rgb[..width as usize * height as usize * channels],
+                    timestamp: 0,
+                    width,
+                    height,
+                };
+                let expected = weighed(&frame, radius, 2.0, |i, j| {
+                    let difference: f32 = (0..3)
+                        .map(|c| {
+                            let (a, b) = (
+                                rgb[i * channels + c % channels],
+                                rgb[j * channels + c % channels],
+                            );
+                            a.abs_diff(b) as f32
+                        })
+                        .sum();
+                    match depth[j] {
+                        0 => None,
+                        _ => Some(bell(difference * difference * scale)),
+                    }
+                });
+                let filtered = frame
+                    .joint_bilateral(format, &guide, radius as u32, 2.0, 20.0)
+                    .unwrap();
+                close(filtered.data, expected);
+            }
+        }
+    }
+}
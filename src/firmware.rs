@@ -0,0 +1,106 @@
+//! Audio firmware which newer devices need before their motor and LED respond, enabled by the
+//! `firmware` feature.
+use crate::freenect::{FreenectError, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+// The audio firmware of Microsoft's Kinect SDK is about 200 KiB, images far off can't be one
+const MIN_SIZE: usize = 16 * 1024;
+const MAX_SIZE: usize = 1024 * 1024;
+
+/// The kind of device a firmware is uploaded to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FirmwareTarget {
+    /// Xbox Kinect model 1473
+    Model1473,
+    /// Kinect for Windows
+    KinectForWindows,
+}
+
+/// An audio firmware image, usually the `audios.bin` shipped with Microsoft's Kinect SDK.
+///
+/// Pass it to [`set_firmware()`](struct.FreenectContext.html#method.set_firmware).
+/// # Examples
+/// ```rust
+/// use freenectrs::freenect::Firmware;
+///
+/// let firmware = Firmware::from_bytes(vec![0; 200 * 1024]).unwrap();
+/// assert_eq!(firmware.sha256().len(), 64);
+/// assert!(firmware.verify_sha256(&firmware.sha256()).is_ok());
+/// assert!(firmware.verify_sha256(&"0".repeat(64)).is_err());
+///
+/// // Far too small or too large to be an audio firmware
+/// assert!(Firmware::from_bytes(Vec::new()).is_err());
+/// assert!(Firmware::from_bytes(vec![1, 2, 3]).is_err());
+/// assert!(Firmware::from_bytes(vec![0; 2 * 1024 * 1024]).is_err());
+/// ```
+#[derive(Clone, Debug)]
+pub struct Firmware {
+    data: Box<[u8]>,
+}
+
+impl Firmware {
+    /// Wraps the given firmware image. Fails unless its size lies between 16 KiB and 1 MiB, as
+    /// audio firmware images do.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Firmware> {
+        if !(MIN_SIZE..=MAX_SIZE).contains(&data.len()) {
+            return Err(FreenectError::new(format!(
+                "Firmware of {} bytes can't be an audio firmware, which has {} to {} bytes",
+                data.len(),
+                MIN_SIZE,
+                MAX_SIZE
+            )));
+        }
+        Ok(Firmware {
+            data: data.into_boxed_slice(),
+        })
+    }
+
+    /// Reads the firmware image from a file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Firmware> {
+        let path = path.as_ref();
+        let data = fs::read(path).map_err(|err| {
+            FreenectError::new(format!(
+                "Unable to read firmware {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+        Firmware::from_bytes(data)
+    }
+
+    /// Returns the SHA-256 hash of the image as lowercase hex string
+    pub fn sha256(&self) -> String {
+        Sha256::digest(&self.data)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Checks the image against a known SHA-256 hash, given as hex string
+    pub fn verify_sha256(&self, expected: &str) -> Result<()> {
+        let actual = self.sha256();
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            return Err(FreenectError::new(format!(
+                "Firmware hash mismatch, expected {} but got {}",
+                expected, actual
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the size of the image in bytes
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Always false, an empty firmware is rejected on creation
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
@@ -1,17 +1,53 @@
 use super::freenect_ffi as ffi;
+#[cfg(feature = "ndarray")]
+pub use crate::arrays::{stack_depth_frames, stack_video_frames};
+#[cfg(feature = "async")]
+pub use crate::asynchronous::FreenectSyncedStreams;
+pub use crate::beamforming::{Direction, MicArray};
+pub use crate::buffers::{BufferRing, FilledBuffer, FrameBuffer};
+use crate::channel::{frame_channel, FrameSender};
+pub use crate::channel::{DeliveryPolicy, FrameReceiver, OverflowStrategy};
+pub use crate::chunk::{Chunk, ChunkHandler, RawCopy};
+use crate::chunk::{dispatch_chunk, DEPTH_PACKET_SIZE, VIDEO_PACKET_SIZE};
+pub use crate::fakenect::{DumpEntry, DumpEntryKind, FakenectDump, FakenectWriter};
+#[cfg(feature = "firmware")]
+pub use crate::firmware::{Firmware, FirmwareTarget};
+#[cfg(feature = "nalgebra")]
+pub use crate::geometry::{kinect_depth_to_rgb, roll_pitch, CameraIntrinsics};
+pub use crate::group::{
+    DeviceGroup, DeviceSelector, DeviceStats, Frame, GroupConfig, GroupStream, TaggedFrame,
+};
+use crate::hotplug::DeviceHealth;
+pub use crate::hotplug::{DeviceEvent, DeviceWatcher};
+#[cfg(feature = "http")]
+pub use crate::http::{LiveView, LiveViewConfig};
+#[cfg(feature = "image")]
+pub use crate::images::DepthImage;
+#[cfg(feature = "net")]
+pub use crate::net::{DeviceSource, NetClient, NetServer, ServerConfig, VideoEncoding};
+#[cfg(feature = "nalgebra")]
+pub use crate::normals::{NormalConfig, NormalMap, NormalMethod};
+pub use crate::segmentation::{BackgroundConfig, BackgroundModel, Blob, Segmentation};
+#[cfg(unix)]
+pub use crate::shm::{
+    ShmFrame, ShmFrameGuard, ShmFrameKind, ShmFrameRef, ShmPublisher, ShmSubscriber,
+};
+pub use crate::temporal::{TemporalConfig, TemporalDepthStream, TemporalFilter};
+#[cfg(feature = "tui")]
+pub use crate::tui::{half_blocks, TerminalViewer, ViewerConfig};
+pub use crate::visualize::{ColorMap, DepthColorizer, DepthRange};
 use std;
-use std::cell::RefCell;
 use std::error::Error;
+use std::ffi::{CStr, CString};
 use std::fmt;
-use std::mem;
-use std::mem::MaybeUninit;
+use std::os;
+use std::panic::{self, AssertUnwindSafe};
 use std::ptr;
 use std::result;
 use std::slice;
-use std::sync::mpsc::{
-    channel, sync_channel, Receiver, Sender, SyncSender, TryRecvError, TrySendError,
-};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, Weak};
 use std::thread;
 
 #[derive(Debug)]
@@ -20,7 +56,7 @@ pub struct FreenectError {
 }
 
 impl FreenectError {
-    fn new<T: Into<String>>(text: T) -> FreenectError {
+    pub(crate) fn new<T: Into<String>>(text: T) -> FreenectError {
         FreenectError {
             reason: text.into(),
         }
@@ -34,18 +70,89 @@ impl fmt::Display for FreenectError {
 
 impl Error for FreenectError {
     fn description(&self) -> &str {
-        &*self.reason
+        &self.reason
     }
 }
 
 pub type Result<T> = result::Result<T, FreenectError>;
 
+/// The state shared by all handles of a context
+struct ContextInner {
+    ctx: *mut ffi::freenect_context,
+    use_video: AtomicBool,
+    use_audio: AtomicBool,
+    process_thread: Mutex<Option<ProcessThread>>,
+    // libfreenect only stores the address of the firmware, so it lives as long as the context
+    #[cfg(feature = "firmware")]
+    firmware: Mutex<Vec<Firmware>>,
+}
+
+// libfreenect's context functions may be called from any thread. Events are only processed by
+// the single process thread.
+unsafe impl Send for ContextInner {}
+unsafe impl Sync for ContextInner {}
+
+/// The thread spawned by [`spawn_process_thread()`](struct.FreenectContext.html#method.spawn_process_thread)
+struct ProcessThread {
+    // Dropping it stops the thread
+    stop_sender: Sender<()>,
+    // Set before joining, releases handlers waiting for space in a `Block` stream
+    interrupt: Arc<AtomicBool>,
+    joiner: thread::JoinHandle<()>,
+}
+
+impl ContextInner {
+    /// Opens the device with the given camera serial
+    fn open_raw_device_by_serial(&self, serial: &str) -> Result<*mut ffi::freenect_device> {
+        let c_serial = CString::new(serial)
+            .map_err(|_| FreenectError::new("Serial must not contain a nul byte"))?;
+        unsafe {
+            let mut dev: *mut ffi::freenect_device = ptr::null_mut();
+            if ffi::freenect_open_device_by_camera_serial(self.ctx, &mut dev, c_serial.as_ptr()) < 0
+            {
+                return Err(FreenectError::new(format!(
+                    "Unable to open device with serial {}",
+                    serial
+                )));
+            }
+            Ok(dev)
+        }
+    }
+
+    fn stop_process_thread(&self) -> thread::Result<()> {
+        let process_thread = self.process_thread.lock().unwrap().take();
+        if let Some(ProcessThread {
+            stop_sender,
+            interrupt,
+            joiner,
+        }) = process_thread
+        {
+            drop(stop_sender);
+            interrupt.store(true, Ordering::SeqCst);
+            joiner.join()
+        } else {
+            // No Thread started
+            Ok(())
+        }
+    }
+}
+
+impl Drop for ContextInner {
+    fn drop(&mut self) {
+        self.stop_process_thread().unwrap();
+        unsafe {
+            ffi::freenect_shutdown(self.ctx);
+        }
+    }
+}
+
 /// FreenectContext should be used as the main point to interact with Kinect.
+///
+/// It is a cheap handle which can be cloned and shared between threads.
+/// libfreenect's context is shut down once the last handle and every device opened with it are dropped.
+#[derive(Clone)]
 pub struct FreenectContext {
-    ctx: *mut ffi::freenect_context,
-    drop_sender: Mutex<Option<Sender<()>>>,
-    use_video: bool,
-    thread_joiner: RefCell<Option<thread::JoinHandle<()>>>,
+    inner: Arc<ContextInner>,
 }
 
 impl FreenectContext {
@@ -55,45 +162,123 @@ impl FreenectContext {
     /// [setup_video_motor]: struct.FreenectContext.html#method.setup_video_motor
     pub fn init() -> Result<FreenectContext> {
         unsafe {
-            let mut ctx: *mut ffi::freenect_context = MaybeUninit::uninit().assume_init();
+            let mut ctx: *mut ffi::freenect_context = ptr::null_mut();
             let res = ffi::freenect_init(&mut ctx, ptr::null_mut());
             if res < 0 {
                 return Err(FreenectError::new("Unable to create freenect context"));
             }
+            #[cfg(feature = "log")]
+            ffi::freenect_set_log_callback(ctx, Some(log_callback));
             let res = FreenectContext {
-                ctx: ctx,
-                use_video: false,
-                drop_sender: Mutex::new(None),
-                thread_joiner: RefCell::new(None),
+                inner: Arc::new(ContextInner {
+                    ctx,
+                    use_video: AtomicBool::new(false),
+                    use_audio: AtomicBool::new(false),
+                    process_thread: Mutex::new(None),
+                    #[cfg(feature = "firmware")]
+                    firmware: Mutex::new(Vec::new()),
+                }),
             };
             Ok(res)
         }
     }
 
     /// Tells libfreenect to select the camera subdevice
-    pub fn setup_video(mut self) -> FreenectContext {
+    pub fn setup_video(self) -> FreenectContext {
         unsafe {
             ffi::freenect_select_subdevices(
-                self.ctx,
-                ffi::freenect_device_flags::FREENECT_DEVICE_CAMERA,
+                self.inner.ctx,
+                ffi::freenect_device_flags::FREENECT_DEVICE_CAMERA as os::raw::c_int,
             );
-            self.use_video = true;
+            self.inner.use_video.store(true, Ordering::SeqCst);
             self
         }
     }
 
     /// Tells libfreenect to select the camera and motor subdevice
-    pub fn setup_video_motor(mut self) -> FreenectContext {
+    pub fn setup_video_motor(self) -> FreenectContext {
+        unsafe {
+            ffi::freenect_select_subdevices(
+                self.inner.ctx,
+                ffi::freenect_device_flags::FREENECT_DEVICE_CAMERA as os::raw::c_int
+                    | ffi::freenect_device_flags::FREENECT_DEVICE_MOTOR as os::raw::c_int,
+            );
+            self.inner.use_video.store(true, Ordering::SeqCst);
+            self
+        }
+    }
+
+    /// Tells libfreenect to select the audio subdevice in addition to the subdevices selected so far.
+    /// The microphone array of newer devices needs the audio firmware, see
+    /// [`set_firmware()`](#method.set_firmware) of the `firmware` feature.
+    pub fn setup_audio(self) -> FreenectContext {
         unsafe {
-            // ffi::freenect_select_subdevices(self.ctx,
-            //                                 ffi::freenect_device_flags::FREENECT_DEVICE_CAMERA | ffi::freenect_device_flags::FREENECT_DEVICE_MOTOR);
-            // TODO Bit-Flags support
-            ffi::freenect_select_subdevices(self.ctx, mem::transmute(3));
-            self.use_video = true;
+            let enabled = ffi::freenect_enabled_subdevices(self.inner.ctx);
+            ffi::freenect_select_subdevices(
+                self.inner.ctx,
+                enabled | ffi::freenect_device_flags::FREENECT_DEVICE_AUDIO as os::raw::c_int,
+            );
+            self.inner.use_audio.store(true, Ordering::SeqCst);
             self
         }
     }
 
+    /// Sets which messages libfreenect emits. With the `log` feature, messages are forwarded to the
+    /// `log` crate using the target `freenect`, otherwise libfreenect prints them to stderr.
+    pub fn set_log_level(&self, level: LogLevel) {
+        unsafe {
+            ffi::freenect_set_log_level(self.inner.ctx, level.to_c());
+        }
+    }
+
+    /// Supplies the audio firmware which model 1473 and Kinect for Windows devices need before their
+    /// motor and LED respond. libfreenect uploads it when such a device is opened, so call this before
+    /// opening devices. The firmware is kept until the context is dropped.
+    ///
+    /// A broken image can leave the device unusable until it is unplugged, so the image is only
+    /// passed on if its SHA-256 hash matches `sha256`, the hex string of a hash known to be good.
+    /// No hashes are bundled, as the image differs between releases of Microsoft's Kinect SDK.
+    /// # Examples
+    /// ```rust,no_run
+    /// use freenectrs::freenect::{Firmware, FirmwareTarget, FreenectContext};
+    ///
+    /// let ctx = FreenectContext::init_with_video_motor().unwrap();
+    /// let firmware = Firmware::from_file("/usr/share/libfreenect/audios.bin").unwrap();
+    /// // The hash of the image taken from the SDK installer
+    /// let sha256 = std::fs::read_to_string("/usr/share/libfreenect/audios.bin.sha256").unwrap();
+    /// ctx.set_firmware(FirmwareTarget::Model1473, firmware, &sha256).unwrap();
+    /// let device = ctx.open_device(0).unwrap();
+    /// device.set_tilt_degree(10.0).unwrap();
+    /// ```
+    #[cfg(feature = "firmware")]
+    pub fn set_firmware(
+        &self,
+        target: FirmwareTarget,
+        firmware: Firmware,
+        sha256: &str,
+    ) -> Result<()> {
+        firmware.verify_sha256(sha256)?;
+        let mut kept = self.inner.firmware.lock().unwrap();
+        // The size was checked to fit on creation of the firmware
+        let (ptr, len) = (
+            firmware.as_bytes().as_ptr() as *mut os::raw::c_uchar,
+            firmware.len() as os::raw::c_uint,
+        );
+        unsafe {
+            match target {
+                FirmwareTarget::Model1473 => {
+                    ffi::freenect_set_fw_address_nui(self.inner.ctx, ptr, len)
+                }
+                FirmwareTarget::KinectForWindows => {
+                    ffi::freenect_set_fw_address_k4w(self.inner.ctx, ptr, len)
+                }
+            }
+        }
+        // Moving the firmware doesn't move its heap buffer
+        kept.push(firmware);
+        Ok(())
+    }
+
     /// Initializes the context directly for fetching rgb and depth data
     pub fn init_with_video() -> Result<FreenectContext> {
         FreenectContext::init().map(|x| x.setup_video())
@@ -104,10 +289,36 @@ impl FreenectContext {
         FreenectContext::init().map(|x| x.setup_video_motor())
     }
 
+    /// Returns all depth modes libfreenect supports
+    pub fn supported_depth_modes() -> Vec<FreenectFrameMode<FreenectDepthFormat>> {
+        let mut modes = Vec::new();
+        for &resolution in FreenectResolution::ALL {
+            for &format in FreenectDepthFormat::ALL {
+                let mode =
+                    unsafe { ffi::freenect_find_depth_mode(resolution.to_c(), format.to_c()) };
+                modes.extend(FreenectFrameMode::from_c(mode, format));
+            }
+        }
+        modes
+    }
+
+    /// Returns all video modes libfreenect supports
+    pub fn supported_video_modes() -> Vec<FreenectFrameMode<FreenectVideoFormat>> {
+        let mut modes = Vec::new();
+        for &resolution in FreenectResolution::ALL {
+            for &format in FreenectVideoFormat::ALL {
+                let mode =
+                    unsafe { ffi::freenect_find_video_mode(resolution.to_c(), format.to_c()) };
+                modes.extend(FreenectFrameMode::from_c(mode, format));
+            }
+        }
+        modes
+    }
+
     /// Returns the number of available devices
     pub fn num_devices(&self) -> Result<u32> {
         unsafe {
-            let res = ffi::freenect_num_devices(self.ctx);
+            let res = ffi::freenect_num_devices(self.inner.ctx);
             if res < 0 {
                 return Err(FreenectError::new(
                     "Unable to retrieve number of freenect devices",
@@ -117,39 +328,104 @@ impl FreenectContext {
         }
     }
 
+    /// Returns the camera serials of all available devices, in the order of their numbers
+    pub fn device_serials(&self) -> Result<Vec<String>> {
+        unsafe {
+            let mut list: *mut ffi::freenect_device_attributes = ptr::null_mut();
+            if ffi::freenect_list_device_attributes(self.inner.ctx, &mut list) < 0 {
+                return Err(FreenectError::new("Unable to list freenect devices"));
+            }
+            let mut serials = Vec::new();
+            let mut attributes = list;
+            while !attributes.is_null() {
+                let serial = (*attributes).camera_serial;
+                if !serial.is_null() {
+                    serials.push(CStr::from_ptr(serial).to_string_lossy().into_owned());
+                }
+                attributes = (*attributes).next;
+            }
+            ffi::freenect_free_device_attributes(list);
+            Ok(serials)
+        }
+    }
+
     /// Opens a device using the given number.
     pub fn open_device(&self, nr: u32) -> Result<FreenectDevice> {
         if nr >= self.num_devices()? {
             return Err(FreenectError::new(format!("Device nr {} not found", nr)));
         }
+        // libfreenect lists the devices in the same order it numbers them
+        let serial = self
+            .device_serials()
+            .ok()
+            .and_then(|serials| serials.into_iter().nth(nr as usize));
         unsafe {
-            let mut dev: *mut ffi::freenect_device = MaybeUninit::uninit().assume_init();
-            if ffi::freenect_open_device(self.ctx, &mut dev, nr as i32) < 0 {
+            let mut dev: *mut ffi::freenect_device = ptr::null_mut();
+            if ffi::freenect_open_device(self.inner.ctx, &mut dev, nr as i32) < 0 {
                 return Err(FreenectError::new("Unable to open device"));
             }
-            Ok(FreenectDevice::new(self, dev, self.use_video))
+            Ok(FreenectDevice::new(
+                self.inner.clone(),
+                dev,
+                serial,
+                self.inner.use_video.load(Ordering::SeqCst),
+                self.inner.use_audio.load(Ordering::SeqCst),
+            ))
+        }
+    }
+
+    /// Opens the device with the given camera serial.
+    pub fn open_device_by_serial(&self, serial: &str) -> Result<FreenectDevice> {
+        let dev = self.inner.open_raw_device_by_serial(serial)?;
+        Ok(FreenectDevice::new(
+            self.inner.clone(),
+            dev,
+            Some(serial.to_owned()),
+            self.inner.use_video.load(Ordering::SeqCst),
+            self.inner.use_audio.load(Ordering::SeqCst),
+        ))
+    }
+
+    /// Returns whether the thread processing libfreenect's events is running.
+    /// The thread ends by itself if processing fails, for example because a device was unplugged.
+    pub fn is_process_thread_running(&self) -> bool {
+        match *self.inner.process_thread.lock().unwrap() {
+            Some(ref process_thread) => !process_thread.joiner.is_finished(),
+            None => false,
         }
     }
 
+    /// Returns whether a process thread was spawned and not stopped since, regardless of whether it is still running
+    pub(crate) fn is_process_thread_spawned(&self) -> bool {
+        self.inner.process_thread.lock().unwrap().is_some()
+    }
+
     /// Spawns a thread which process libfreenect's events. Only one of this thread can be spawned.
     pub fn spawn_process_thread(&self) -> Result<()> {
-        let mut drop_sender = self.drop_sender.lock().unwrap();
-        if let Some(ref sender) = *drop_sender {
-            if let Err(_) = sender.send(()) {
+        let mut process_thread = self.inner.process_thread.lock().unwrap();
+        if let Some(running) = process_thread.take() {
+            // The thread only ends by itself if processing the events failed
+            if running.stop_sender.send(()).is_ok() {
+                *process_thread = Some(running);
                 return Err(FreenectError::new(
                     "Cannot spawn process thread, thread is already \
                                                running",
                 ));
             }
+            let _ = running.joiner.join();
         }
         struct Helper {
             ctx: *mut ffi::freenect_context,
         }
         unsafe impl Send for Helper {}
         let (s, r) = channel();
-        *drop_sender = Some(s);
-        let ctx = Helper { ctx: self.ctx };
-        *self.thread_joiner.borrow_mut() = Some(thread::spawn(move || {
+        let ctx = Helper {
+            ctx: self.inner.ctx,
+        };
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let thread_interrupt = interrupt.clone();
+        let joiner = thread::spawn(move || {
+            crate::channel::set_interrupt(thread_interrupt);
             'l: loop {
                 match r.try_recv() {
                     Ok(_) => (),
@@ -173,35 +449,123 @@ impl FreenectContext {
                     }
                 }
             }
-        }));
+        });
+        *process_thread = Some(ProcessThread {
+            stop_sender: s,
+            interrupt,
+            joiner,
+        });
         Ok(())
     }
 
     /// Stops the thread which process libfreenect's events
     pub fn stop_process_thread(&self) -> thread::Result<()> {
-        let mut drop_sender = self.drop_sender.lock().unwrap();
-        if let Some(sender) = drop_sender.take() {
-            drop(sender);
+        self.inner.stop_process_thread()
+    }
+}
+
+/// Forwards a message of libfreenect to the `log` crate.
+/// A panic of the logger must not unwind into libfreenect, so it is caught and the message is dropped.
+#[cfg(feature = "log")]
+unsafe extern "C" fn log_callback(
+    _ctx: *mut ffi::freenect_context,
+    level: ffi::freenect_loglevel,
+    msg: *const os::raw::c_char,
+) {
+    if msg.is_null() {
+        return;
+    }
+    let msg = CStr::from_ptr(msg).to_string_lossy();
+    let _ = panic::catch_unwind(AssertUnwindSafe(
+        || log::log!(target: "freenect", LogLevel::from_c(level).to_log(), "{}", msg.trim_end()),
+    ));
+}
+
+/// Enumeration of log levels, ordered from the most severe one. See [here](https://zarvox.org/kinect/docs/libfreenect_8h.html) for more information
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Fatal,
+    Error,
+    Warning,
+    Notice,
+    Info,
+    Debug,
+    Spew,
+    Flood,
+}
+
+impl LogLevel {
+    fn to_c(self) -> ffi::freenect_loglevel {
+        match self {
+            LogLevel::Fatal => ffi::freenect_loglevel::FREENECT_LOG_FATAL,
+            LogLevel::Error => ffi::freenect_loglevel::FREENECT_LOG_ERROR,
+            LogLevel::Warning => ffi::freenect_loglevel::FREENECT_LOG_WARNING,
+            LogLevel::Notice => ffi::freenect_loglevel::FREENECT_LOG_NOTICE,
+            LogLevel::Info => ffi::freenect_loglevel::FREENECT_LOG_INFO,
+            LogLevel::Debug => ffi::freenect_loglevel::FREENECT_LOG_DEBUG,
+            LogLevel::Spew => ffi::freenect_loglevel::FREENECT_LOG_SPEW,
+            LogLevel::Flood => ffi::freenect_loglevel::FREENECT_LOG_FLOOD,
         }
-        if let Some(joiner) = self.thread_joiner.borrow_mut().take() {
-            joiner.join()
-        } else {
-            // No Thread started
-            Ok(())
+    }
+
+    #[cfg(feature = "log")]
+    fn from_c(level: ffi::freenect_loglevel) -> LogLevel {
+        match level {
+            ffi::freenect_loglevel::FREENECT_LOG_FATAL => LogLevel::Fatal,
+            ffi::freenect_loglevel::FREENECT_LOG_ERROR => LogLevel::Error,
+            ffi::freenect_loglevel::FREENECT_LOG_WARNING => LogLevel::Warning,
+            ffi::freenect_loglevel::FREENECT_LOG_NOTICE => LogLevel::Notice,
+            ffi::freenect_loglevel::FREENECT_LOG_INFO => LogLevel::Info,
+            ffi::freenect_loglevel::FREENECT_LOG_DEBUG => LogLevel::Debug,
+            ffi::freenect_loglevel::FREENECT_LOG_SPEW => LogLevel::Spew,
+            ffi::freenect_loglevel::FREENECT_LOG_FLOOD => LogLevel::Flood,
         }
     }
-}
 
-impl Drop for FreenectContext {
-    fn drop(&mut self) {
-        self.stop_process_thread().unwrap();
-        unsafe {
-            ffi::freenect_shutdown(self.ctx);
+    /// Returns the matching level of the `log` crate
+    #[cfg(feature = "log")]
+    pub fn to_log(self) -> log::Level {
+        match self {
+            LogLevel::Fatal | LogLevel::Error => log::Level::Error,
+            LogLevel::Warning => log::Level::Warn,
+            LogLevel::Notice | LogLevel::Info => log::Level::Info,
+            LogLevel::Debug => log::Level::Debug,
+            LogLevel::Spew | LogLevel::Flood => log::Level::Trace,
         }
     }
 }
 
+/// Implements `Display` and `FromStr` with the given names, which are matched case-insensitively
+macro_rules! named_enum {
+    ($ty:ident, $what:expr, { $($variant:ident => $name:expr),* $(,)? }) => {
+        impl $ty {
+            /// All variants, in declaration order
+            pub const ALL: &'static [$ty] = &[$($ty::$variant),*];
+        }
+
+        impl fmt::Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(match *self {
+                    $($ty::$variant => $name),*
+                })
+            }
+        }
+
+        impl std::str::FromStr for $ty {
+            type Err = FreenectError;
+
+            fn from_str(s: &str) -> Result<$ty> {
+                match s.to_ascii_lowercase().as_str() {
+                    $($name => Ok($ty::$variant),)*
+                    _ => Err(FreenectError::new(format!("Unknown {} {}", $what, s))),
+                }
+            }
+        }
+    };
+}
+
 /// Enumeration of available resolutions. See [here](https://zarvox.org/kinect/docs/libfreenect_8h.html#ac610d7d6fe91ecb4c54e3ff2d2525a58) for more information
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FreenectResolution {
     Low,
     Medium,
@@ -218,7 +582,14 @@ impl FreenectResolution {
     }
 }
 
+named_enum!(FreenectResolution, "resolution", {
+    Low => "low",
+    Medium => "medium",
+    High => "high",
+});
+
 /// Enumeration of video formats. See [here](https://zarvox.org/kinect/docs/libfreenect_8h.html#ad651c9006cf1033b2246b49cae0b453a) for more information
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FreenectVideoFormat {
     Rgb,
     Bayer,
@@ -245,7 +616,18 @@ impl FreenectVideoFormat {
     }
 }
 
+named_enum!(FreenectVideoFormat, "video format", {
+    Rgb => "rgb",
+    Bayer => "bayer",
+    IR8 => "ir8",
+    IR10 => "ir10",
+    IR10Packed => "ir10packed",
+    YuvRgb => "yuvrgb",
+    YuvRaw => "yuvraw",
+});
+
 /// Enumeration of depth formats. See [here](https://zarvox.org/kinect/docs/libfreenect_8h.html#a258154182b56136a1c75a64ad5db6022) for more information
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FreenectDepthFormat {
     Bit11,
     Bit10,
@@ -272,266 +654,1612 @@ impl FreenectDepthFormat {
             FreenectDepthFormat::MM => ffi::freenect_depth_format::FREENECT_DEPTH_MM,
         }
     }
+
+    /// Returns whether `value` is a reading in this format. Pixels without one are 0, and in
+    /// 11-bit and 10-bit depth also 2047. Millimeters go beyond 2047, so it is a reading there.
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::FreenectDepthFormat;
+    ///
+    /// assert!(!FreenectDepthFormat::Bit11.is_reading(2047));
+    /// assert!(FreenectDepthFormat::MM.is_reading(2047));
+    /// assert!(!FreenectDepthFormat::MM.is_reading(0));
+    /// ```
+    pub fn is_reading(self, value: u16) -> bool {
+        // The value 11-bit and 10-bit depth use for pixels without a reading
+        const NO_READING: u16 = 2047;
+        match self {
+            FreenectDepthFormat::Registered | FreenectDepthFormat::MM => value != 0,
+            _ => value != 0 && value != NO_READING,
+        }
+    }
 }
 
-/// Interacts with a freenect device (Kinect)
-pub struct FreenectDevice<'a, 'b> {
-    pub ctx: &'a FreenectContext,
-    device: *mut ffi::freenect_device,
-    use_video: bool,
-    depth_sender: Mutex<Option<SyncSender<(&'b [u16], u32)>>>,
-    video_sender: Mutex<Option<SyncSender<(&'b [u8], u32)>>>,
+named_enum!(FreenectDepthFormat, "depth format", {
+    Bit11 => "11bit",
+    Bit10 => "10bit",
+    Bit11Packed => "11bitpacked",
+    Bit10Packed => "10bitpacked",
+    Registered => "registered",
+    MM => "mm",
+});
+
+/// Enumeration of device flags. See [here](https://zarvox.org/kinect/docs/libfreenect_8h.html) for more information
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FreenectFlag {
+    AutoExposure,
+    AutoWhiteBalance,
+    RawColor,
+    MirrorDepth,
+    MirrorVideo,
+    NearMode,
 }
 
-impl<'a, 'b> FreenectDevice<'a, 'b> {
-    fn new(
-        ctx: &'a FreenectContext,
-        device: *mut ffi::freenect_device,
-        use_video: bool,
-    ) -> FreenectDevice {
-        let res = FreenectDevice {
-            ctx: ctx,
-            device: device,
-            use_video: use_video,
-            depth_sender: Mutex::new(None),
-            video_sender: Mutex::new(None),
-        };
-        unsafe {
-            ffi::freenect_set_depth_callback(device, Some(depth_callback));
-            if use_video {
-                ffi::freenect_set_video_callback(device, Some(video_callback));
-            }
+impl FreenectFlag {
+    fn to_c(self) -> ffi::freenect_flag {
+        match self {
+            FreenectFlag::AutoExposure => ffi::freenect_flag::FREENECT_AUTO_EXPOSURE,
+            FreenectFlag::AutoWhiteBalance => ffi::freenect_flag::FREENECT_AUTO_WHITE_BALANCE,
+            FreenectFlag::RawColor => ffi::freenect_flag::FREENECT_RAW_COLOR,
+            FreenectFlag::MirrorDepth => ffi::freenect_flag::FREENECT_MIRROR_DEPTH,
+            FreenectFlag::MirrorVideo => ffi::freenect_flag::FREENECT_MIRROR_VIDEO,
+            FreenectFlag::NearMode => ffi::freenect_flag::FREENECT_NEAR_MODE,
         }
-        res
     }
+}
 
-    /// Returns a stream-object for fetching depth data
-    pub fn depth_stream(&'a self) -> Result<FreenectDepthStream<'a, 'b>> {
-        unsafe {
-            ffi::freenect_set_user(self.device, mem::transmute(self));
-        }
-        let mut d_sender = self.depth_sender.lock().unwrap();
-        if d_sender.is_some() {
-            return Err(FreenectError::new("Depth Stream already created"));
+/// Enumeration of LED states. See [here](https://zarvox.org/kinect/docs/libfreenect_8h.html) for more information
+/// # Examples
+/// ```rust
+/// use freenectrs::freenect::FreenectLed;
+///
+/// let led: FreenectLed = "Blink-Green".parse().unwrap();
+/// assert_eq!(led, FreenectLed::BlinkGreen);
+/// assert_eq!(led.to_string(), "blink-green");
+/// assert!("purple".parse::<FreenectLed>().is_err());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FreenectLed {
+    Off,
+    Green,
+    Red,
+    Yellow,
+    BlinkGreen,
+    BlinkRedYellow,
+}
+
+impl FreenectLed {
+    fn to_c(self) -> ffi::freenect_led_options {
+        match self {
+            FreenectLed::Off => ffi::freenect_led_options::LED_OFF,
+            FreenectLed::Green => ffi::freenect_led_options::LED_GREEN,
+            FreenectLed::Red => ffi::freenect_led_options::LED_RED,
+            FreenectLed::Yellow => ffi::freenect_led_options::LED_YELLOW,
+            FreenectLed::BlinkGreen => ffi::freenect_led_options::LED_BLINK_GREEN,
+            FreenectLed::BlinkRedYellow => ffi::freenect_led_options::LED_BLINK_RED_YELLOW,
         }
-        let (res, sender) = FreenectDepthStream::new(self)?;
-        *d_sender = Some(sender);
-        Ok(res)
     }
+}
 
-    pub fn set_depth_mode(
-        &self,
-        resol: FreenectResolution,
-        format: FreenectDepthFormat,
-    ) -> Result<()> {
-        unsafe {
-            if ffi::freenect_set_depth_mode(
-                self.device,
-                ffi::freenect_find_depth_mode(resol.to_c(), format.to_c()),
-            ) < 0
-            {
-                return Err(FreenectError::new("Unable to set depth mode"));
-            }
+named_enum!(FreenectLed, "LED state", {
+    Off => "off",
+    Green => "green",
+    Red => "red",
+    Yellow => "yellow",
+    BlinkGreen => "blink-green",
+    BlinkRedYellow => "blink-red-yellow",
+});
+
+/// What the tilt motor is doing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FreenectTiltStatus {
+    Stopped,
+    /// The motor reached its limit before the requested angle
+    Limit,
+    Moving,
+}
+
+named_enum!(FreenectTiltStatus, "tilt status", {
+    Stopped => "stopped",
+    Limit => "limit",
+    Moving => "moving",
+});
+
+/// The state of the tilt motor and the accelerometer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FreenectTiltState {
+    /// The tilt angle in degrees
+    pub degree: f64,
+    pub status: FreenectTiltStatus,
+    /// The acceleration along the x, y and z axes in m/s²
+    pub accelerometer: [f64; 3],
+}
+
+/// A mode libfreenect supports for depth or video frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FreenectFrameMode<F> {
+    pub resolution: FreenectResolution,
+    pub format: F,
+    pub width: u32,
+    pub height: u32,
+    /// Frames per second
+    pub framerate: u32,
+    /// The size of a frame in bytes
+    pub bytes: usize,
+}
+
+impl<F> FreenectFrameMode<F> {
+    /// Returns `None` if libfreenect doesn't support the mode
+    fn from_c(mode: ffi::freenect_frame_mode, format: F) -> Option<FreenectFrameMode<F>> {
+        let resolution = FreenectResolution::ALL
+            .iter()
+            .copied()
+            .find(|resolution| resolution.to_c() as u32 == mode.resolution as u32)?;
+        if mode.is_valid == 0 {
+            return None;
         }
-        Ok(())
+        Some(FreenectFrameMode {
+            resolution,
+            format,
+            width: mode.width.max(0) as u32,
+            height: mode.height.max(0) as u32,
+            framerate: mode.framerate.max(0) as u32,
+            bytes: mode.bytes.max(0) as usize,
+        })
     }
+}
 
-    pub fn set_video_mode(
-        &self,
-        resol: FreenectResolution,
-        format: FreenectVideoFormat,
-    ) -> Result<()> {
-        unsafe {
-            if ffi::freenect_set_video_mode(
-                self.device,
-                ffi::freenect_find_video_mode(resol.to_c(), format.to_c()),
-            ) < 0
-            {
-                return Err(FreenectError::new("Unable to change video mode"));
-            }
+/// A depth frame received from Kinect.
+#[derive(Clone, Debug)]
+pub struct DepthFrame {
+    /// The depth values, row by row
+    pub data: Vec<u16>,
+    /// The timestamp libfreenect assigned to this frame
+    pub timestamp: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DepthFrame {
+    /// Borrows the frame, for functions which also take frames handed to callbacks
+    pub fn as_frame_ref(&self) -> DepthFrameRef<'_> {
+        DepthFrameRef {
+            data: &self.data,
+            timestamp: self.timestamp,
+            width: self.width,
+            height: self.height,
         }
-        Ok(())
     }
+}
 
-    /// Returns a stream-object for fetching rgb data
-    pub fn video_stream(&'a self) -> Result<FreenectVideoStream<'a, 'b>> {
-        unsafe {
-            ffi::freenect_set_user(self.device, mem::transmute(self));
-        }
-        if !self.use_video {
-            return Err(FreenectError::new(
-                "Cannot build video stream, context created without \
-                                           support for it",
-            ));
-        }
-        let mut v_sender = self.video_sender.lock().unwrap();
-        if v_sender.is_some() {
-            return Err(FreenectError::new("Video Stream already created"));
+/// A depth frame which borrows libfreenect's internal buffer.
+/// It is handed to callbacks registered with [`on_depth()`][on_depth].
+///
+/// [on_depth]: struct.FreenectDevice.html#method.on_depth
+#[derive(Clone, Copy, Debug)]
+pub struct DepthFrameRef<'a> {
+    /// The depth values, row by row
+    pub data: &'a [u16],
+    /// The timestamp libfreenect assigned to this frame
+    pub timestamp: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl<'a> DepthFrameRef<'a> {
+    /// Copies the frame so it can be kept after the callback returned
+    pub fn to_owned(&self) -> DepthFrame {
+        DepthFrame {
+            data: self.data.to_vec(),
+            timestamp: self.timestamp,
+            width: self.width,
+            height: self.height,
         }
-        let (res, sender) = FreenectVideoStream::new(self)?;
-        *v_sender = Some(sender);
-        Ok(res)
     }
+}
 
-    pub fn get_tilt_degree(&self) -> Result<f64> {
-        unsafe {
-            if ffi::freenect_update_tilt_state(self.device) < 0 {
-                Err(FreenectError::new("Unable to update tilt state"))
-            } else {
-                let state = ffi::freenect_get_tilt_state(self.device);
-                let degree = ffi::freenect_get_tilt_degs(state);
-                Ok(degree)
-            }
+/// A video frame received from Kinect. The layout of `data` depends on the video format.
+#[derive(Clone, Debug)]
+pub struct VideoFrame {
+    /// The pixel bytes, row by row
+    pub data: Vec<u8>,
+    /// The timestamp libfreenect assigned to this frame
+    pub timestamp: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl VideoFrame {
+    /// Borrows the frame, for functions which also take frames handed to callbacks
+    pub fn as_frame_ref(&self) -> VideoFrameRef<'_> {
+        VideoFrameRef {
+            data: &self.data,
+            timestamp: self.timestamp,
+            width: self.width,
+            height: self.height,
         }
     }
+}
 
-    pub fn set_tilt_degree(&self, degree: f64) -> Result<()> {
-        unsafe {
-            if ffi::freenect_set_tilt_degs(self.device, degree) < 0 {
-                Err(FreenectError::new("Unable to set tilt degree"))
-            } else {
-                Ok(())
-            }
+/// A video frame which borrows libfreenect's internal buffer.
+/// It is handed to callbacks registered with [`on_video()`][on_video].
+///
+/// [on_video]: struct.FreenectDevice.html#method.on_video
+#[derive(Clone, Copy, Debug)]
+pub struct VideoFrameRef<'a> {
+    /// The pixel bytes, row by row
+    pub data: &'a [u8],
+    /// The timestamp libfreenect assigned to this frame
+    pub timestamp: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl<'a> VideoFrameRef<'a> {
+    /// Copies the frame so it can be kept after the callback returned
+    pub fn to_owned(&self) -> VideoFrame {
+        VideoFrame {
+            data: self.data.to_vec(),
+            timestamp: self.timestamp,
+            width: self.width,
+            height: self.height,
         }
     }
 }
 
-impl<'a, 'b> Drop for FreenectDevice<'a, 'b> {
-    fn drop(&mut self) {
-        unsafe {
-            ffi::freenect_close_device(self.device);
-        }
+/// Samples received from the microphone array, recorded at 16 kHz.
+/// All channels hold `num_samples` samples.
+#[derive(Clone, Debug)]
+pub struct AudioFrame {
+    /// The four microphone channels, in the order libfreenect reports them
+    pub mics: [Vec<i32>; 4],
+    /// The mono channel after Kinect's echo cancellation
+    pub cancelled: Vec<i16>,
+    pub num_samples: usize,
+}
+
+impl AudioFrame {
+    /// Returns the microphone channels interleaved, i.e. the first sample of each microphone,
+    /// then the second sample of each and so on
+    pub fn interleaved(&self) -> Vec<i32> {
+        (0..self.num_samples)
+            .flat_map(|i| self.mics.iter().map(move |mic| mic[i]))
+            .collect()
     }
 }
 
-/// FreenectDepthStream should be used for fetching depth data from Kinect.
+/// Audio samples which borrow libfreenect's internal buffers.
+/// It is handed to callbacks registered with [`on_audio()`][on_audio].
+///
+/// [on_audio]: struct.FreenectDevice.html#method.on_audio
 /// # Examples
-/// ```rust,ignore
-/// let dstream = device.depth_stream().unwrap();
-/// if let Ok((data, timestamp)) = dstream.receiver.recv() {
-///  // Fetch depth value for position x,y
-///  let idx = y * 640 + x;
-///  let depth_value = data[idx as usize];
-/// //...
-/// }
+/// ```rust
+/// use freenectrs::freenect::AudioFrameRef;
+///
+/// // The buffers libfreenect passes to its audio callback
+/// let mics = [vec![1, 2], vec![3, 4], vec![5, 6], vec![7, 8]];
+/// let cancelled = vec![-1i16, 1];
+/// let frame = unsafe {
+///     AudioFrameRef::from_raw(
+///         2,
+///         [mics[0].as_ptr(), mics[1].as_ptr(), mics[2].as_ptr(), mics[3].as_ptr()],
+///         cancelled.as_ptr(),
+///     )
+/// };
+/// let owned = frame.to_owned();
+/// assert_eq!(owned.num_samples, 2);
+/// assert_eq!(owned.mics[2], vec![5, 6]);
+/// assert_eq!(owned.cancelled, vec![-1, 1]);
+/// assert_eq!(owned.interleaved(), vec![1, 3, 5, 7, 2, 4, 6, 8]);
+/// assert!(AudioFrameRef::new([&[1], &[2], &[3], &[]], &[0]).is_err());
 /// ```
-pub struct FreenectDepthStream<'a, 'b>
-where
-    'b: 'a,
-{
-    parent: &'a FreenectDevice<'a, 'b>,
-    pub receiver: Receiver<(&'b [u16], u32)>,
+#[derive(Clone, Copy, Debug)]
+pub struct AudioFrameRef<'a> {
+    /// The four microphone channels, in the order libfreenect reports them
+    pub mics: [&'a [i32]; 4],
+    /// The mono channel after Kinect's echo cancellation
+    pub cancelled: &'a [i16],
+    pub num_samples: usize,
 }
 
-impl<'a, 'b> FreenectDepthStream<'a, 'b> {
-    fn new(
-        parent: &'a FreenectDevice<'a, 'b>,
-    ) -> Result<(FreenectDepthStream<'a, 'b>, SyncSender<(&'b [u16], u32)>)> {
-        unsafe {
-            if ffi::freenect_start_depth(parent.device) < 0 {
-                return Err(FreenectError::new("Unable to start depth"));
-            }
+impl<'a> AudioFrameRef<'a> {
+    /// Creates a frame from separate channel buffers. Fails if their lengths differ.
+    pub fn new(mics: [&'a [i32]; 4], cancelled: &'a [i16]) -> Result<AudioFrameRef<'a>> {
+        let num_samples = cancelled.len();
+        if mics.iter().any(|mic| mic.len() != num_samples) {
+            return Err(FreenectError::new(
+                "Audio channels differ in their number of samples",
+            ));
         }
-        let (s, r) = sync_channel(2);
-        Ok((
-            FreenectDepthStream {
-                parent: parent,
-                receiver: r,
-            },
-            s,
-        ))
+        Ok(AudioFrameRef {
+            mics,
+            cancelled,
+            num_samples,
+        })
     }
-}
 
-extern "C" fn depth_callback(
-    dev: *mut ffi::freenect_device,
-    data: *mut std::os::raw::c_void,
-    timestamp: u32,
-) {
-    unsafe {
-        let data = data as *mut u16;
-        let data = slice::from_raw_parts(data, 640 * 480);
-        let device = ffi::freenect_get_user(dev) as *mut FreenectDevice;
-        let device = &*device;
-        let sender = device.depth_sender.lock().unwrap();
-        let sender = sender.as_ref().unwrap();
-        match sender.try_send((data, timestamp)) {
-            Err(TrySendError::Disconnected(_)) => panic!("Depth Channel is disconnected"),
-            _ => (),
+    /// Creates a frame from the buffers libfreenect passes to its audio callback.
+    /// # Safety
+    /// Every pointer must point to `num_samples` valid samples which outlive the frame.
+    pub unsafe fn from_raw(
+        num_samples: usize,
+        mics: [*const i32; 4],
+        cancelled: *const i16,
+    ) -> AudioFrameRef<'a> {
+        AudioFrameRef {
+            mics: [
+                slice::from_raw_parts(mics[0], num_samples),
+                slice::from_raw_parts(mics[1], num_samples),
+                slice::from_raw_parts(mics[2], num_samples),
+                slice::from_raw_parts(mics[3], num_samples),
+            ],
+            cancelled: slice::from_raw_parts(cancelled, num_samples),
+            num_samples,
         }
     }
-}
 
-extern "C" fn video_callback(
-    dev: *mut ffi::freenect_device,
-    data: *mut std::os::raw::c_void,
-    timestamp: u32,
-) {
-    unsafe {
-        let data = data as *mut u8;
-        let data = slice::from_raw_parts(data, 640 * 480 * 3);
-        let device = ffi::freenect_get_user(dev) as *mut FreenectDevice;
-        let device = &*device;
-        let sender = device.video_sender.lock().unwrap();
-        let sender = sender.as_ref().unwrap();
-        match sender.try_send((data, timestamp)) {
-            Err(TrySendError::Disconnected(_)) => panic!("Video Channel is disconnected"),
-            _ => (),
+    /// Copies the samples so they can be kept after the callback returned
+    pub fn to_owned(&self) -> AudioFrame {
+        AudioFrame {
+            mics: [
+                self.mics[0].to_vec(),
+                self.mics[1].to_vec(),
+                self.mics[2].to_vec(),
+                self.mics[3].to_vec(),
+            ],
+            cancelled: self.cancelled.to_vec(),
+            num_samples: self.num_samples,
         }
     }
 }
 
-impl<'a, 'b> Drop for FreenectDepthStream<'a, 'b> {
-    fn drop(&mut self) {
-        unsafe {
-            ffi::freenect_stop_depth(self.parent.device);
-        }
-        *self.parent.depth_sender.lock().unwrap() = None;
-    }
+type DepthHandler = Box<dyn FnMut(&DepthFrameRef) + Send>;
+type VideoHandler = Box<dyn FnMut(&VideoFrameRef) + Send>;
+type AudioHandler = Box<dyn FnMut(&AudioFrameRef) + Send>;
+
+/// The handlers libfreenect's callbacks dispatch to
+#[derive(Default)]
+struct Handlers {
+    depth: Mutex<Option<DepthHandler>>,
+    video: Mutex<Option<VideoHandler>>,
+    audio: Mutex<Option<AudioHandler>>,
+    depth_chunk: Mutex<Option<Box<dyn ChunkHandler>>>,
+    video_chunk: Mutex<Option<Box<dyn ChunkHandler>>>,
+    // The caller-owned buffers libfreenect currently writes frames into, null if it uses its own
+    depth_buffer: AtomicPtr<os::raw::c_void>,
+    video_buffer: AtomicPtr<os::raw::c_void>,
 }
 
-/// FreenectVideoStream should be used for fetching rgb data from Kinect.
-/// # Examples
-/// ```rust,ignore
-/// let dstream = device.depth_stream().unwrap();
-/// if let Ok((data, timestamp)) = dstream.receiver.recv() {
-///  // Fetch rgb value for position x,y
-///  let idx = 3 * (y * 640 + x) as usize;
-///  let (r, g, b) = (data[idx], data[idx + 1], data[idx + 2]);
-/// //...
-/// }
-/// ```
-pub struct FreenectVideoStream<'a, 'b>
-where
-    'b: 'a,
-{
-    parent: &'a FreenectDevice<'a, 'b>,
-    pub receiver: Receiver<(&'b [u8], u32)>,
-}
-impl<'a, 'b> FreenectVideoStream<'a, 'b> {
-    fn new(
-        parent: &'a FreenectDevice<'a, 'b>,
-    ) -> Result<(FreenectVideoStream<'a, 'b>, SyncSender<(&'b [u8], u32)>)> {
-        unsafe {
-            if ffi::freenect_start_video(parent.device) < 0 {
-                return Err(FreenectError::new("Unable to start video"));
-            }
-        }
-        let (s, r) = sync_channel(2);
-        Ok((
-            FreenectVideoStream {
-                parent: parent,
-                receiver: r,
-            },
-            s,
-        ))
-    }
+/// The settings made on a device, restored if it gets reconnected
+#[derive(Default)]
+struct DeviceConfig {
+    depth_mode: Option<(FreenectResolution, FreenectDepthFormat)>,
+    video_mode: Option<(FreenectResolution, FreenectVideoFormat)>,
+    flags: Vec<(FreenectFlag, bool)>,
+    tilt_degree: Option<f64>,
+    led: Option<FreenectLed>,
+    // Whether chunk handlers were installed
+    depth_chunks: bool,
+    video_chunks: bool,
+    // The streams libfreenect was told to run
+    depth_running: bool,
+    video_running: bool,
+    audio_running: bool,
 }
-impl<'a, 'b> Drop for FreenectVideoStream<'a, 'b> {
-    fn drop(&mut self) {
-        unsafe {
-            ffi::freenect_stop_video(self.parent.device);
-        }
-        *self.parent.video_sender.lock().unwrap() = None;
+
+/// The state shared by all handles of a device. The user data of the device points to it.
+struct DeviceInner {
+    // Keeps the context alive as long as the device is open
+    ctx: Arc<ContextInner>,
+    // Replaced if the device gets reconnected
+    device: AtomicPtr<ffi::freenect_device>,
+    serial: Option<String>,
+    use_video: bool,
+    use_audio: bool,
+    handlers: Handlers,
+    // Serializes calls which change the device or read its state
+    control: Mutex<DeviceConfig>,
+    // Failed transfers to the device and callbacks of its streams so far, which the device
+    // watcher compares between polls
+    errors: AtomicU64,
+    callbacks: AtomicU64,
+}
+
+// libfreenect's device functions may be called from any thread, as long as calls reading or
+// changing the same state don't overlap. `control` takes care of this, starting and stopping
+// streams included. A handler lock may be taken before `control` but never while holding it,
+// because handlers run with their lock held and may change the device themselves.
+unsafe impl Send for DeviceInner {}
+unsafe impl Sync for DeviceInner {}
+
+unsafe fn set_depth_mode(
+    device: *mut ffi::freenect_device,
+    resol: FreenectResolution,
+    format: FreenectDepthFormat,
+) -> Result<()> {
+    if ffi::freenect_set_depth_mode(
+        device,
+        ffi::freenect_find_depth_mode(resol.to_c(), format.to_c()),
+    ) < 0
+    {
+        return Err(FreenectError::new("Unable to set depth mode"));
+    }
+    Ok(())
+}
+
+unsafe fn set_video_mode(
+    device: *mut ffi::freenect_device,
+    resol: FreenectResolution,
+    format: FreenectVideoFormat,
+) -> Result<()> {
+    if ffi::freenect_set_video_mode(
+        device,
+        ffi::freenect_find_video_mode(resol.to_c(), format.to_c()),
+    ) < 0
+    {
+        return Err(FreenectError::new("Unable to change video mode"));
+    }
+    Ok(())
+}
+
+unsafe fn set_flag(
+    device: *mut ffi::freenect_device,
+    flag: FreenectFlag,
+    enabled: bool,
+) -> Result<()> {
+    let value = if enabled {
+        ffi::freenect_flag_value::FREENECT_ON
+    } else {
+        ffi::freenect_flag_value::FREENECT_OFF
+    };
+    if ffi::freenect_set_flag(device, flag.to_c(), value) < 0 {
+        return Err(FreenectError::new("Unable to set flag"));
+    }
+    Ok(())
+}
+
+unsafe fn set_tilt_degree(device: *mut ffi::freenect_device, degree: f64) -> Result<()> {
+    if ffi::freenect_set_tilt_degs(device, degree) < 0 {
+        return Err(FreenectError::new("Unable to set tilt degree"));
+    }
+    Ok(())
+}
+
+unsafe fn set_led(device: *mut ffi::freenect_device, led: FreenectLed) -> Result<()> {
+    if ffi::freenect_set_led(device, led.to_c()) < 0 {
+        return Err(FreenectError::new("Unable to set LED"));
+    }
+    Ok(())
+}
+
+impl DeviceInner {
+    fn raw(&self) -> *mut ffi::freenect_device {
+        self.device.load(Ordering::SeqCst)
+    }
+
+    /// Counts a failed transfer to the device
+    fn count_error<T>(&self, result: Result<T>) -> Result<T> {
+        if result.is_err() {
+            self.errors.fetch_add(1, Ordering::SeqCst);
+        }
+        result
+    }
+
+    /// Points libfreenect's user data and callbacks of `device` to this state
+    unsafe fn attach(&self, device: *mut ffi::freenect_device, config: &DeviceConfig) {
+        // The shared state lives on the heap, so this address stays valid until the device is closed
+        ffi::freenect_set_user(device, self as *const DeviceInner as *mut os::raw::c_void);
+        ffi::freenect_set_depth_callback(device, Some(depth_callback));
+        if self.use_video {
+            ffi::freenect_set_video_callback(device, Some(video_callback));
+        }
+        if self.use_audio {
+            ffi::freenect_set_audio_in_callback(device, Some(audio_callback));
+        }
+        if config.depth_chunks {
+            ffi::freenect_set_depth_chunk_callback(device, Some(depth_chunk_callback));
+        }
+        if config.video_chunks {
+            ffi::freenect_set_video_chunk_callback(device, Some(video_chunk_callback));
+        }
+    }
+
+    /// Reopens the device by its serial and restores modes, flags, tilt and running streams.
+    /// Events must not be processed meanwhile.
+    fn reconnect(&self) -> Result<()> {
+        let serial = self
+            .serial
+            .as_ref()
+            .ok_or_else(|| FreenectError::new("Unable to reconnect device without serial"))?;
+        let config = self.control.lock().unwrap();
+        let device = self.ctx.open_raw_device_by_serial(serial)?;
+        let old = self.device.swap(device, Ordering::SeqCst);
+        unsafe {
+            ffi::freenect_close_device(old);
+            self.attach(device, &config);
+            if let Some((resol, format)) = config.depth_mode {
+                set_depth_mode(device, resol, format)?;
+            }
+            if let Some((resol, format)) = config.video_mode {
+                set_video_mode(device, resol, format)?;
+            }
+            for &(flag, enabled) in &config.flags {
+                set_flag(device, flag, enabled)?;
+            }
+            if let Some(degree) = config.tilt_degree {
+                set_tilt_degree(device, degree)?;
+            }
+            if let Some(led) = config.led {
+                set_led(device, led)?;
+            }
+            let depth_buffer = self.handlers.depth_buffer.load(Ordering::SeqCst);
+            if !depth_buffer.is_null() {
+                ffi::freenect_set_depth_buffer(device, depth_buffer);
+            }
+            let video_buffer = self.handlers.video_buffer.load(Ordering::SeqCst);
+            if !video_buffer.is_null() {
+                ffi::freenect_set_video_buffer(device, video_buffer);
+            }
+            if config.depth_running && ffi::freenect_start_depth(device) < 0 {
+                return Err(FreenectError::new("Unable to start depth"));
+            }
+            if config.video_running && ffi::freenect_start_video(device) < 0 {
+                return Err(FreenectError::new("Unable to start video"));
+            }
+            if config.audio_running && ffi::freenect_start_audio(device) < 0 {
+                return Err(FreenectError::new("Unable to start audio"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Installs `handler` and starts the depth stream.
+    /// Frames are written into `buffer` unless it is null.
+    fn start_depth(&self, handler: DepthHandler, buffer: *mut os::raw::c_void) -> Result<()> {
+        let mut current = self.handlers.depth.lock().unwrap();
+        if current.is_some() {
+            return Err(FreenectError::new(
+                "Depth Stream or callback already created",
+            ));
+        }
+        let mut config = self.control.lock().unwrap();
+        unsafe {
+            if !buffer.is_null() {
+                ffi::freenect_set_depth_buffer(self.raw(), buffer);
+                self.handlers.depth_buffer.store(buffer, Ordering::SeqCst);
+            }
+            if ffi::freenect_start_depth(self.raw()) < 0 {
+                self.errors.fetch_add(1, Ordering::SeqCst);
+                self.reset_depth_buffer();
+                return Err(FreenectError::new("Unable to start depth"));
+            }
+        }
+        config.depth_running = true;
+        *current = Some(handler);
+        Ok(())
+    }
+
+    fn stop_depth(&self) {
+        {
+            let mut config = self.control.lock().unwrap();
+            unsafe {
+                ffi::freenect_stop_depth(self.raw());
+                self.reset_depth_buffer();
+            }
+            config.depth_running = false;
+        }
+        *self.handlers.depth.lock().unwrap() = None;
+    }
+
+    /// Lets libfreenect use its own buffer again, the depth stream must not run
+    unsafe fn reset_depth_buffer(&self) {
+        if !self.handlers.depth_buffer.load(Ordering::SeqCst).is_null() {
+            ffi::freenect_set_depth_buffer(self.raw(), ptr::null_mut());
+            self.handlers
+                .depth_buffer
+                .store(ptr::null_mut(), Ordering::SeqCst);
+        }
+    }
+
+    /// Installs `handler` and starts the video stream.
+    /// Frames are written into `buffer` unless it is null.
+    fn start_video(&self, handler: VideoHandler, buffer: *mut os::raw::c_void) -> Result<()> {
+        if !self.use_video {
+            return Err(FreenectError::new(
+                "Cannot build video stream, context created without \
+                                           support for it",
+            ));
+        }
+        let mut current = self.handlers.video.lock().unwrap();
+        if current.is_some() {
+            return Err(FreenectError::new(
+                "Video Stream or callback already created",
+            ));
+        }
+        let mut config = self.control.lock().unwrap();
+        unsafe {
+            if !buffer.is_null() {
+                ffi::freenect_set_video_buffer(self.raw(), buffer);
+                self.handlers.video_buffer.store(buffer, Ordering::SeqCst);
+            }
+            if ffi::freenect_start_video(self.raw()) < 0 {
+                self.errors.fetch_add(1, Ordering::SeqCst);
+                self.reset_video_buffer();
+                return Err(FreenectError::new("Unable to start video"));
+            }
+        }
+        config.video_running = true;
+        *current = Some(handler);
+        Ok(())
+    }
+
+    fn stop_video(&self) {
+        {
+            let mut config = self.control.lock().unwrap();
+            unsafe {
+                ffi::freenect_stop_video(self.raw());
+                self.reset_video_buffer();
+            }
+            config.video_running = false;
+        }
+        *self.handlers.video.lock().unwrap() = None;
+    }
+
+    /// Lets libfreenect use its own buffer again, the video stream must not run
+    unsafe fn reset_video_buffer(&self) {
+        if !self.handlers.video_buffer.load(Ordering::SeqCst).is_null() {
+            ffi::freenect_set_video_buffer(self.raw(), ptr::null_mut());
+            self.handlers
+                .video_buffer
+                .store(ptr::null_mut(), Ordering::SeqCst);
+        }
+    }
+
+    /// Installs `handler` and starts the audio stream
+    fn start_audio(&self, handler: AudioHandler) -> Result<()> {
+        if !self.use_audio {
+            return Err(FreenectError::new(
+                "Cannot build audio stream, context created without support for it",
+            ));
+        }
+        let mut current = self.handlers.audio.lock().unwrap();
+        if current.is_some() {
+            return Err(FreenectError::new(
+                "Audio Stream or callback already created",
+            ));
+        }
+        let mut config = self.control.lock().unwrap();
+        unsafe {
+            if ffi::freenect_start_audio(self.raw()) < 0 {
+                self.errors.fetch_add(1, Ordering::SeqCst);
+                return Err(FreenectError::new("Unable to start audio"));
+            }
+        }
+        config.audio_running = true;
+        *current = Some(handler);
+        Ok(())
+    }
+
+    fn stop_audio(&self) {
+        {
+            let mut config = self.control.lock().unwrap();
+            unsafe {
+                ffi::freenect_stop_audio(self.raw());
+            }
+            config.audio_running = false;
+        }
+        *self.handlers.audio.lock().unwrap() = None;
+    }
+}
+
+impl Drop for DeviceInner {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::freenect_close_device(self.raw());
+        }
+    }
+}
+
+/// Interacts with a freenect device (Kinect)
+///
+/// It is a cheap handle which can be cloned and moved to other threads.
+/// The device is closed once the last handle and every stream created with it are dropped.
+#[derive(Clone)]
+pub struct FreenectDevice {
+    inner: Arc<DeviceInner>,
+}
+
+impl FreenectDevice {
+    fn new(
+        ctx: Arc<ContextInner>,
+        device: *mut ffi::freenect_device,
+        serial: Option<String>,
+        use_video: bool,
+        use_audio: bool,
+    ) -> FreenectDevice {
+        let inner = Arc::new(DeviceInner {
+            ctx,
+            device: AtomicPtr::new(device),
+            serial,
+            use_video,
+            use_audio,
+            handlers: Handlers::default(),
+            control: Mutex::new(DeviceConfig::default()),
+            errors: AtomicU64::new(0),
+            callbacks: AtomicU64::new(0),
+        });
+        unsafe {
+            inner.attach(device, &DeviceConfig::default());
+        }
+        FreenectDevice { inner }
+    }
+
+    /// Returns the camera serial of this device, if known
+    pub fn serial(&self) -> Option<&str> {
+        self.inner.serial.as_deref()
+    }
+
+    /// Reopens the device and restores its modes, flags, tilt and running streams.
+    /// The process thread must not run meanwhile.
+    pub(crate) fn reconnect(&self) -> Result<()> {
+        self.inner.reconnect()
+    }
+
+    pub(crate) fn downgrade(&self) -> WeakFreenectDevice {
+        WeakFreenectDevice(Arc::downgrade(&self.inner))
+    }
+
+    /// Returns what the device watcher compares between polls to notice a failed device
+    pub(crate) fn health(&self) -> DeviceHealth {
+        let config = self.inner.control.lock().unwrap();
+        let streaming = config.depth_running || config.video_running || config.audio_running;
+        DeviceHealth {
+            handle: self.inner.raw() as usize,
+            errors: self.inner.errors.load(Ordering::SeqCst),
+            callbacks: Some(self.inner.callbacks.load(Ordering::SeqCst)).filter(|_| streaming),
+        }
+    }
+
+    /// Returns the context this device was opened with
+    pub fn context(&self) -> FreenectContext {
+        FreenectContext {
+            inner: self.inner.ctx.clone(),
+        }
+    }
+
+    /// Returns a stream-object for fetching depth data.
+    /// Frames are delivered using the default [`DeliveryPolicy`](enum.DeliveryPolicy.html).
+    pub fn depth_stream(&self) -> Result<FreenectDepthStream> {
+        self.depth_stream_with_policy(DeliveryPolicy::default())
+    }
+
+    /// Returns a stream-object for fetching depth data which delivers frames according to `policy`
+    pub fn depth_stream_with_policy(&self, policy: DeliveryPolicy) -> Result<FreenectDepthStream> {
+        FreenectDepthStream::new(self.clone(), policy)
+    }
+
+    /// Returns a stream-object which writes depth frames into the given caller-owned buffers instead of
+    /// copying them. The buffers are used in turns, each one is handed out as
+    /// [`FilledBuffer`](struct.FilledBuffer.html) and used again once it is dropped.
+    /// If all other buffers are still handed out, frames are skipped.
+    ///
+    /// Fails if there are less than two buffers or a buffer is smaller than a frame of the current depth mode
+    /// or not aligned to 2 bytes.
+    /// # Examples
+    /// ```rust,no_run
+    /// use freenectrs::freenect::{DeliveryPolicy, FreenectContext};
+    ///
+    /// let ctx = FreenectContext::init_with_video().unwrap();
+    /// let device = ctx.open_device(0).unwrap();
+    /// let buffers = vec![vec![0u16; 640 * 480]; 4];
+    /// let dstream = device.depth_buffer_stream(buffers, DeliveryPolicy::KeepLatest).unwrap();
+    /// ctx.spawn_process_thread().unwrap();
+    /// if let Ok(frame) = dstream.receiver.recv() {
+    ///     let depth: &Vec<u16> = &frame;
+    ///     // ... the buffer returns to the stream once frame is dropped
+    /// }
+    /// ```
+    pub fn depth_buffer_stream<B: FrameBuffer>(
+        &self,
+        buffers: Vec<B>,
+        policy: DeliveryPolicy,
+    ) -> Result<FreenectDepthBufferStream<B>> {
+        FreenectDepthBufferStream::new(self.clone(), BufferRing::new(buffers)?, policy)
+    }
+
+    /// Starts the depth stream and calls `handler` for every frame, without copying it into a channel.
+    ///
+    /// The frame borrows libfreenect's internal buffer and is only valid during the call.
+    /// Use [`DepthFrameRef::to_owned()`](struct.DepthFrameRef.html#method.to_owned) to keep it.
+    /// `handler` runs within the thread processing libfreenect's events, so it should return quickly
+    /// and must not drop the returned object. A panic within `handler` only discards the current frame.
+    /// Depth streaming stops as soon as the returned object is dropped.
+    /// # Examples
+    /// ```rust,ignore
+    /// let callback = device.on_depth(|frame| {
+    ///     // upload frame.data to the GPU ...
+    /// }).unwrap();
+    /// ```
+    pub fn on_depth<F>(&self, handler: F) -> Result<FreenectDepthCallback>
+    where
+        F: FnMut(&DepthFrameRef) + Send + 'static,
+    {
+        self.inner.start_depth(Box::new(handler), ptr::null_mut())?;
+        Ok(FreenectDepthCallback {
+            device: self.clone(),
+        })
+    }
+
+    /// Assembles depth frames from their USB packets with `handler` instead of libfreenect's copying.
+    ///
+    /// Depth streams and callbacks decode the frame from libfreenect's buffer once all packets have
+    /// arrived, so they only receive valid data if `handler` fills this buffer, for example using
+    /// [`RawCopy`](struct.RawCopy.html). A panic within `handler` only discards the current packet.
+    pub fn set_depth_chunk_handler<H>(&self, handler: H)
+    where
+        H: ChunkHandler + 'static,
+    {
+        let mut current = self.inner.handlers.depth_chunk.lock().unwrap();
+        let mut config = self.inner.control.lock().unwrap();
+        *current = Some(Box::new(handler));
+        config.depth_chunks = true;
+        unsafe {
+            ffi::freenect_set_depth_chunk_callback(self.inner.raw(), Some(depth_chunk_callback));
+        }
+    }
+
+    /// Goes back to copying the packets of depth frames into libfreenect's buffer
+    pub fn reset_depth_chunk_handler(&self) {
+        self.set_depth_chunk_handler(RawCopy);
+    }
+
+    pub fn set_depth_mode(
+        &self,
+        resol: FreenectResolution,
+        format: FreenectDepthFormat,
+    ) -> Result<()> {
+        let mut config = self.inner.control.lock().unwrap();
+        unsafe {
+            set_depth_mode(self.inner.raw(), resol, format)?;
+        }
+        config.depth_mode = Some((resol, format));
+        Ok(())
+    }
+
+    pub fn set_video_mode(
+        &self,
+        resol: FreenectResolution,
+        format: FreenectVideoFormat,
+    ) -> Result<()> {
+        let mut config = self.inner.control.lock().unwrap();
+        unsafe {
+            set_video_mode(self.inner.raw(), resol, format)?;
+        }
+        config.video_mode = Some((resol, format));
+        Ok(())
+    }
+
+    /// Returns the depth mode last set with [`set_depth_mode()`](#method.set_depth_mode)
+    pub fn depth_mode(&self) -> Option<(FreenectResolution, FreenectDepthFormat)> {
+        self.inner.control.lock().unwrap().depth_mode
+    }
+
+    /// Returns the video mode last set with [`set_video_mode()`](#method.set_video_mode)
+    pub fn video_mode(&self) -> Option<(FreenectResolution, FreenectVideoFormat)> {
+        self.inner.control.lock().unwrap().video_mode
+    }
+
+    /// Returns the depth mode the device currently uses
+    pub fn current_depth_mode(&self) -> Option<FreenectFrameMode<FreenectDepthFormat>> {
+        let mode = unsafe { ffi::freenect_get_current_depth_mode(self.inner.raw()) };
+        // The format is read as number, since libfreenect could report one unknown to the bindings
+        let format = FreenectDepthFormat::ALL
+            .iter()
+            .copied()
+            .find(|format| format.to_c() as u32 == mode._bindgen_data_1_[0])?;
+        FreenectFrameMode::from_c(mode, format)
+    }
+
+    /// Returns the video mode the device currently uses
+    pub fn current_video_mode(&self) -> Option<FreenectFrameMode<FreenectVideoFormat>> {
+        let mode = unsafe { ffi::freenect_get_current_video_mode(self.inner.raw()) };
+        let format = FreenectVideoFormat::ALL
+            .iter()
+            .copied()
+            .find(|format| format.to_c() as u32 == mode._bindgen_data_1_[0])?;
+        FreenectFrameMode::from_c(mode, format)
+    }
+
+    /// Enables or disables `flag`
+    pub fn set_flag(&self, flag: FreenectFlag, enabled: bool) -> Result<()> {
+        let mut config = self.inner.control.lock().unwrap();
+        unsafe {
+            set_flag(self.inner.raw(), flag, enabled)?;
+        }
+        config.flags.retain(|&(f, _)| f != flag);
+        config.flags.push((flag, enabled));
+        Ok(())
+    }
+
+    /// Returns a stream-object for fetching rgb data.
+    /// Frames are delivered using the default [`DeliveryPolicy`](enum.DeliveryPolicy.html).
+    pub fn video_stream(&self) -> Result<FreenectVideoStream> {
+        self.video_stream_with_policy(DeliveryPolicy::default())
+    }
+
+    /// Returns a stream-object for fetching rgb data which delivers frames according to `policy`
+    pub fn video_stream_with_policy(&self, policy: DeliveryPolicy) -> Result<FreenectVideoStream> {
+        FreenectVideoStream::new(self.clone(), policy)
+    }
+
+    /// The video counterpart of [`depth_buffer_stream()`](#method.depth_buffer_stream)
+    pub fn video_buffer_stream<B: FrameBuffer>(
+        &self,
+        buffers: Vec<B>,
+        policy: DeliveryPolicy,
+    ) -> Result<FreenectVideoBufferStream<B>> {
+        FreenectVideoBufferStream::new(self.clone(), BufferRing::new(buffers)?, policy)
+    }
+
+    /// Starts the video stream and calls `handler` for every frame, without copying it into a channel.
+    ///
+    /// The same contract as for [`on_depth()`](#method.on_depth) applies: the frame is only valid
+    /// during the call and video streaming stops as soon as the returned object is dropped.
+    pub fn on_video<F>(&self, handler: F) -> Result<FreenectVideoCallback>
+    where
+        F: FnMut(&VideoFrameRef) + Send + 'static,
+    {
+        self.inner.start_video(Box::new(handler), ptr::null_mut())?;
+        Ok(FreenectVideoCallback {
+            device: self.clone(),
+        })
+    }
+
+    /// Returns a stream-object for fetching the samples of the microphone array.
+    /// Frames are delivered using the default [`DeliveryPolicy`](enum.DeliveryPolicy.html).
+    /// The context must have been set up with [`setup_audio()`](struct.FreenectContext.html#method.setup_audio).
+    pub fn audio_stream(&self) -> Result<FreenectAudioStream> {
+        self.audio_stream_with_policy(DeliveryPolicy::default())
+    }
+
+    /// Returns a stream-object for fetching audio samples which delivers frames according to `policy`
+    pub fn audio_stream_with_policy(&self, policy: DeliveryPolicy) -> Result<FreenectAudioStream> {
+        FreenectAudioStream::new(self.clone(), policy)
+    }
+
+    /// Starts the audio stream and calls `handler` for every block of samples, without copying it into a channel.
+    ///
+    /// The same contract as for [`on_depth()`](#method.on_depth) applies: the samples are only valid
+    /// during the call and audio streaming stops as soon as the returned object is dropped.
+    pub fn on_audio<F>(&self, handler: F) -> Result<FreenectAudioCallback>
+    where
+        F: FnMut(&AudioFrameRef) + Send + 'static,
+    {
+        self.inner.start_audio(Box::new(handler))?;
+        Ok(FreenectAudioCallback {
+            device: self.clone(),
+        })
+    }
+
+    /// The video counterpart of [`set_depth_chunk_handler()`](#method.set_depth_chunk_handler)
+    pub fn set_video_chunk_handler<H>(&self, handler: H)
+    where
+        H: ChunkHandler + 'static,
+    {
+        let mut current = self.inner.handlers.video_chunk.lock().unwrap();
+        let mut config = self.inner.control.lock().unwrap();
+        *current = Some(Box::new(handler));
+        config.video_chunks = true;
+        unsafe {
+            ffi::freenect_set_video_chunk_callback(self.inner.raw(), Some(video_chunk_callback));
+        }
+    }
+
+    /// Goes back to copying the packets of video frames into libfreenect's buffer
+    pub fn reset_video_chunk_handler(&self) {
+        self.set_video_chunk_handler(RawCopy);
+    }
+
+    pub fn get_tilt_degree(&self) -> Result<f64> {
+        let _control = self.inner.control.lock().unwrap();
+        unsafe {
+            if ffi::freenect_update_tilt_state(self.inner.raw()) < 0 {
+                self.inner
+                    .count_error(Err(FreenectError::new("Unable to update tilt state")))
+            } else {
+                let state = ffi::freenect_get_tilt_state(self.inner.raw());
+                let degree = ffi::freenect_get_tilt_degs(state);
+                Ok(degree)
+            }
+        }
+    }
+
+    pub fn set_tilt_degree(&self, degree: f64) -> Result<()> {
+        let mut config = self.inner.control.lock().unwrap();
+        unsafe {
+            self.inner
+                .count_error(set_tilt_degree(self.inner.raw(), degree))?;
+        }
+        config.tilt_degree = Some(degree);
+        Ok(())
+    }
+
+    /// Reads the tilt angle, the state of the motor and the accelerometer
+    pub fn get_tilt_state(&self) -> Result<FreenectTiltState> {
+        let _control = self.inner.control.lock().unwrap();
+        unsafe {
+            if ffi::freenect_update_tilt_state(self.inner.raw()) < 0 {
+                return self
+                    .inner
+                    .count_error(Err(FreenectError::new("Unable to update tilt state")));
+            }
+            let state = ffi::freenect_get_tilt_state(self.inner.raw());
+            // Read as an integer, the device may report codes the enum doesn't cover
+            let status = match *(ptr::addr_of!((*state).tilt_status) as *const u32) {
+                0 => FreenectTiltStatus::Stopped,
+                1 => FreenectTiltStatus::Limit,
+                _ => FreenectTiltStatus::Moving,
+            };
+            let mut accelerometer = [0.0; 3];
+            let [x, y, z] = &mut accelerometer;
+            ffi::freenect_get_mks_accel(state, x, y, z);
+            Ok(FreenectTiltState {
+                degree: ffi::freenect_get_tilt_degs(state),
+                status,
+                accelerometer,
+            })
+        }
+    }
+
+    /// Returns the LED state last set with [`set_led()`](#method.set_led)
+    pub fn led(&self) -> Option<FreenectLed> {
+        self.inner.control.lock().unwrap().led
+    }
+
+    pub fn set_led(&self, led: FreenectLed) -> Result<()> {
+        let mut config = self.inner.control.lock().unwrap();
+        unsafe {
+            self.inner.count_error(set_led(self.inner.raw(), led))?;
+        }
+        config.led = Some(led);
+        Ok(())
+    }
+}
+
+/// A handle which doesn't keep the device open
+pub(crate) struct WeakFreenectDevice(Weak<DeviceInner>);
+
+impl WeakFreenectDevice {
+    pub fn upgrade(&self) -> Option<FreenectDevice> {
+        self.0.upgrade().map(|inner| FreenectDevice { inner })
+    }
+}
+
+/// FreenectDepthStream should be used for fetching depth data from Kinect.
+///
+/// The stream keeps its device open and can be moved to other threads.
+/// # Examples
+/// ```rust,ignore
+/// let dstream = device.depth_stream().unwrap();
+/// if let Ok(frame) = dstream.receiver.recv() {
+///  // Fetch depth value for position x,y
+///  let idx = y * frame.width + x;
+///  let depth_value = frame.data[idx as usize];
+///  // Or, with the `ndarray` feature
+///  let depth_value = frame.as_array().unwrap()[[y as usize, x as usize]];
+/// //...
+/// }
+/// ```
+pub struct FreenectDepthStream {
+    device: FreenectDevice,
+    pub receiver: FrameReceiver<DepthFrame>,
+}
+
+impl FreenectDepthStream {
+    fn new(device: FreenectDevice, policy: DeliveryPolicy) -> Result<FreenectDepthStream> {
+        let (sender, receiver) = frame_channel(policy)?;
+        device.inner.start_depth(
+            Box::new(move |frame: &DepthFrameRef| {
+                // An error only means that the stream is about to be dropped
+                let _ = sender.send(frame.to_owned());
+            }),
+            ptr::null_mut(),
+        )?;
+        Ok(FreenectDepthStream { device, receiver })
+    }
+
+    /// Returns the device this stream belongs to
+    pub fn device(&self) -> &FreenectDevice {
+        &self.device
+    }
+}
+
+/// Calls the handler registered for the device whose events are currently processed.
+/// A panic must not unwind into libfreenect, so it is caught and the frame is dropped.
+extern "C" fn depth_callback(
+    dev: *mut ffi::freenect_device,
+    data: *mut std::os::raw::c_void,
+    timestamp: u32,
+) {
+    unsafe {
+        let mode = ffi::freenect_get_current_depth_mode(dev);
+        let frame = DepthFrameRef {
+            data: slice::from_raw_parts(data as *const u16, mode.bytes as usize / 2),
+            timestamp,
+            width: mode.width as u32,
+            height: mode.height as u32,
+        };
+        let device = &*(ffi::freenect_get_user(dev) as *const DeviceInner);
+        device.callbacks.fetch_add(1, Ordering::SeqCst);
+        if let Some(handler) = device.handlers.depth.lock().unwrap().as_mut() {
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| handler(&frame)));
+        }
+    }
+}
+
+/// Returns the size of libfreenect's buffer for depth frames as they arrive from the device
+unsafe fn packed_depth_bytes(dev: *mut ffi::freenect_device) -> usize {
+    use self::ffi::freenect_depth_format::*;
+    let mode = ffi::freenect_get_current_depth_mode(dev);
+    // The format is read as number, since libfreenect could report one unknown to the bindings
+    let packed = if mode._bindgen_data_1_[0] == FREENECT_DEPTH_10BIT as u32
+        || mode._bindgen_data_1_[0] == FREENECT_DEPTH_10BIT_PACKED as u32
+    {
+        FREENECT_DEPTH_10BIT_PACKED
+    } else {
+        FREENECT_DEPTH_11BIT_PACKED
+    };
+    ffi::freenect_find_depth_mode(mode.resolution, packed)
+        .bytes
+        .max(0) as usize
+}
+
+/// Returns the size of libfreenect's buffer for video frames as they arrive from the device
+unsafe fn packed_video_bytes(dev: *mut ffi::freenect_device) -> usize {
+    use self::ffi::freenect_video_format::*;
+    let mode = ffi::freenect_get_current_video_mode(dev);
+    let format = mode._bindgen_data_1_[0];
+    let packed =
+        if format == FREENECT_VIDEO_YUV_RGB as u32 || format == FREENECT_VIDEO_YUV_RAW as u32 {
+            FREENECT_VIDEO_YUV_RAW
+        } else if format == FREENECT_VIDEO_IR_8BIT as u32
+            || format == FREENECT_VIDEO_IR_10BIT as u32
+            || format == FREENECT_VIDEO_IR_10BIT_PACKED as u32
+        {
+            FREENECT_VIDEO_IR_10BIT_PACKED
+        } else {
+            FREENECT_VIDEO_BAYER
+        };
+    ffi::freenect_find_video_mode(mode.resolution, packed)
+        .bytes
+        .max(0) as usize
+}
+
+/// Calls the depth chunk handler of the device whose user data is passed
+extern "C" fn depth_chunk_callback(
+    buffer: *mut os::raw::c_void,
+    pkt_data: *mut os::raw::c_void,
+    pkt_num: os::raw::c_int,
+    datalen: os::raw::c_int,
+    user_data: *mut os::raw::c_void,
+) {
+    unsafe {
+        let device = &*(user_data as *const DeviceInner);
+        let frame_len = packed_depth_bytes(device.raw());
+        dispatch_chunk(
+            &device.handlers.depth_chunk,
+            buffer,
+            frame_len,
+            DEPTH_PACKET_SIZE,
+            pkt_data,
+            pkt_num,
+            datalen,
+        );
+    }
+}
+
+/// The video counterpart of `depth_chunk_callback`
+extern "C" fn video_chunk_callback(
+    buffer: *mut os::raw::c_void,
+    pkt_data: *mut os::raw::c_void,
+    pkt_num: os::raw::c_int,
+    datalen: os::raw::c_int,
+    user_data: *mut os::raw::c_void,
+) {
+    unsafe {
+        let device = &*(user_data as *const DeviceInner);
+        let frame_len = packed_video_bytes(device.raw());
+        dispatch_chunk(
+            &device.handlers.video_chunk,
+            buffer,
+            frame_len,
+            VIDEO_PACKET_SIZE,
+            pkt_data,
+            pkt_num,
+            datalen,
+        );
+    }
+}
+
+/// The video counterpart of `depth_callback`
+extern "C" fn video_callback(
+    dev: *mut ffi::freenect_device,
+    data: *mut std::os::raw::c_void,
+    timestamp: u32,
+) {
+    unsafe {
+        let mode = ffi::freenect_get_current_video_mode(dev);
+        let frame = VideoFrameRef {
+            data: slice::from_raw_parts(data as *const u8, mode.bytes as usize),
+            timestamp,
+            width: mode.width as u32,
+            height: mode.height as u32,
+        };
+        let device = &*(ffi::freenect_get_user(dev) as *const DeviceInner);
+        device.callbacks.fetch_add(1, Ordering::SeqCst);
+        if let Some(handler) = device.handlers.video.lock().unwrap().as_mut() {
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| handler(&frame)));
+        }
+    }
+}
+
+impl Drop for FreenectDepthStream {
+    fn drop(&mut self) {
+        // Release a callback which waits for space in a blocking channel
+        self.receiver.close();
+        self.device.inner.stop_depth();
+    }
+}
+
+/// FreenectVideoStream should be used for fetching rgb data from Kinect.
+///
+/// The stream keeps its device open and can be moved to other threads.
+/// # Examples
+/// ```rust,ignore
+/// let vstream = device.video_stream().unwrap();
+/// if let Ok(frame) = vstream.receiver.recv() {
+///  // Fetch rgb value for position x,y
+///  let idx = 3 * (y * frame.width + x) as usize;
+///  let (r, g, b) = (frame.data[idx], frame.data[idx + 1], frame.data[idx + 2]);
+/// //...
+/// }
+/// ```
+pub struct FreenectVideoStream {
+    device: FreenectDevice,
+    pub receiver: FrameReceiver<VideoFrame>,
+}
+impl FreenectVideoStream {
+    fn new(device: FreenectDevice, policy: DeliveryPolicy) -> Result<FreenectVideoStream> {
+        let (sender, receiver) = frame_channel(policy)?;
+        device.inner.start_video(
+            Box::new(move |frame: &VideoFrameRef| {
+                // An error only means that the stream is about to be dropped
+                let _ = sender.send(frame.to_owned());
+            }),
+            ptr::null_mut(),
+        )?;
+        Ok(FreenectVideoStream { device, receiver })
+    }
+
+    /// Returns the device this stream belongs to
+    pub fn device(&self) -> &FreenectDevice {
+        &self.device
+    }
+}
+impl Drop for FreenectVideoStream {
+    fn drop(&mut self) {
+        // Release a callback which waits for space in a blocking channel
+        self.receiver.close();
+        self.device.inner.stop_video();
+    }
+}
+
+/// Delivers depth frames written into caller-owned buffers,
+/// created by [`depth_buffer_stream()`](struct.FreenectDevice.html#method.depth_buffer_stream).
+pub struct FreenectDepthBufferStream<B: FrameBuffer> {
+    device: FreenectDevice,
+    pub receiver: FrameReceiver<FilledBuffer<B>>,
+}
+
+impl<B: FrameBuffer> FreenectDepthBufferStream<B> {
+    fn new(
+        device: FreenectDevice,
+        mut ring: BufferRing<B>,
+        policy: DeliveryPolicy,
+    ) -> Result<FreenectDepthBufferStream<B>> {
+        let bytes = unsafe { ffi::freenect_get_current_depth_mode(device.inner.raw()).bytes };
+        ring.require(bytes.max(0) as usize, 2)?;
+        let (sender, receiver) = frame_channel(policy)?;
+        let first = ring.current().as_mut_ptr() as *mut os::raw::c_void;
+        let weak = Arc::downgrade(&device.inner);
+        device.inner.start_depth(
+            Box::new(move |frame: &DepthFrameRef| {
+                let inner = match weak.upgrade() {
+                    Some(inner) => inner,
+                    None => return,
+                };
+                // Without a free buffer, the next frame overwrites the current one
+                if let Some(filled) = ring.rotate(frame.timestamp, frame.width, frame.height) {
+                    let next = ring.current().as_mut_ptr() as *mut os::raw::c_void;
+                    unsafe {
+                        ffi::freenect_set_depth_buffer(inner.raw(), next);
+                    }
+                    inner.handlers.depth_buffer.store(next, Ordering::SeqCst);
+                    // An error only means that the stream is about to be dropped
+                    let _ = sender.send(filled);
+                }
+            }),
+            first,
+        )?;
+        Ok(FreenectDepthBufferStream { device, receiver })
+    }
+
+    /// Returns the device this stream belongs to
+    pub fn device(&self) -> &FreenectDevice {
+        &self.device
+    }
+}
+
+impl<B: FrameBuffer> Drop for FreenectDepthBufferStream<B> {
+    fn drop(&mut self) {
+        // Release a callback which waits for space in a blocking channel
+        self.receiver.close();
+        // libfreenect gets its own buffer back before the ring is dropped together with the handler
+        self.device.inner.stop_depth();
+    }
+}
+
+/// Delivers video frames written into caller-owned buffers,
+/// created by [`video_buffer_stream()`](struct.FreenectDevice.html#method.video_buffer_stream).
+pub struct FreenectVideoBufferStream<B: FrameBuffer> {
+    device: FreenectDevice,
+    pub receiver: FrameReceiver<FilledBuffer<B>>,
+}
+
+impl<B: FrameBuffer> FreenectVideoBufferStream<B> {
+    fn new(
+        device: FreenectDevice,
+        mut ring: BufferRing<B>,
+        policy: DeliveryPolicy,
+    ) -> Result<FreenectVideoBufferStream<B>> {
+        let bytes = unsafe { ffi::freenect_get_current_video_mode(device.inner.raw()).bytes };
+        ring.require(bytes.max(0) as usize, 1)?;
+        let (sender, receiver) = frame_channel(policy)?;
+        let first = ring.current().as_mut_ptr() as *mut os::raw::c_void;
+        let weak = Arc::downgrade(&device.inner);
+        device.inner.start_video(
+            Box::new(move |frame: &VideoFrameRef| {
+                let inner = match weak.upgrade() {
+                    Some(inner) => inner,
+                    None => return,
+                };
+                // Without a free buffer, the next frame overwrites the current one
+                if let Some(filled) = ring.rotate(frame.timestamp, frame.width, frame.height) {
+                    let next = ring.current().as_mut_ptr() as *mut os::raw::c_void;
+                    unsafe {
+                        ffi::freenect_set_video_buffer(inner.raw(), next);
+                    }
+                    inner.handlers.video_buffer.store(next, Ordering::SeqCst);
+                    // An error only means that the stream is about to be dropped
+                    let _ = sender.send(filled);
+                }
+            }),
+            first,
+        )?;
+        Ok(FreenectVideoBufferStream { device, receiver })
+    }
+
+    /// Returns the device this stream belongs to
+    pub fn device(&self) -> &FreenectDevice {
+        &self.device
+    }
+}
+
+impl<B: FrameBuffer> Drop for FreenectVideoBufferStream<B> {
+    fn drop(&mut self) {
+        // Release a callback which waits for space in a blocking channel
+        self.receiver.close();
+        // libfreenect gets its own buffer back before the ring is dropped together with the handler
+        self.device.inner.stop_video();
+    }
+}
+
+/// FreenectAudioStream should be used for fetching samples from Kinect's microphone array.
+///
+/// The stream keeps its device open and can be moved to other threads.
+/// # Examples
+/// ```rust,no_run
+/// use freenectrs::freenect::FreenectContext;
+///
+/// let ctx = FreenectContext::init().unwrap().setup_audio();
+/// let device = ctx.open_device(0).unwrap();
+/// let astream = device.audio_stream().unwrap();
+/// ctx.spawn_process_thread().unwrap();
+/// while let Ok(frame) = astream.receiver.recv() {
+///     // ... handle frame.num_samples samples of each channel in frame.mics
+/// }
+/// ```
+pub struct FreenectAudioStream {
+    device: FreenectDevice,
+    pub receiver: FrameReceiver<AudioFrame>,
+}
+
+impl FreenectAudioStream {
+    fn new(device: FreenectDevice, policy: DeliveryPolicy) -> Result<FreenectAudioStream> {
+        let (sender, receiver) = frame_channel(policy)?;
+        device.inner.start_audio(audio_sender(sender))?;
+        Ok(FreenectAudioStream { device, receiver })
+    }
+
+    /// Returns the device this stream belongs to
+    pub fn device(&self) -> &FreenectDevice {
+        &self.device
+    }
+}
+
+/// The handler of an audio stream, copies every frame into the stream's channel
+fn audio_sender(sender: FrameSender<AudioFrame>) -> AudioHandler {
+    Box::new(move |frame: &AudioFrameRef| {
+        // An error only means that the stream is about to be dropped
+        let _ = sender.send(frame.to_owned());
+    })
+}
+
+impl Drop for FreenectAudioStream {
+    fn drop(&mut self) {
+        // Release a callback which waits for space in a blocking channel
+        self.receiver.close();
+        self.device.inner.stop_audio();
+    }
+}
+
+/// The audio counterpart of `depth_callback`
+extern "C" fn audio_callback(
+    dev: *mut ffi::freenect_device,
+    num_samples: os::raw::c_int,
+    mic1: *mut i32,
+    mic2: *mut i32,
+    mic3: *mut i32,
+    mic4: *mut i32,
+    cancelled: *mut i16,
+    // libfreenect doesn't document this buffer, so it isn't handed to the handler
+    _unknown: *mut os::raw::c_void,
+) {
+    unsafe {
+        let device = &*(ffi::freenect_get_user(dev) as *const DeviceInner);
+        device.callbacks.fetch_add(1, Ordering::SeqCst);
+        dispatch_audio(
+            &device.handlers,
+            num_samples,
+            [mic1, mic2, mic3, mic4],
+            cancelled,
+        );
+    }
+}
+
+/// Hands the buffers of `audio_callback` to the audio handler. Callbacks without samples are skipped.
+unsafe fn dispatch_audio(
+    handlers: &Handlers,
+    num_samples: os::raw::c_int,
+    mics: [*const i32; 4],
+    cancelled: *const i16,
+) {
+    if num_samples <= 0 || cancelled.is_null() || mics.iter().any(|mic| mic.is_null()) {
+        return;
+    }
+    let frame = AudioFrameRef::from_raw(num_samples as usize, mics, cancelled);
+    if let Some(handler) = handlers.audio.lock().unwrap().as_mut() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| handler(&frame)));
+    }
+}
+
+/// Keeps a handler registered with [`on_depth()`][on_depth] active. Dropping it stops the depth stream.
+///
+/// [on_depth]: struct.FreenectDevice.html#method.on_depth
+pub struct FreenectDepthCallback {
+    device: FreenectDevice,
+}
+
+impl Drop for FreenectDepthCallback {
+    fn drop(&mut self) {
+        self.device.inner.stop_depth();
+    }
+}
+
+/// Keeps a handler registered with [`on_video()`][on_video] active. Dropping it stops the video stream.
+///
+/// [on_video]: struct.FreenectDevice.html#method.on_video
+pub struct FreenectVideoCallback {
+    device: FreenectDevice,
+}
+
+impl Drop for FreenectVideoCallback {
+    fn drop(&mut self) {
+        self.device.inner.stop_video();
+    }
+}
+
+/// Keeps a handler registered with [`on_audio()`][on_audio] active. Dropping it stops the audio stream.
+///
+/// [on_audio]: struct.FreenectDevice.html#method.on_audio
+pub struct FreenectAudioCallback {
+    device: FreenectDevice,
+}
+
+impl Drop for FreenectAudioCallback {
+    fn drop(&mut self) {
+        self.device.inner.stop_audio();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::TryRecvError;
+
+    /// Calls the audio handler of `handlers` the way `audio_callback` does with the given buffers
+    fn dispatch(handlers: &Handlers, num_samples: i32, mics: &[Vec<i32>; 4], cancelled: &[i16]) {
+        unsafe {
+            dispatch_audio(
+                handlers,
+                num_samples,
+                [
+                    mics[0].as_ptr(),
+                    mics[1].as_ptr(),
+                    mics[2].as_ptr(),
+                    mics[3].as_ptr(),
+                ],
+                cancelled.as_ptr(),
+            );
+        }
+    }
+
+    fn audio_stream_handlers() -> (Handlers, FrameReceiver<AudioFrame>) {
+        let (sender, receiver) = frame_channel(DeliveryPolicy::Block { capacity: 4 }).unwrap();
+        let handlers = Handlers::default();
+        *handlers.audio.lock().unwrap() = Some(audio_sender(sender));
+        (handlers, receiver)
+    }
+
+    #[test]
+    fn audio_callback_delivers_the_reported_number_of_samples() {
+        let (handlers, receiver) = audio_stream_handlers();
+        // libfreenect's buffers can be longer than the samples of the current callback
+        let mics = [
+            vec![1, 2, 3, 0],
+            vec![4, 5, 6, 0],
+            vec![7, 8, 9, 0],
+            vec![10, 11, 12, 0],
+        ];
+        let cancelled = [-1, 0, 1, 0];
+        dispatch(&handlers, 3, &mics, &cancelled);
+        let frame = receiver.try_recv().unwrap();
+        assert_eq!(frame.num_samples, 3);
+        assert_eq!(frame.mics[0], vec![1, 2, 3]);
+        assert_eq!(frame.mics[3], vec![10, 11, 12]);
+        assert_eq!(frame.cancelled, vec![-1, 0, 1]);
+        assert!(matches!(receiver.try_recv(), Err(TryRecvError::Empty)));
+    }
+
+    #[test]
+    fn audio_callback_skips_callbacks_without_samples() {
+        let (handlers, receiver) = audio_stream_handlers();
+        let mics = [vec![1], vec![2], vec![3], vec![4]];
+        dispatch(&handlers, 0, &mics, &[0]);
+        dispatch(&handlers, -1, &mics, &[0]);
+        unsafe {
+            dispatch_audio(&handlers, 1, [ptr::null(); 4], ptr::null());
+        }
+        assert!(matches!(receiver.try_recv(), Err(TryRecvError::Empty)));
+    }
+
+    #[test]
+    fn audio_callback_survives_a_panicking_handler() {
+        let handlers = Handlers::default();
+        *handlers.audio.lock().unwrap() = Some(Box::new(|_: &AudioFrameRef| panic!("handler")));
+        let mics = [vec![1], vec![2], vec![3], vec![4]];
+        dispatch(&handlers, 1, &mics, &[0]);
+        // The handler stays installed for the next callback
+        assert!(handlers.audio.lock().unwrap().is_some());
     }
 }
@@ -3,7 +3,8 @@
 #![allow(dead_code,
          non_camel_case_types,
          non_upper_case_globals,
-         non_snake_case)]
+         non_snake_case,
+         clippy::useless_transmute)]
 pub type int8_t = i8;
 pub type int16_t = i16;
 pub type int32_t = i32;
@@ -550,6 +551,18 @@ pub type freenect_chunk_cb = ::std::option::Option<
         user_data: *mut ::std::os::raw::c_void,
     ),
 >;
+pub type freenect_audio_in_cb = ::std::option::Option<
+    unsafe extern "C" fn(
+        dev: *mut freenect_device,
+        num_samples: ::std::os::raw::c_int,
+        mic1: *mut int32_t,
+        mic2: *mut int32_t,
+        mic3: *mut int32_t,
+        mic4: *mut int32_t,
+        cancelled: *mut int16_t,
+        unknown: *mut ::std::os::raw::c_void,
+    ),
+>;
 #[link(name = "freenect", kind = "dylib")]
 extern "C" {
     pub static mut tzname: [*mut ::std::os::raw::c_char; 0usize];
@@ -647,8 +660,8 @@ extern "C" {
     ) -> ::std::os::raw::c_int;
     pub fn freenect_free_device_attributes(attribute_list: *mut freenect_device_attributes);
     pub fn freenect_supported_subdevices() -> ::std::os::raw::c_int;
-    pub fn freenect_select_subdevices(ctx: *mut freenect_context, subdevs: freenect_device_flags);
-    pub fn freenect_enabled_subdevices(ctx: *mut freenect_context) -> freenect_device_flags;
+    pub fn freenect_select_subdevices(ctx: *mut freenect_context, subdevs: ::std::os::raw::c_int);
+    pub fn freenect_enabled_subdevices(ctx: *mut freenect_context) -> ::std::os::raw::c_int;
     pub fn freenect_open_device(
         ctx: *mut freenect_context,
         dev: *mut *mut freenect_device,
@@ -737,4 +750,10 @@ extern "C" {
         fw_ptr: *mut ::std::os::raw::c_uchar,
         num_bytes: ::std::os::raw::c_uint,
     );
+    pub fn freenect_set_audio_in_callback(
+        dev: *mut freenect_device,
+        callback: freenect_audio_in_cb,
+    );
+    pub fn freenect_start_audio(dev: *mut freenect_device) -> ::std::os::raw::c_int;
+    pub fn freenect_stop_audio(dev: *mut freenect_device) -> ::std::os::raw::c_int;
 }
@@ -0,0 +1,290 @@
+//! Points, poses and gravity as `nalgebra` types, enabled by the `nalgebra` feature.
+//!
+//! Points are given in meters in the frame of the depth camera: x points right, y down and z
+//! forward along the optical axis.
+use crate::freenect::{
+    Blob, DepthFrame, DepthFrameRef, FreenectDepthFormat, FreenectError, FreenectTiltState, Result,
+};
+use nalgebra::{Isometry3, Point2, Point3, Translation3, UnitQuaternion, Vector3};
+
+// Width and height the constants of CameraIntrinsics refer to
+const CALIBRATED_WIDTH: f32 = 640.0;
+const CALIBRATED_HEIGHT: f32 = 480.0;
+
+/// The pinhole model of a camera, in pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraIntrinsics {
+    /// Focal length along x
+    pub fx: f32,
+    /// Focal length along y
+    pub fy: f32,
+    /// Principal point
+    pub cx: f32,
+    pub cy: f32,
+}
+
+impl CameraIntrinsics {
+    /// Typical values of the Kinect depth camera at 640×480, as calibrated by Nicolas Burrus
+    pub const KINECT_DEPTH: CameraIntrinsics = CameraIntrinsics {
+        fx: 594.214,
+        fy: 591.040,
+        cx: 339.308,
+        cy: 242.739,
+    };
+
+    /// Typical values of the Kinect rgb camera at 640×480, as calibrated by Nicolas Burrus
+    pub const KINECT_RGB: CameraIntrinsics = CameraIntrinsics {
+        fx: 529.215,
+        fy: 525.563,
+        cx: 328.942,
+        cy: 267.481,
+    };
+
+    /// Scales intrinsics of a 640×480 camera to frames of another resolution
+    pub fn scaled(&self, width: u32, height: u32) -> CameraIntrinsics {
+        let (sx, sy) = (
+            width as f32 / CALIBRATED_WIDTH,
+            height as f32 / CALIBRATED_HEIGHT,
+        );
+        CameraIntrinsics {
+            fx: self.fx * sx,
+            fy: self.fy * sy,
+            cx: self.cx * sx,
+            cy: self.cy * sy,
+        }
+    }
+
+    /// Returns the point at pixel `(x, y)` which is `z` meters away along the optical axis
+    pub fn unproject(&self, x: f32, y: f32, z: f32) -> Point3<f32> {
+        Point3::new((x - self.cx) * z / self.fx, (y - self.cy) * z / self.fy, z)
+    }
+
+    /// Returns the pixel a point is seen at, or `None` if it is behind the camera
+    pub fn project(&self, point: &Point3<f32>) -> Option<Point2<f32>> {
+        if point.z <= 0.0 {
+            return None;
+        }
+        Some(Point2::new(
+            point.x * self.fx / point.z + self.cx,
+            point.y * self.fy / point.z + self.cy,
+        ))
+    }
+}
+
+/// The approximate pose of the rgb camera relative to the depth camera of a Kinect: 2.5 cm to the
+/// right, without rotation. Transforms points of the depth camera into the frame of the rgb camera
+/// when inverted.
+pub fn kinect_depth_to_rgb() -> Isometry3<f32> {
+    Isometry3::from_parts(
+        Translation3::new(0.025, 0.0, 0.0),
+        UnitQuaternion::identity(),
+    )
+}
+
+/// Returns a function converting a depth value of `format` to meters, or `None` for invalid values.
+/// Fails for formats without a known conversion.
+fn meters(format: FreenectDepthFormat) -> Result<fn(u16) -> Option<f32>> {
+    match format {
+        FreenectDepthFormat::MM | FreenectDepthFormat::Registered => Ok(|value| {
+            if value == 0 {
+                None
+            } else {
+                Some(value as f32 / 1000.0)
+            }
+        }),
+        // The disparity model calibrated by Nicolas Burrus
+        FreenectDepthFormat::Bit11 => Ok(|value| {
+            let inverse = value as f32 * -0.003_071_101_6 + 3.330_949_5;
+            if value == 0 || value >= 2047 || inverse <= 0.0 {
+                None
+            } else {
+                Some(1.0 / inverse)
+            }
+        }),
+        format => Err(FreenectError::new(format!(
+            "Depth format {} can't be converted to points",
+            format
+        ))),
+    }
+}
+
+impl<'a> DepthFrameRef<'a> {
+    /// Converts the valid pixels of a frame of `format` to points in the camera frame, in meters.
+    /// `intrinsics` must match the resolution of the frame, see [`CameraIntrinsics::scaled()`][scaled].
+    /// Fails for formats other than millimeters, registered and 11-bit depth.
+    ///
+    /// [scaled]: struct.CameraIntrinsics.html#method.scaled
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::{CameraIntrinsics, DepthFrameRef, FreenectDepthFormat};
+    ///
+    /// let intrinsics = CameraIntrinsics { fx: 2.0, fy: 2.0, cx: 1.0, cy: 0.0 };
+    /// let data = [1000, 0, 2000];
+    /// let frame = DepthFrameRef { data: &data, timestamp: 0, width: 3, height: 1 };
+    /// let points = frame.to_points(&intrinsics, FreenectDepthFormat::MM).unwrap();
+    /// // The pixel without reading is skipped
+    /// assert_eq!(points.len(), 2);
+    /// assert_eq!((points[0].x, points[0].z), (-0.5, 1.0));
+    /// assert_eq!((points[1].x, points[1].z), (1.0, 2.0));
+    /// ```
+    pub fn to_points(
+        &self,
+        intrinsics: &CameraIntrinsics,
+        format: FreenectDepthFormat,
+    ) -> Result<Vec<Point3<f32>>> {
+        let meters = meters(format)?;
+        let width = self.width.max(1) as usize;
+        Ok(self
+            .data
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &value)| {
+                let z = meters(value)?;
+                Some(intrinsics.unproject((i % width) as f32, (i / width) as f32, z))
+            })
+            .collect())
+    }
+
+    /// Converts every pixel to a point like [`to_points()`](#method.to_points), row by row, with
+    /// `None` for pixels without a reading. The points keep the neighbourhood of their pixels,
+    /// for example to compute [`normals()`](#method.normals).
+    pub fn to_organized_points(
+        &self,
+        intrinsics: &CameraIntrinsics,
+        format: FreenectDepthFormat,
+    ) -> Result<Vec<Option<Point3<f32>>>> {
+        let meters = meters(format)?;
+        let width = self.width.max(1) as usize;
+        Ok(self
+            .data
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| {
+                let z = meters(value)?;
+                Some(intrinsics.unproject((i % width) as f32, (i / width) as f32, z))
+            })
+            .collect())
+    }
+
+    /// Converts the valid pixels to points like [`to_points()`](#method.to_points) and moves them
+    /// by `pose`, the pose of the camera within another frame such as a robot's base.
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::{CameraIntrinsics, DepthFrameRef, FreenectDepthFormat};
+    /// use nalgebra::{Isometry3, Vector3};
+    ///
+    /// let intrinsics = CameraIntrinsics { fx: 1.0, fy: 1.0, cx: 0.0, cy: 0.0 };
+    /// let frame = DepthFrameRef { data: &[1000], timestamp: 0, width: 1, height: 1 };
+    /// // The camera is mounted 0.5 m above the base, looking along its x axis
+    /// let pose = Isometry3::new(Vector3::new(0.0, 0.0, 0.5), Vector3::y() * std::f32::consts::FRAC_PI_2);
+    /// let points = frame.to_points_in(&intrinsics, FreenectDepthFormat::MM, &pose).unwrap();
+    /// assert!((points[0] - nalgebra::Point3::new(1.0, 0.0, 0.5)).norm() < 1e-6);
+    /// ```
+    pub fn to_points_in(
+        &self,
+        intrinsics: &CameraIntrinsics,
+        format: FreenectDepthFormat,
+        pose: &Isometry3<f32>,
+    ) -> Result<Vec<Point3<f32>>> {
+        let mut points = self.to_points(intrinsics, format)?;
+        for point in &mut points {
+            *point = pose * *point;
+        }
+        Ok(points)
+    }
+}
+
+impl DepthFrame {
+    /// Converts the valid pixels to points, see [`DepthFrameRef::to_points()`](struct.DepthFrameRef.html#method.to_points)
+    pub fn to_points(
+        &self,
+        intrinsics: &CameraIntrinsics,
+        format: FreenectDepthFormat,
+    ) -> Result<Vec<Point3<f32>>> {
+        self.as_frame_ref().to_points(intrinsics, format)
+    }
+
+    /// Converts every pixel to a point, see
+    /// [`DepthFrameRef::to_organized_points()`](struct.DepthFrameRef.html#method.to_organized_points)
+    pub fn to_organized_points(
+        &self,
+        intrinsics: &CameraIntrinsics,
+        format: FreenectDepthFormat,
+    ) -> Result<Vec<Option<Point3<f32>>>> {
+        self.as_frame_ref().to_organized_points(intrinsics, format)
+    }
+
+    /// Converts the valid pixels to points moved by `pose`, see
+    /// [`DepthFrameRef::to_points_in()`](struct.DepthFrameRef.html#method.to_points_in)
+    pub fn to_points_in(
+        &self,
+        intrinsics: &CameraIntrinsics,
+        format: FreenectDepthFormat,
+        pose: &Isometry3<f32>,
+    ) -> Result<Vec<Point3<f32>>> {
+        self.as_frame_ref().to_points_in(intrinsics, format, pose)
+    }
+}
+
+impl Blob {
+    /// Returns the position of the blob in the camera frame, in meters: its centroid seen at its
+    /// mean depth. Fails for formats [`DepthFrameRef::to_points()`](struct.DepthFrameRef.html#method.to_points)
+    /// can't convert.
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::{BackgroundConfig, BackgroundModel, CameraIntrinsics, DepthFrameRef, FreenectDepthFormat};
+    ///
+    /// let config = BackgroundConfig { learning_frames: 1, min_blob_pixels: 1, ..BackgroundConfig::default() };
+    /// let mut model = BackgroundModel::new(config);
+    /// model.update(&DepthFrameRef { data: &[3000; 4], timestamp: 0, width: 2, height: 2 }).unwrap();
+    /// let data = [3000, 3000, 3000, 1500];
+    /// let frame = DepthFrameRef { data: &data, timestamp: 0, width: 2, height: 2 };
+    /// let blobs = model.update(&frame).unwrap().unwrap().blobs;
+    ///
+    /// let intrinsics = CameraIntrinsics { fx: 1.0, fy: 1.0, cx: 0.0, cy: 0.0 };
+    /// let position = blobs[0].position(&intrinsics, FreenectDepthFormat::MM).unwrap();
+    /// assert_eq!((position.x, position.y, position.z), (1.5, 1.5, 1.5));
+    /// ```
+    pub fn position(
+        &self,
+        intrinsics: &CameraIntrinsics,
+        format: FreenectDepthFormat,
+    ) -> Result<Point3<f32>> {
+        let z = meters(format)?(self.depth.round() as u16)
+            .ok_or_else(|| FreenectError::new("Blob has no valid depth"))?;
+        Ok(intrinsics.unproject(self.centroid.0, self.centroid.1, z))
+    }
+}
+
+/// Returns roll and pitch in degrees from the acceleration measured at rest, which points against gravity.
+///
+/// Roll is the angle between the x axis and the horizontal plane, pitch that of the z axis.
+/// Both are 0 when the device stands level, with gravity along its y axis.
+/// # Examples
+/// ```rust
+/// use freenectrs::freenect::roll_pitch;
+/// use nalgebra::Vector3;
+///
+/// assert_eq!(roll_pitch(&Vector3::new(0.0, 9.81, 0.0)), (0.0, 0.0));
+/// let (roll, pitch) = roll_pitch(&Vector3::new(0.0, 1.0, 1.0));
+/// assert_eq!(roll, 0.0);
+/// assert!((pitch - 45.0).abs() < 1e-9);
+/// ```
+pub fn roll_pitch(acceleration: &Vector3<f64>) -> (f64, f64) {
+    let (x, y, z) = (acceleration.x, acceleration.y, acceleration.z);
+    let roll = x.atan2((y * y + z * z).sqrt());
+    let pitch = z.atan2((x * x + y * y).sqrt());
+    (roll.to_degrees(), pitch.to_degrees())
+}
+
+impl FreenectTiltState {
+    /// Returns the accelerometer reading in m/s²
+    pub fn acceleration(&self) -> Vector3<f64> {
+        Vector3::from(self.accelerometer)
+    }
+
+    /// Returns roll and pitch of the device in degrees, see [`roll_pitch()`](fn.roll_pitch.html)
+    pub fn roll_pitch(&self) -> (f64, f64) {
+        roll_pitch(&self.acceleration())
+    }
+}
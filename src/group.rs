@@ -0,0 +1,258 @@
+//! Handling several devices as one unit.
+use crate::channel::{frame_channel, FrameSender};
+use crate::freenect::{
+    DeliveryPolicy, DepthFrame, FrameReceiver, FreenectContext, FreenectDepthCallback,
+    FreenectDepthFormat, FreenectDevice, FreenectFlag, FreenectResolution, FreenectVideoCallback,
+    FreenectVideoFormat, Result, VideoFrame,
+};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Selects a device to open
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceSelector {
+    /// The device with this number, like in [`open_device()`](struct.FreenectContext.html#method.open_device)
+    Index(u32),
+    /// The device with this camera serial
+    Serial(String),
+}
+
+/// The settings a [`DeviceGroup`](struct.DeviceGroup.html) applies to each of its devices.
+/// `None` leaves the current setting untouched.
+#[derive(Clone, Debug, Default)]
+pub struct GroupConfig {
+    pub depth_mode: Option<(FreenectResolution, FreenectDepthFormat)>,
+    pub video_mode: Option<(FreenectResolution, FreenectVideoFormat)>,
+    pub flags: Vec<(FreenectFlag, bool)>,
+    pub tilt_degree: Option<f64>,
+}
+
+/// A depth or video frame
+#[derive(Clone, Debug)]
+pub enum Frame {
+    Depth(DepthFrame),
+    Video(VideoFrame),
+}
+
+/// A frame together with the device it comes from
+#[derive(Clone, Debug)]
+pub struct TaggedFrame {
+    /// The position of the device within the group
+    pub device: usize,
+    pub frame: Frame,
+}
+
+/// Statistics for one device of a group
+#[derive(Clone, Debug, Default)]
+pub struct DeviceStats {
+    pub serial: Option<String>,
+    /// Number of depth frames received from the device
+    pub depth_frames: u64,
+    /// Number of video frames received from the device
+    pub video_frames: u64,
+    /// Number of frames dropped by the delivery policy
+    pub dropped_frames: u64,
+    pub last_depth_timestamp: Option<u32>,
+    pub last_video_timestamp: Option<u32>,
+    started: Option<Instant>,
+}
+
+impl DeviceStats {
+    /// Average number of depth frames per second since the streams were started
+    pub fn depth_fps(&self) -> f64 {
+        self.per_second(self.depth_frames)
+    }
+
+    /// Average number of video frames per second since the streams were started
+    pub fn video_fps(&self) -> f64 {
+        self.per_second(self.video_frames)
+    }
+
+    fn per_second(&self, frames: u64) -> f64 {
+        match self.started {
+            Some(started) if started.elapsed().as_secs_f64() > 0.0 => {
+                frames as f64 / started.elapsed().as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// Opens several devices, configures them alike and streams from all of them at once.
+/// # Examples
+/// ```rust,no_run
+/// use freenectrs::freenect::{DeliveryPolicy, DeviceGroup, DeviceSelector, Frame, FreenectContext,
+///     FreenectDepthFormat, FreenectResolution, GroupConfig};
+///
+/// let ctx = FreenectContext::init_with_video().unwrap();
+/// let group = DeviceGroup::open(&ctx, &[DeviceSelector::Index(0), DeviceSelector::Index(1)]).unwrap();
+/// group.configure(&GroupConfig {
+///     depth_mode: Some((FreenectResolution::Medium, FreenectDepthFormat::MM)),
+///     ..GroupConfig::default()
+/// }).unwrap();
+/// let stream = group.start(true, false, DeliveryPolicy::default()).unwrap();
+/// while let Ok(tagged) = stream.receiver.recv() {
+///     if let Frame::Depth(frame) = tagged.frame {
+///         // ... handle depth frame of device tagged.device
+///     }
+/// }
+/// ```
+pub struct DeviceGroup {
+    ctx: FreenectContext,
+    devices: Vec<FreenectDevice>,
+    stats: Arc<Mutex<Vec<DeviceStats>>>,
+}
+
+impl DeviceGroup {
+    /// Opens the selected devices. The order of `selectors` determines the position of each device.
+    pub fn open(ctx: &FreenectContext, selectors: &[DeviceSelector]) -> Result<DeviceGroup> {
+        let devices = selectors
+            .iter()
+            .map(|selector| match *selector {
+                DeviceSelector::Index(nr) => ctx.open_device(nr),
+                DeviceSelector::Serial(ref serial) => ctx.open_device_by_serial(serial),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(DeviceGroup::from_devices(ctx, devices))
+    }
+
+    /// Opens all available devices
+    pub fn open_all(ctx: &FreenectContext) -> Result<DeviceGroup> {
+        let selectors: Vec<_> = (0..ctx.num_devices()?).map(DeviceSelector::Index).collect();
+        DeviceGroup::open(ctx, &selectors)
+    }
+
+    /// Creates a group of already opened devices
+    pub fn from_devices(ctx: &FreenectContext, devices: Vec<FreenectDevice>) -> DeviceGroup {
+        let stats = devices
+            .iter()
+            .map(|device| DeviceStats {
+                serial: device.serial().map(String::from),
+                ..DeviceStats::default()
+            })
+            .collect();
+        DeviceGroup {
+            ctx: ctx.clone(),
+            devices,
+            stats: Arc::new(Mutex::new(stats)),
+        }
+    }
+
+    /// Returns the devices in the order of their position
+    pub fn devices(&self) -> &[FreenectDevice] {
+        &self.devices
+    }
+
+    /// Applies `config` to every device
+    pub fn configure(&self, config: &GroupConfig) -> Result<()> {
+        for device in &self.devices {
+            if let Some((resol, format)) = config.depth_mode {
+                device.set_depth_mode(resol, format)?;
+            }
+            if let Some((resol, format)) = config.video_mode {
+                device.set_video_mode(resol, format)?;
+            }
+            for &(flag, enabled) in &config.flags {
+                device.set_flag(flag, enabled)?;
+            }
+            if let Some(degree) = config.tilt_degree {
+                device.set_tilt_degree(degree)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts the depth and/or video streams of all devices and merges their frames into one stream,
+    /// which delivers frames according to `policy`. The process thread is spawned if it isn't running yet.
+    /// If a stream can't be started, the ones already started are stopped again.
+    pub fn start(&self, depth: bool, video: bool, policy: DeliveryPolicy) -> Result<GroupStream> {
+        let (sender, receiver) = frame_channel(policy)?;
+        let sender = Arc::new(sender);
+        {
+            let mut stats = self.stats.lock().unwrap();
+            for device_stats in stats.iter_mut() {
+                *device_stats = DeviceStats {
+                    serial: device_stats.serial.take(),
+                    started: Some(Instant::now()),
+                    ..DeviceStats::default()
+                };
+            }
+        }
+        let mut stream = GroupStream {
+            receiver,
+            depth_callbacks: Vec::new(),
+            video_callbacks: Vec::new(),
+        };
+        for (nr, device) in self.devices.iter().enumerate() {
+            if depth {
+                let sender = sender.clone();
+                let stats = self.stats.clone();
+                stream.depth_callbacks.push(device.on_depth(move |frame| {
+                    if let Some(device_stats) = stats.lock().unwrap().get_mut(nr) {
+                        device_stats.depth_frames += 1;
+                        device_stats.last_depth_timestamp = Some(frame.timestamp);
+                    }
+                    let tagged = TaggedFrame {
+                        device: nr,
+                        frame: Frame::Depth(frame.to_owned()),
+                    };
+                    deliver(&sender, &stats, tagged);
+                })?);
+            }
+            if video {
+                let sender = sender.clone();
+                let stats = self.stats.clone();
+                stream.video_callbacks.push(device.on_video(move |frame| {
+                    if let Some(device_stats) = stats.lock().unwrap().get_mut(nr) {
+                        device_stats.video_frames += 1;
+                        device_stats.last_video_timestamp = Some(frame.timestamp);
+                    }
+                    let tagged = TaggedFrame {
+                        device: nr,
+                        frame: Frame::Video(frame.to_owned()),
+                    };
+                    deliver(&sender, &stats, tagged);
+                })?);
+            }
+        }
+        if !self.ctx.is_process_thread_running() {
+            self.ctx.spawn_process_thread()?;
+        }
+        Ok(stream)
+    }
+
+    /// Returns the statistics of each device, in the order of their position
+    pub fn stats(&self) -> Vec<DeviceStats> {
+        self.stats.lock().unwrap().clone()
+    }
+}
+
+/// Sends `tagged` and counts a frame the policy dropped for the device it belongs to
+fn deliver(
+    sender: &FrameSender<TaggedFrame>,
+    stats: &Mutex<Vec<DeviceStats>>,
+    tagged: TaggedFrame,
+) {
+    // An error only means that the stream is about to be dropped
+    if let Ok(Some(dropped)) = sender.send(tagged) {
+        if let Some(device_stats) = stats.lock().unwrap().get_mut(dropped.device) {
+            device_stats.dropped_frames += 1;
+        }
+    }
+}
+
+/// The merged frames of a [`DeviceGroup`](struct.DeviceGroup.html). Dropping it stops all streams of the group.
+pub struct GroupStream {
+    pub receiver: FrameReceiver<TaggedFrame>,
+    depth_callbacks: Vec<FreenectDepthCallback>,
+    video_callbacks: Vec<FreenectVideoCallback>,
+}
+
+impl Drop for GroupStream {
+    fn drop(&mut self) {
+        // Release callbacks which wait for space in a blocking channel
+        self.receiver.close();
+        self.depth_callbacks.clear();
+        self.video_callbacks.clear();
+    }
+}
@@ -0,0 +1,401 @@
+//! Detection of plugged and unplugged devices.
+use crate::freenect::{FreenectContext, FreenectDevice, FreenectError, WeakFreenectDevice};
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long the running streams of a device may go without a callback before it counts as failed
+const STALL_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A change noticed by a [`DeviceWatcher`](struct.DeviceWatcher.html). Devices are identified by their camera serial.
+#[derive(Debug)]
+pub enum DeviceEvent {
+    Connected(String),
+    Disconnected(String),
+    /// A device registered with [`DeviceWatcher::reconnect()`](struct.DeviceWatcher.html#method.reconnect)
+    /// was reopened and its settings and streams were restored
+    Reconnected(String),
+    /// Reopening a registered device failed. The watcher tries again when the device is connected
+    /// next time or fails again.
+    ReconnectFailed(String, FreenectError),
+}
+
+/// Watches for plugged and unplugged devices by polling libfreenect's device list in a background thread.
+/// The watcher keeps its context alive.
+///
+/// Devices registered with [`reconnect()`](#method.reconnect) are reopened as soon as they are
+/// connected again. Their depth and video modes, flags, tilt and running streams are restored,
+/// so existing handles and streams continue to deliver frames.
+///
+/// A registered device whose serial stays listed also counts as disconnected and connected again
+/// if it was unplugged and plugged in between two polls, or stopped working: if a transfer to it
+/// failed, its running streams delivered nothing for 3 seconds, the process thread ended by itself
+/// or its libfreenect handle changed.
+/// # Examples
+/// ```rust,no_run
+/// use freenectrs::freenect::{DeviceEvent, DeviceWatcher, FreenectContext};
+/// use std::time::Duration;
+///
+/// let ctx = FreenectContext::init_with_video().unwrap();
+/// let device = ctx.open_device(0).unwrap();
+/// let dstream = device.depth_stream().unwrap();
+/// ctx.spawn_process_thread().unwrap();
+///
+/// let watcher = DeviceWatcher::spawn(&ctx, Duration::from_secs(1));
+/// watcher.reconnect(&device).unwrap();
+/// for event in watcher.events.iter() {
+///     match event {
+///         DeviceEvent::Disconnected(serial) => eprintln!("Lost {}", serial),
+///         DeviceEvent::Reconnected(serial) => eprintln!("Recovered {}", serial),
+///         _ => (),
+///     }
+/// }
+/// ```
+pub struct DeviceWatcher {
+    pub events: Receiver<DeviceEvent>,
+    reconnecting: Arc<Mutex<Vec<WeakFreenectDevice>>>,
+    // Dropping it stops the thread
+    stop_sender: Option<Sender<()>>,
+    joiner: Option<thread::JoinHandle<()>>,
+}
+
+impl DeviceWatcher {
+    /// Spawns a thread which checks for device changes every `interval`.
+    /// Devices available at this point don't cause a `Connected` event.
+    pub fn spawn(ctx: &FreenectContext, interval: Duration) -> DeviceWatcher {
+        let (event_sender, events) = channel();
+        let (stop_sender, stop_receiver) = channel::<()>();
+        let reconnecting = Arc::new(Mutex::new(Vec::new()));
+        let ctx = ctx.clone();
+        let devices = reconnecting.clone();
+        let joiner = thread::spawn(move || {
+            let mut tracker = Tracker::new(ctx.device_serials().unwrap_or_default());
+            while let Err(RecvTimeoutError::Timeout) = stop_receiver.recv_timeout(interval) {
+                let current = match ctx.device_serials() {
+                    Ok(serials) => serials,
+                    // Try again next time
+                    Err(_) => continue,
+                };
+                let processing = if ctx.is_process_thread_running() {
+                    Processing::Running
+                } else if ctx.is_process_thread_spawned() {
+                    Processing::Failed
+                } else {
+                    Processing::Stopped
+                };
+                let registered = health(&registered_devices(&devices));
+                let (disconnected, connected) =
+                    tracker.update(current, &registered, processing, Instant::now());
+                for serial in disconnected {
+                    let _ = event_sender.send(DeviceEvent::Disconnected(serial));
+                }
+                for serial in &connected {
+                    let _ = event_sender.send(DeviceEvent::Connected(serial.clone()));
+                }
+                let to_reconnect: Vec<FreenectDevice> = registered_devices(&devices)
+                    .into_iter()
+                    .filter(|device| {
+                        device
+                            .serial()
+                            .is_some_and(|serial| connected.iter().any(|s| s == serial))
+                    })
+                    .collect();
+                if !to_reconnect.is_empty() {
+                    reconnect_all(&ctx, &to_reconnect, &event_sender);
+                    tracker.reopened(&health(&to_reconnect), Instant::now());
+                }
+            }
+        });
+        DeviceWatcher {
+            events,
+            reconnecting,
+            stop_sender: Some(stop_sender),
+            joiner: Some(joiner),
+        }
+    }
+
+    /// Reopens `device` whenever it is connected again. The watcher doesn't keep the device open.
+    ///
+    /// Fails if the serial of the device is unknown.
+    pub fn reconnect(&self, device: &FreenectDevice) -> Result<(), FreenectError> {
+        if device.serial().is_none() {
+            return Err(FreenectError::new(
+                "Cannot watch device, its serial is unknown",
+            ));
+        }
+        self.reconnecting.lock().unwrap().push(device.downgrade());
+        Ok(())
+    }
+}
+
+/// Returns the registered devices and forgets about dropped ones
+fn registered_devices(devices: &Mutex<Vec<WeakFreenectDevice>>) -> Vec<FreenectDevice> {
+    let mut devices = devices.lock().unwrap();
+    devices.retain(|weak| weak.upgrade().is_some());
+    devices.iter().filter_map(|weak| weak.upgrade()).collect()
+}
+
+fn health(devices: &[FreenectDevice]) -> Vec<(String, DeviceHealth)> {
+    devices
+        .iter()
+        .filter_map(|device| Some((device.serial()?.to_owned(), device.health())))
+        .collect()
+}
+
+/// What the watcher compares between polls to notice a failed device whose serial is still listed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct DeviceHealth {
+    /// libfreenect's handle of the device, which changes when it is reopened
+    pub handle: usize,
+    /// The transfers to the device which failed so far
+    pub errors: u64,
+    /// The callbacks of the device's streams so far, `None` while no stream runs
+    pub callbacks: Option<u64>,
+}
+
+/// The state of the process thread at a poll
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Processing {
+    Running,
+    Stopped,
+    /// The thread ended by itself, since processing libfreenect's events failed
+    Failed,
+}
+
+/// Decides which devices were disconnected and connected since the previous poll
+struct Tracker {
+    known: Vec<String>,
+    // The health of the registered devices at the previous poll, and when their callbacks last advanced
+    health: HashMap<String, (DeviceHealth, Instant)>,
+}
+
+impl Tracker {
+    fn new(known: Vec<String>) -> Tracker {
+        Tracker {
+            known,
+            health: HashMap::new(),
+        }
+    }
+
+    /// Returns the serials of the disconnected and of the connected devices. A registered device
+    /// which failed while its serial stayed listed counts as both, since it was replugged between
+    /// the polls or has to be reopened.
+    fn update(
+        &mut self,
+        current: Vec<String>,
+        registered: &[(String, DeviceHealth)],
+        processing: Processing,
+        now: Instant,
+    ) -> (Vec<String>, Vec<String>) {
+        self.health
+            .retain(|serial, _| registered.iter().any(|(s, _)| s == serial));
+        let mut replugged = Vec::new();
+        for (serial, health) in registered {
+            if self.failed(serial, *health, processing, now)
+                && self.known.contains(serial)
+                && current.contains(serial)
+                && !replugged.contains(serial)
+            {
+                replugged.push(serial.clone());
+            }
+        }
+        let mut disconnected: Vec<String> = self
+            .known
+            .iter()
+            .filter(|s| !current.contains(s))
+            .cloned()
+            .collect();
+        let mut connected: Vec<String> = current
+            .iter()
+            .filter(|s| !self.known.contains(s))
+            .cloned()
+            .collect();
+        disconnected.extend(replugged.iter().cloned());
+        connected.extend(replugged);
+        self.known = current;
+        (disconnected, connected)
+    }
+
+    /// Compares the health of a registered device with the previous poll and records it
+    fn failed(
+        &mut self,
+        serial: &str,
+        health: DeviceHealth,
+        processing: Processing,
+        now: Instant,
+    ) -> bool {
+        let (previous, advanced) = match self.health.insert(serial.to_owned(), (health, now)) {
+            Some(previous) => previous,
+            None => return processing == Processing::Failed,
+        };
+        // Callbacks only stall while the process thread runs
+        let stalled = processing == Processing::Running
+            && health.callbacks.is_some()
+            && health.callbacks == previous.callbacks;
+        if stalled {
+            self.health.insert(serial.to_owned(), (health, advanced));
+        }
+        processing == Processing::Failed
+            || health.handle != previous.handle
+            || health.errors > previous.errors
+            || (stalled && now.duration_since(advanced) >= STALL_TIMEOUT)
+    }
+
+    /// Records the health of devices the watcher reopened, whose new handle isn't a failure
+    fn reopened(&mut self, registered: &[(String, DeviceHealth)], now: Instant) {
+        for (serial, health) in registered {
+            self.health.insert(serial.clone(), (*health, now));
+        }
+    }
+}
+
+/// Reconnects `devices`. libfreenect's events must not be processed meanwhile,
+/// so the process thread is stopped and, if it was spawned before, started again.
+fn reconnect_all(
+    ctx: &FreenectContext,
+    devices: &[FreenectDevice],
+    event_sender: &Sender<DeviceEvent>,
+) {
+    let restart = ctx.is_process_thread_spawned();
+    let _ = ctx.stop_process_thread();
+    for device in devices {
+        let serial = device.serial().unwrap_or_default().to_owned();
+        let event = match device.reconnect() {
+            Ok(()) => DeviceEvent::Reconnected(serial),
+            Err(err) => DeviceEvent::ReconnectFailed(serial, err),
+        };
+        let _ = event_sender.send(event);
+    }
+    if restart {
+        let _ = ctx.spawn_process_thread();
+    }
+}
+
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        self.stop_sender.take();
+        if let Some(joiner) = self.joiner.take() {
+            let _ = joiner.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn serials(serials: &[&str]) -> Vec<String> {
+        serials.iter().map(|&s| s.to_owned()).collect()
+    }
+
+    fn healthy(callbacks: u64) -> DeviceHealth {
+        DeviceHealth {
+            handle: 1,
+            errors: 0,
+            callbacks: Some(callbacks),
+        }
+    }
+
+    #[test]
+    fn listed_serials_decide_without_registered_devices() {
+        let mut tracker = Tracker::new(serials(&["A", "B"]));
+        let now = Instant::now();
+        let update = |tracker: &mut Tracker, current| {
+            tracker.update(serials(current), &[], Processing::Running, now)
+        };
+        assert_eq!(update(&mut tracker, &["A", "B"]), (vec![], vec![]));
+        assert_eq!(update(&mut tracker, &["A"]), (serials(&["B"]), vec![]));
+        assert_eq!(update(&mut tracker, &["A", "C"]), (vec![], serials(&["C"])));
+        // A failing process thread says nothing about unregistered devices
+        let failed = tracker.update(serials(&["A", "C"]), &[], Processing::Failed, now);
+        assert_eq!(failed, (vec![], vec![]));
+    }
+
+    #[test]
+    fn failed_devices_which_stay_listed_are_replugged() {
+        let mut tracker = Tracker::new(serials(&["A", "B"]));
+        let start = Instant::now();
+        let mut poll = |health: DeviceHealth, processing, seconds| {
+            // B runs no stream
+            let idle = DeviceHealth {
+                callbacks: None,
+                ..healthy(0)
+            };
+            let registered = [("A".to_owned(), health), ("B".to_owned(), idle)];
+            let now = start + Duration::from_secs(seconds);
+            tracker.update(serials(&["A", "B"]), &registered, processing, now)
+        };
+        let replugged = (serials(&["A"]), serials(&["A"]));
+        let nothing = (vec![], vec![]);
+        assert_eq!(poll(healthy(10), Processing::Running, 0), nothing);
+        assert_eq!(poll(healthy(20), Processing::Running, 1), nothing);
+
+        // A failed transfer, and a handle which changed
+        let failing = DeviceHealth {
+            errors: 1,
+            ..healthy(30)
+        };
+        assert_eq!(poll(failing, Processing::Running, 2), replugged);
+        assert_eq!(poll(failing, Processing::Running, 3), nothing);
+        let reopened = DeviceHealth {
+            handle: 2,
+            ..failing
+        };
+        assert_eq!(poll(reopened, Processing::Running, 4), replugged);
+
+        // Streams without callbacks, which only stall while events are processed
+        let stalled = DeviceHealth {
+            callbacks: Some(40),
+            ..reopened
+        };
+        assert_eq!(poll(stalled, Processing::Running, 5), nothing);
+        assert_eq!(poll(stalled, Processing::Stopped, 9), nothing);
+        assert_eq!(poll(stalled, Processing::Running, 10), nothing);
+        assert_eq!(poll(stalled, Processing::Running, 11), nothing);
+        assert_eq!(poll(stalled, Processing::Running, 12), replugged);
+        let stopped = DeviceHealth {
+            callbacks: None,
+            ..stalled
+        };
+        assert_eq!(poll(stopped, Processing::Running, 20), nothing);
+
+        // Both registered devices are listed when the process thread fails
+        let both = (serials(&["A", "B"]), serials(&["A", "B"]));
+        assert_eq!(poll(stopped, Processing::Failed, 21), both);
+    }
+
+    #[test]
+    fn reopened_devices_and_unlisted_ones_are_not_replugged() {
+        let mut tracker = Tracker::new(serials(&["A"]));
+        let now = Instant::now();
+        let registered = [("A".to_owned(), healthy(0))];
+        tracker.update(serials(&["A"]), &registered, Processing::Running, now);
+
+        // Unplugged, its transfers fail until it is plugged in again
+        let failing = [(
+            "A".to_owned(),
+            DeviceHealth {
+                errors: 3,
+                ..healthy(0)
+            },
+        )];
+        let unplugged = tracker.update(vec![], &failing, Processing::Failed, now);
+        assert_eq!(unplugged, (serials(&["A"]), vec![]));
+        let plugged = tracker.update(serials(&["A"]), &failing, Processing::Failed, now);
+        assert_eq!(plugged, (vec![], serials(&["A"])));
+
+        // The watcher reopens it, which gives it a new handle
+        let reopened = [(
+            "A".to_owned(),
+            DeviceHealth {
+                handle: 2,
+                ..failing[0].1
+            },
+        )];
+        tracker.reopened(&reopened, now);
+        let next = tracker.update(serials(&["A"]), &reopened, Processing::Running, now);
+        assert_eq!(next, (vec![], vec![]));
+    }
+}
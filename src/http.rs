@@ -0,0 +1,1040 @@
+//! A live view for browsers, enabled by the `http` feature.
+//!
+//! The server answers each request on its own connection and closes it afterwards. Requests whose
+//! line and headers exceed 8 KiB are refused, as are connections beyond
+//! [`max_connections`](struct.LiveViewConfig.html#structfield.max_connections):
+//! * `GET /` serves a page showing both streams and the device status, with controls for tilt and LED.
+//! * `GET /video.mjpeg` and `GET /depth.mjpeg` stream rgb and colourized depth frames as MJPEG.
+//!   Frames are encoded on a thread of each stream, and only while a client watches the stream.
+//! * `GET /status` returns the device status as JSON, `GET /ws` pushes it over a WebSocket.
+//! * `POST /tilt?degrees=`, `/led?color=`, `/depth_mode?resolution=&format=` and
+//!   `/video_mode?resolution=&format=` control the device and return the new status.
+use crate::channel::{frame_channel, FrameReceiver, FrameSender};
+use crate::freenect::{
+    DeliveryPolicy, DepthColorizer, DepthFrame, DepthFrameRef, FreenectDepthCallback,
+    FreenectDepthFormat, FreenectDevice, FreenectError, FreenectLed, FreenectResolution,
+    FreenectTiltState, FreenectVideoCallback, FreenectVideoFormat, Result, VideoFrame,
+    VideoFrameRef,
+};
+use sha1::{Digest, Sha1};
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const BOUNDARY: &str = "frame";
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+// How long waiting threads sleep before checking whether the view was dropped
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+// How long a client may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+// The longest request line and headers accepted
+const MAX_REQUEST_LEN: u64 = 8 * 1024;
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><meta name="viewport" content="width=device-width,initial-scale=1">
+<title>Kinect</title>
+<style>body{font-family:sans-serif;margin:.5em}img{width:100%;max-width:640px}</style></head>
+<body>
+<img src="/video.mjpeg" alt="rgb"><img src="/depth.mjpeg" alt="depth">
+<p><button onclick="tilt(-5)">Tilt down</button> <button onclick="tilt(5)">Tilt up</button>
+<select onchange="post('/led?color='+this.value)"><option>off</option><option>green</option>
+<option>red</option><option>yellow</option><option>blink-green</option><option>blink-red-yellow</option></select></p>
+<pre id="status">connecting</pre>
+<script>
+let degree = 0;
+const ws = new WebSocket('ws://' + location.host + '/ws');
+ws.onmessage = e => {
+  const status = JSON.parse(e.data);
+  if (status.tilt) degree = status.tilt.degree;
+  document.getElementById('status').textContent = JSON.stringify(status, null, 1);
+};
+ws.onclose = () => document.getElementById('status').textContent = 'disconnected';
+function post(url) { fetch(url, {method: 'POST'}); }
+function tilt(step) { post('/tilt?degrees=' + Math.max(-30, Math.min(30, degree + step))); }
+</script>
+</body></html>
+"#;
+
+/// The settings of a [`LiveView`](struct.LiveView.html)
+#[derive(Clone, Copy, Debug)]
+pub struct LiveViewConfig {
+    /// The JPEG quality of both streams, from 1 to 100
+    pub jpeg_quality: u8,
+    /// How depth is drawn. Depth of a served device is drawn in the format the device delivers.
+    pub depth_colors: DepthColorizer,
+    /// How often WebSocket clients receive the status
+    pub status_interval: Duration,
+    /// How many connections are served at once, each by its own thread. Further clients get
+    /// `503 Service Unavailable`.
+    pub max_connections: usize,
+}
+
+impl Default for LiveViewConfig {
+    /// JPEG of quality 80, the default depth colours, two status updates per second and up to
+    /// 16 connections
+    fn default() -> LiveViewConfig {
+        LiveViewConfig {
+            jpeg_quality: 80,
+            depth_colors: DepthColorizer::default(),
+            status_interval: Duration::from_millis(500),
+            max_connections: 16,
+        }
+    }
+}
+
+/// The latest JPEG of a stream, which MJPEG clients wait for
+struct JpegSlot {
+    latest: Mutex<(u64, Option<Arc<Vec<u8>>>)>,
+    updated: Condvar,
+    // The MJPEG clients currently waiting for JPEGs
+    watchers: AtomicUsize,
+}
+
+/// Counts an MJPEG client as watching a stream until it is dropped
+struct Watching<'a>(&'a JpegSlot);
+
+impl Drop for Watching<'_> {
+    fn drop(&mut self) {
+        self.0.watchers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl JpegSlot {
+    fn new() -> JpegSlot {
+        JpegSlot {
+            latest: Mutex::new((0, None)),
+            updated: Condvar::new(),
+            watchers: AtomicUsize::new(0),
+        }
+    }
+
+    fn watch(&self) -> Watching<'_> {
+        self.watchers.fetch_add(1, Ordering::SeqCst);
+        Watching(self)
+    }
+
+    fn watched(&self) -> bool {
+        self.watchers.load(Ordering::SeqCst) > 0
+    }
+
+    fn put(&self, jpeg: Vec<u8>) {
+        let mut latest = self.latest.lock().unwrap();
+        *latest = (latest.0 + 1, Some(Arc::new(jpeg)));
+        self.updated.notify_all();
+    }
+
+    /// Waits for a JPEG newer than `sequence`. Returns `None` on timeout.
+    fn wait_newer(&self, sequence: u64, timeout: Duration) -> Option<(u64, Arc<Vec<u8>>)> {
+        let latest = self.latest.lock().unwrap();
+        let (latest, _) = self
+            .updated
+            .wait_timeout_while(latest, timeout, |latest| latest.0 <= sequence)
+            .unwrap();
+        match *latest {
+            (newer, Some(ref jpeg)) if newer > sequence => Some((newer, jpeg.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// Counts frames per second over windows of a second
+struct FpsCounter {
+    start: Instant,
+    frames: u32,
+    fps: f64,
+}
+
+impl FpsCounter {
+    fn new() -> FpsCounter {
+        FpsCounter {
+            start: Instant::now(),
+            frames: 0,
+            fps: 0.0,
+        }
+    }
+
+    fn tick(&mut self) {
+        self.frames += 1;
+        let elapsed = self.start.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            self.fps = self.frames as f64 / elapsed.as_secs_f64();
+            self.start = Instant::now();
+            self.frames = 0;
+        }
+    }
+
+    fn fps(&self) -> f64 {
+        // A stream which stopped shouldn't keep its last rate
+        if self.start.elapsed() >= Duration::from_secs(2) {
+            0.0
+        } else {
+            self.fps
+        }
+    }
+}
+
+/// The device served by a view, together with its running streams
+struct DeviceControl {
+    device: FreenectDevice,
+    depth: Option<FreenectDepthCallback>,
+    video: Option<FreenectVideoCallback>,
+    // When the tilt state was last read, and what was read
+    tilt: Option<(Instant, Option<FreenectTiltState>)>,
+}
+
+impl DeviceControl {
+    fn new(device: FreenectDevice) -> DeviceControl {
+        DeviceControl {
+            device,
+            depth: None,
+            video: None,
+            tilt: None,
+        }
+    }
+
+    /// Returns the tilt state, which is read from the device at most once per `interval` and shared
+    /// by all clients
+    fn tilt_state(&mut self, interval: Duration) -> Option<FreenectTiltState> {
+        match self.tilt {
+            Some((read, state)) if read.elapsed() < interval => state,
+            _ => {
+                let state = self.device.get_tilt_state().ok();
+                self.tilt = Some((Instant::now(), state));
+                state
+            }
+        }
+    }
+}
+
+struct ViewShared {
+    config: LiveViewConfig,
+    depth: Arc<JpegSlot>,
+    video: Arc<JpegSlot>,
+    // Feed the encoding threads, which end once these are dropped with the view
+    depth_frames: FrameSender<(DepthFrame, FreenectDepthFormat)>,
+    video_frames: FrameSender<VideoFrame>,
+    depth_fps: Mutex<FpsCounter>,
+    video_fps: Mutex<FpsCounter>,
+    device: Mutex<Option<DeviceControl>>,
+    stopped: AtomicBool,
+    // The connections currently served
+    connections: AtomicUsize,
+}
+
+/// Counts a connection as served until it is dropped
+struct ConnectionGuard(Arc<ViewShared>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl ViewShared {
+    fn publish_depth(&self, frame: &DepthFrameRef, format: FreenectDepthFormat) -> Result<()> {
+        self.depth_fps.lock().unwrap().tick();
+        if frame.data.len() != frame.width as usize * frame.height as usize {
+            return Err(FreenectError::new("Depth frame doesn't match its size"));
+        }
+        if self.depth.watched() {
+            let _ = self.depth_frames.send((frame.to_owned(), format));
+        }
+        Ok(())
+    }
+
+    fn publish_video(&self, frame: &VideoFrameRef) -> Result<()> {
+        self.video_fps.lock().unwrap().tick();
+        if frame.data.len() != 3 * frame.width as usize * frame.height as usize {
+            return Err(FreenectError::new("Only rgb frames can be shown"));
+        }
+        if self.video.watched() {
+            let _ = self.video_frames.send(frame.to_owned());
+        }
+        Ok(())
+    }
+
+    fn status_json(&self) -> String {
+        let mut control = self.device.lock().unwrap();
+        let mut json = String::new();
+        let _ = write!(json, "{{\"connected\":{}", control.is_some());
+        match control
+            .as_mut()
+            .and_then(|control| control.tilt_state(self.config.status_interval))
+        {
+            Some(state) => {
+                let [x, y, z] = state.accelerometer;
+                let _ = write!(
+                    json,
+                    ",\"tilt\":{{\"degree\":{},\"status\":\"{}\"}},\"accelerometer\":[{},{},{}]",
+                    json_number(state.degree),
+                    state.status,
+                    json_number(x),
+                    json_number(y),
+                    json_number(z)
+                );
+            }
+            _ => json.push_str(",\"tilt\":null,\"accelerometer\":null"),
+        }
+        match control.as_ref().and_then(|control| control.device.led()) {
+            Some(led) => {
+                let _ = write!(json, ",\"led\":\"{}\"", led);
+            }
+            None => json.push_str(",\"led\":null"),
+        }
+        let _ = write!(
+            json,
+            ",\"fps\":{{\"depth\":{},\"video\":{}}}",
+            json_number(self.depth_fps.lock().unwrap().fps()),
+            json_number(self.video_fps.lock().unwrap().fps())
+        );
+        let device = control.as_ref().map(|control| &control.device);
+        let depth_mode = device.and_then(FreenectDevice::depth_mode);
+        let video_mode = device.and_then(FreenectDevice::video_mode);
+        json.push_str(",\"depth_mode\":");
+        json.push_str(&mode_json(depth_mode));
+        json.push_str(",\"video_mode\":");
+        json.push_str(&mode_json(video_mode));
+        json.push('}');
+        json
+    }
+}
+
+fn mode_json<R: std::fmt::Display, F: std::fmt::Display>(mode: Option<(R, F)>) -> String {
+    match mode {
+        Some((resolution, format)) => format!(
+            "{{\"resolution\":\"{}\",\"format\":\"{}\"}}",
+            resolution, format
+        ),
+        None => "null".to_owned(),
+    }
+}
+
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        format!("{:.2}", value)
+    } else {
+        "null".to_owned()
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn encode_jpeg(rgb: &[u8], width: u32, height: u32, quality: u8) -> Result<Vec<u8>> {
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, quality)
+        .encode(rgb, width, height, image::ColorType::Rgb8)
+        .map_err(|err| FreenectError::new(format!("Unable to encode JPEG: {}", err)))?;
+    Ok(jpeg)
+}
+
+/// Encodes the frames of `frames` on a thread of its own and puts the JPEGs into `slot`.
+/// The thread ends once the sender is dropped.
+fn spawn_encoder<F: Send + 'static>(
+    frames: FrameReceiver<F>,
+    slot: Arc<JpegSlot>,
+    encode: impl Fn(F) -> Result<Vec<u8>> + Send + 'static,
+) {
+    thread::spawn(move || {
+        while let Ok(frame) = frames.recv() {
+            if let Ok(jpeg) = encode(frame) {
+                slot.put(jpeg);
+            }
+        }
+    });
+}
+
+fn start_depth(shared: &Arc<ViewShared>, device: &FreenectDevice) -> Result<FreenectDepthCallback> {
+    let shared = shared.clone();
+    // Frames are drawn in the format the device delivers, which changes only by restarting depth
+    let format = device
+        .current_depth_mode()
+        .map_or(shared.config.depth_colors.format, |mode| mode.format);
+    device.on_depth(move |frame| {
+        let _ = shared.publish_depth(frame, format);
+    })
+}
+
+fn start_video(shared: &Arc<ViewShared>, device: &FreenectDevice) -> Result<FreenectVideoCallback> {
+    let shared = shared.clone();
+    device.on_video(move |frame| {
+        let _ = shared.publish_video(frame);
+    })
+}
+
+/// Serves the streams and status of a device to browsers, so it can be checked from a phone.
+///
+/// Frames come from a device, see [`serve_device()`](#method.serve_device), or are published
+/// directly. See the [module documentation](index.html) for the available routes. The REST
+/// endpoints answer with `503 Service Unavailable` while no device is served.
+/// # Examples
+/// ```rust
+/// use freenectrs::freenect::{LiveView, LiveViewConfig, VideoFrameRef};
+/// use std::io::{Read, Write};
+/// use std::net::TcpStream;
+/// use std::time::Duration;
+///
+/// let view = LiveView::bind("127.0.0.1:0", LiveViewConfig::default()).unwrap();
+/// let rgb = vec![90u8; 64 * 48 * 3];
+/// let frame = VideoFrameRef { data: &rgb, timestamp: 1, width: 64, height: 48 };
+///
+/// let mut stream = TcpStream::connect(view.local_addr()).unwrap();
+/// stream.write_all(b"GET /video.mjpeg HTTP/1.1\r\nHost: kinect\r\n\r\n").unwrap();
+/// stream.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+/// let mut received = Vec::new();
+/// let mut buffer = [0u8; 4096];
+/// // Frames are only encoded while a client watches, so publish until the start of a JPEG arrives
+/// while !received.windows(2).any(|w| w == [0xff, 0xd8]) {
+///     view.publish_video(&frame).unwrap();
+///     if let Ok(n) = stream.read(&mut buffer) {
+///         assert!(n > 0);
+///         received.extend_from_slice(&buffer[..n]);
+///     }
+/// }
+/// let text = String::from_utf8_lossy(&received);
+/// assert!(text.starts_with("HTTP/1.1 200 OK"));
+/// assert!(text.contains("multipart/x-mixed-replace; boundary=frame"));
+/// assert!(text.contains("Content-Type: image/jpeg"));
+/// ```
+pub struct LiveView {
+    shared: Arc<ViewShared>,
+    local_addr: SocketAddr,
+    joiner: Option<thread::JoinHandle<()>>,
+}
+
+impl LiveView {
+    /// Listens on `addr` and answers requests in background threads
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::{LiveView, LiveViewConfig};
+    /// use std::io::{Read, Write};
+    /// use std::net::{SocketAddr, TcpStream};
+    ///
+    /// fn request(addr: SocketAddr, request: &str) -> String {
+    ///     let mut stream = TcpStream::connect(addr).unwrap();
+    ///     stream.write_all(request.as_bytes()).unwrap();
+    ///     let mut response = String::new();
+    ///     stream.read_to_string(&mut response).unwrap();
+    ///     response
+    /// }
+    ///
+    /// let view = LiveView::bind("127.0.0.1:0", LiveViewConfig::default()).unwrap();
+    /// let status = request(view.local_addr(), "GET /status HTTP/1.1\r\n\r\n");
+    /// assert!(status.starts_with("HTTP/1.1 200 OK"));
+    /// assert!(status.contains(r#""connected":false"#));
+    /// assert!(status.contains(r#""fps":{"depth":0.00,"video":0.00}"#));
+    ///
+    /// // There is no device to control
+    /// let tilt = request(view.local_addr(), "POST /tilt?degrees=10 HTTP/1.1\r\n\r\n");
+    /// assert!(tilt.starts_with("HTTP/1.1 503"));
+    ///
+    /// // The WebSocket handshake of RFC 6455, followed by a status message
+    /// let mut stream = TcpStream::connect(view.local_addr()).unwrap();
+    /// stream.write_all(b"GET /ws HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+    ///     Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n").unwrap();
+    /// let mut received = Vec::new();
+    /// let mut buffer = [0u8; 1024];
+    /// while !received.ends_with(b"}") {
+    ///     let n = stream.read(&mut buffer).unwrap();
+    ///     assert!(n > 0);
+    ///     received.extend_from_slice(&buffer[..n]);
+    /// }
+    /// let text = String::from_utf8_lossy(&received);
+    /// assert!(text.starts_with("HTTP/1.1 101 Switching Protocols"));
+    /// assert!(text.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    /// let frame = &received[text.find("\r\n\r\n").unwrap() + 4..];
+    /// // A final text frame
+    /// assert_eq!(frame[0], 0x81);
+    /// ```
+    pub fn bind<A: ToSocketAddrs>(addr: A, config: LiveViewConfig) -> Result<LiveView> {
+        let io_error = |err: io::Error| FreenectError::new(format!("Unable to bind: {}", err));
+        let listener = TcpListener::bind(addr).map_err(io_error)?;
+        let local_addr = listener.local_addr().map_err(io_error)?;
+        // Only the latest frame of a stream is encoded if the encoder falls behind
+        let (depth_frames, depth_receiver) = frame_channel(DeliveryPolicy::KeepLatest)?;
+        let (video_frames, video_receiver) = frame_channel(DeliveryPolicy::KeepLatest)?;
+        let depth = Arc::new(JpegSlot::new());
+        let video = Arc::new(JpegSlot::new());
+        spawn_encoder(
+            depth_receiver,
+            depth.clone(),
+            move |(frame, format): (DepthFrame, FreenectDepthFormat)| {
+                let colors = DepthColorizer {
+                    format,
+                    ..config.depth_colors
+                };
+                let rgb = colors.colorize(&frame.as_frame_ref());
+                encode_jpeg(&rgb, frame.width, frame.height, config.jpeg_quality)
+            },
+        );
+        spawn_encoder(video_receiver, video.clone(), move |frame: VideoFrame| {
+            encode_jpeg(&frame.data, frame.width, frame.height, config.jpeg_quality)
+        });
+        let shared = Arc::new(ViewShared {
+            config,
+            depth,
+            video,
+            depth_frames,
+            video_frames,
+            depth_fps: Mutex::new(FpsCounter::new()),
+            video_fps: Mutex::new(FpsCounter::new()),
+            device: Mutex::new(None),
+            stopped: AtomicBool::new(false),
+            connections: AtomicUsize::new(0),
+        });
+        let accepting = shared.clone();
+        let joiner = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if accepting.stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                if accepting.connections.fetch_add(1, Ordering::SeqCst)
+                    >= accepting.config.max_connections
+                {
+                    accepting.connections.fetch_sub(1, Ordering::SeqCst);
+                    let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+                    let _ = respond(
+                        &mut stream,
+                        "503 Service Unavailable",
+                        "text/plain",
+                        b"Too many connections",
+                    );
+                    continue;
+                }
+                let guard = ConnectionGuard(accepting.clone());
+                thread::spawn(move || {
+                    let _ = handle(&guard.0, stream);
+                });
+            }
+        });
+        Ok(LiveView {
+            shared,
+            local_addr,
+            joiner: Some(joiner),
+        })
+    }
+
+    /// Returns the address the view listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Shows a depth frame in the format of [`depth_colors`](struct.LiveViewConfig.html#structfield.depth_colors).
+    /// The frame is only copied if a client watches the depth stream, and is colourized and encoded
+    /// on a thread of the view. Fails if the frame doesn't match its size.
+    pub fn publish_depth(&self, frame: &DepthFrameRef) -> Result<()> {
+        self.shared
+            .publish_depth(frame, self.shared.config.depth_colors.format)
+    }
+
+    /// Shows an rgb frame. The frame is only copied if a client watches the video stream, and is
+    /// encoded on a thread of the view. Fails for other video formats.
+    pub fn publish_video(&self, frame: &VideoFrameRef) -> Result<()> {
+        self.shared.publish_video(frame)
+    }
+
+    /// Starts the depth and/or video stream of `device`, shows its frames and status and lets
+    /// clients control it. Replaces a device served before. The process thread must be spawned by the caller.
+    /// # Examples
+    /// ```rust,no_run
+    /// use freenectrs::freenect::{FreenectContext, LiveView, LiveViewConfig};
+    ///
+    /// let ctx = FreenectContext::init_with_video_motor().unwrap();
+    /// let device = ctx.open_device(0).unwrap();
+    /// let view = LiveView::bind("0.0.0.0:8080", LiveViewConfig::default()).unwrap();
+    /// view.serve_device(&device, true, true).unwrap();
+    /// ctx.spawn_process_thread().unwrap();
+    /// // Browse to port 8080 of this machine
+    /// std::thread::park();
+    /// ```
+    pub fn serve_device(&self, device: &FreenectDevice, depth: bool, video: bool) -> Result<()> {
+        let mut control = self.shared.device.lock().unwrap();
+        // Stop the streams of the previous device first, it may be the same one
+        *control = None;
+        let mut served = DeviceControl::new(device.clone());
+        if depth {
+            served.depth = Some(start_depth(&self.shared, device)?);
+        }
+        if video {
+            served.video = Some(start_video(&self.shared, device)?);
+        }
+        *control = Some(served);
+        Ok(())
+    }
+
+    /// Stops the streams of the served device and releases it
+    pub fn release_device(&self) {
+        *self.shared.device.lock().unwrap() = None;
+    }
+}
+
+impl Drop for LiveView {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::SeqCst);
+        // The callbacks of the device keep the shared state alive
+        self.release_device();
+        // Wakes up the accepting thread
+        let _ = TcpStream::connect(self.local_addr);
+        if let Some(joiner) = self.joiner.take() {
+            let _ = joiner.join();
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Reads the request line and headers. A body is ignored.
+    /// Fails if they are longer than `MAX_REQUEST_LEN` bytes.
+    fn read(stream: &TcpStream) -> Option<Request> {
+        // A line cut off by the limit is followed by the end of the input
+        let mut reader = BufReader::new(stream.take(MAX_REQUEST_LEN));
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let mut parts = line.split_whitespace();
+        let method = parts.next()?.to_owned();
+        let target = parts.next()?.to_owned();
+        let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+        let query = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (name.to_owned(), value.to_owned())
+            })
+            .collect();
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).ok()? == 0 || headers.len() > 100 {
+                return None;
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                headers.push((name.trim().to_ascii_lowercase(), value.trim().to_owned()));
+            }
+        }
+        Some(Request {
+            method,
+            path: path.to_owned(),
+            query,
+            headers,
+        })
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parses the query parameter `name`, failing with `400 Bad Request`
+    fn param<T: FromStr>(&self, name: &str) -> std::result::Result<T, (&'static str, String)> {
+        self.query
+            .iter()
+            .find(|(param, _)| param == name)
+            .and_then(|(_, value)| value.parse().ok())
+            .ok_or_else(|| {
+                (
+                    "400 Bad Request",
+                    format!("Missing or invalid parameter {}", name),
+                )
+            })
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)
+}
+
+fn handle(shared: &Arc<ViewShared>, mut stream: TcpStream) -> io::Result<()> {
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let request = match Request::read(&stream) {
+        Some(request) => request,
+        None => return respond(&mut stream, "400 Bad Request", "text/plain", b"Bad request"),
+    };
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => respond(
+            &mut stream,
+            "200 OK",
+            "text/html; charset=utf-8",
+            PAGE.as_bytes(),
+        ),
+        ("GET", "/video.mjpeg") => stream_mjpeg(shared, &shared.video, stream),
+        ("GET", "/depth.mjpeg") => stream_mjpeg(shared, &shared.depth, stream),
+        ("GET", "/status") => respond(
+            &mut stream,
+            "200 OK",
+            "application/json",
+            shared.status_json().as_bytes(),
+        ),
+        ("GET", "/ws") => websocket(shared, &request, stream),
+        ("POST", "/tilt")
+        | ("POST", "/led")
+        | ("POST", "/depth_mode")
+        | ("POST", "/video_mode") => match control(shared, &request) {
+            Ok(()) => respond(
+                &mut stream,
+                "200 OK",
+                "application/json",
+                shared.status_json().as_bytes(),
+            ),
+            Err((status, message)) => {
+                let body = format!("{{\"error\":{}}}", json_string(&message));
+                respond(&mut stream, status, "application/json", body.as_bytes())
+            }
+        },
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b"Not found"),
+    }
+}
+
+/// Applies a REST request to the served device
+fn control(
+    shared: &Arc<ViewShared>,
+    request: &Request,
+) -> std::result::Result<(), (&'static str, String)> {
+    let failed = |err: FreenectError| ("500 Internal Server Error", err.to_string());
+    let mut served = shared.device.lock().unwrap();
+    let served = served
+        .as_mut()
+        .ok_or(("503 Service Unavailable", "No device is served".to_owned()))?;
+    match request.path.as_str() {
+        "/tilt" => {
+            let degrees: f64 = request.param("degrees")?;
+            // The status answered with shows the motor moving
+            served.tilt = None;
+            served.device.set_tilt_degree(degrees).map_err(failed)
+        }
+        "/led" => {
+            let led: FreenectLed = request.param("color")?;
+            served.device.set_led(led).map_err(failed)
+        }
+        "/depth_mode" => {
+            let resolution: FreenectResolution = request.param("resolution")?;
+            let format: FreenectDepthFormat = request.param("format")?;
+            let device = served.device.clone();
+            let previous = device
+                .current_depth_mode()
+                .map(|mode| (mode.resolution, mode.format));
+            change_mode(
+                &mut served.depth,
+                (resolution, format),
+                previous,
+                |(resolution, format)| device.set_depth_mode(resolution, format),
+                || start_depth(shared, &device),
+            )
+            .map_err(failed)
+        }
+        _ => {
+            let resolution: FreenectResolution = request.param("resolution")?;
+            let format: FreenectVideoFormat = request.param("format")?;
+            let device = served.device.clone();
+            let previous = device
+                .current_video_mode()
+                .map(|mode| (mode.resolution, mode.format));
+            change_mode(
+                &mut served.video,
+                (resolution, format),
+                previous,
+                |(resolution, format)| device.set_video_mode(resolution, format),
+                || start_video(shared, &device),
+            )
+            .map_err(failed)
+        }
+    }
+}
+
+/// Sets `mode` while the stream of `callback` is stopped, since modes can't change while it runs.
+/// If the mode can't be set or the stream doesn't start with it, the `previous` mode is set again
+/// and the stream restarted with it, so a failed request leaves the device as it was.
+fn change_mode<C, M: Copy>(
+    callback: &mut Option<C>,
+    mode: M,
+    previous: Option<M>,
+    set_mode: impl Fn(M) -> Result<()>,
+    start: impl Fn() -> Result<C>,
+) -> Result<()> {
+    let running = callback.take().is_some();
+    let result = set_mode(mode).and_then(|()| {
+        if running {
+            *callback = Some(start()?);
+        }
+        Ok(())
+    });
+    if result.is_err() {
+        if let Some(previous) = previous {
+            let _ = set_mode(previous);
+        }
+        if running {
+            *callback = start().ok();
+        }
+    }
+    result
+}
+
+/// Sends each new JPEG of `slot` as a part of a multipart response until the view is dropped
+fn stream_mjpeg(shared: &ViewShared, slot: &JpegSlot, mut stream: TcpStream) -> io::Result<()> {
+    let _watching = slot.watch();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        BOUNDARY
+    )?;
+    let mut sequence = 0;
+    while !shared.stopped.load(Ordering::SeqCst) {
+        if let Some((newer, jpeg)) = slot.wait_newer(sequence, POLL_INTERVAL) {
+            sequence = newer;
+            write!(
+                stream,
+                "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                BOUNDARY,
+                jpeg.len()
+            )?;
+            stream.write_all(&jpeg)?;
+            stream.write_all(b"\r\n")?;
+        }
+    }
+    Ok(())
+}
+
+/// Completes the WebSocket handshake and sends the status until the client goes away.
+/// Messages of the client are ignored.
+fn websocket(shared: &ViewShared, request: &Request, mut stream: TcpStream) -> io::Result<()> {
+    let key = match request.header("sec-websocket-key") {
+        Some(key) => key,
+        None => {
+            return respond(
+                &mut stream,
+                "400 Bad Request",
+                "text/plain",
+                b"Expected a WebSocket handshake",
+            )
+        }
+    };
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        websocket_accept(key)
+    )?;
+    let mut next = Instant::now();
+    while !shared.stopped.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if now < next {
+            thread::sleep((next - now).min(POLL_INTERVAL));
+            continue;
+        }
+        next = now + shared.config.status_interval;
+        write_text_frame(&mut stream, &shared.status_json())?;
+    }
+    // A close frame without status code
+    stream.write_all(&[0x88, 0])
+}
+
+fn websocket_accept(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.as_bytes());
+    hasher.update(WEBSOCKET_GUID.as_bytes());
+    base64(&hasher.finalize())
+}
+
+/// Writes an unmasked, final text frame
+fn write_text_frame(stream: &mut TcpStream, text: &str) -> io::Result<()> {
+    let len = text.len();
+    let mut header = vec![0x81];
+    if len < 126 {
+        header.push(len as u8);
+    } else if len <= u16::MAX as usize {
+        header.push(126);
+        header.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        header.push(127);
+        header.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+    stream.write_all(&header)?;
+    stream.write_all(text.as_bytes())
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for group in bytes.chunks(3) {
+        let bits = group.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= group.len() {
+                out.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::io::Read;
+
+    #[test]
+    fn failed_mode_changes_restore_the_previous_mode_and_stream() {
+        let mode = Cell::new(1);
+        let set_mode = |new| {
+            mode.set(new);
+            Ok(())
+        };
+        // The stream only starts with mode 1
+        let start = || {
+            if mode.get() == 1 {
+                Ok("stream")
+            } else {
+                Err(FreenectError::new("Unsupported mode"))
+            }
+        };
+        let mut callback = Some("stream");
+        assert!(change_mode(&mut callback, 2, Some(1), set_mode, start).is_err());
+        assert_eq!((mode.get(), callback), (1, Some("stream")));
+
+        let set_mode = |new| {
+            if new == 3 {
+                return Err(FreenectError::new("Unknown mode"));
+            }
+            mode.set(new);
+            Ok(())
+        };
+        assert!(change_mode(&mut callback, 3, Some(1), set_mode, start).is_err());
+        assert_eq!((mode.get(), callback), (1, Some("stream")));
+        assert!(change_mode(&mut callback, 1, Some(1), set_mode, start).is_ok());
+        assert_eq!(callback, Some("stream"));
+    }
+
+    fn request(addr: SocketAddr, request: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        // The server may answer before the whole request is sent
+        let _ = stream.write_all(request);
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response);
+        String::from_utf8_lossy(&response).into_owned()
+    }
+
+    #[test]
+    fn long_requests_are_refused() {
+        let view = LiveView::bind("127.0.0.1:0", LiveViewConfig::default()).unwrap();
+        let mut long = b"GET /status HTTP/1.1\r\nX-Padding: ".to_vec();
+        long.resize(long.len() + MAX_REQUEST_LEN as usize, b'a');
+        long.extend_from_slice(b"\r\n\r\n");
+        assert!(request(view.local_addr(), &long).starts_with("HTTP/1.1 400"));
+        let short = b"GET /status HTTP/1.1\r\n\r\n";
+        assert!(request(view.local_addr(), short).starts_with("HTTP/1.1 200"));
+    }
+
+    #[test]
+    fn connections_beyond_the_limit_are_refused() {
+        let config = LiveViewConfig {
+            max_connections: 1,
+            ..LiveViewConfig::default()
+        };
+        let view = LiveView::bind("127.0.0.1:0", config).unwrap();
+        // Holds the only connection by not sending a request
+        let idle = TcpStream::connect(view.local_addr()).unwrap();
+        while view.shared.connections.load(Ordering::SeqCst) == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let refused = request(view.local_addr(), b"GET /status HTTP/1.1\r\n\r\n");
+        assert!(refused.starts_with("HTTP/1.1 503"));
+        drop(idle);
+        while view.shared.connections.load(Ordering::SeqCst) > 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let served = request(view.local_addr(), b"GET /status HTTP/1.1\r\n\r\n");
+        assert!(served.starts_with("HTTP/1.1 200"));
+    }
+
+    #[test]
+    fn frames_are_only_encoded_while_watched() {
+        let view = LiveView::bind("127.0.0.1:0", LiveViewConfig::default()).unwrap();
+        let rgb = vec![90u8; 16 * 12 * 3];
+        let frame = VideoFrameRef {
+            data: &rgb,
+            timestamp: 1,
+            width: 16,
+            height: 12,
+        };
+        view.publish_video(&frame).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(view.shared.video.latest.lock().unwrap().0, 0);
+
+        let mut stream = TcpStream::connect(view.local_addr()).unwrap();
+        stream
+            .write_all(b"GET /video.mjpeg HTTP/1.1\r\n\r\n")
+            .unwrap();
+        while !view.shared.video.watched() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        view.publish_video(&frame).unwrap();
+        assert!(view
+            .shared
+            .video
+            .wait_newer(0, Duration::from_secs(5))
+            .is_some());
+        // The depth stream isn't watched
+        let depth = vec![500u16; 16 * 12];
+        view.publish_depth(&DepthFrameRef {
+            data: &depth,
+            timestamp: 1,
+            width: 16,
+            height: 12,
+        })
+        .unwrap();
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(view.shared.depth.latest.lock().unwrap().0, 0);
+    }
+}
@@ -0,0 +1,184 @@
+//! Conversions between frames and buffers of the `image` crate, enabled by the `image` feature.
+use crate::freenect::{
+    DepthFrame, DepthFrameRef, FreenectError, Result, VideoFrame, VideoFrameRef,
+};
+use image::{GrayImage, ImageBuffer, Luma, Pixel, Rgb, RgbImage};
+use std::ops::Deref;
+use std::path::Path;
+
+/// A depth map with one 16-bit value per pixel
+pub type DepthImage = ImageBuffer<Luma<u16>, Vec<u16>>;
+
+/// Wraps `data` if it holds exactly the pixels of the given size
+fn buffer<P, C>(width: u32, height: u32, data: C) -> Option<ImageBuffer<P, C>>
+where
+    P: Pixel + 'static,
+    C: Deref<Target = [P::Subpixel]>,
+{
+    let len = width as usize * height as usize * P::CHANNEL_COUNT as usize;
+    if data.len() == len {
+        ImageBuffer::from_raw(width, height, data)
+    } else {
+        None
+    }
+}
+
+/// Converts rgb to grey using the weights of ITU-R BT.601
+fn luma(rgb: &[u8]) -> u8 {
+    ((299 * rgb[0] as u32 + 587 * rgb[1] as u32 + 114 * rgb[2] as u32) / 1000) as u8
+}
+
+impl DepthFrame {
+    /// Turns the frame into an image without copying. Returns `None` if the size of `data`
+    /// doesn't match `width` and `height`.
+    pub fn into_image(self) -> Option<DepthImage> {
+        buffer(self.width, self.height, self.data)
+    }
+
+    /// Copies the frame into an image. Returns `None` if the size of `data` doesn't match `width` and `height`.
+    pub fn to_image(&self) -> Option<DepthImage> {
+        self.as_frame_ref().to_image()
+    }
+
+    /// Creates a frame from a depth map. The timestamp is 0.
+    pub fn from_image(image: DepthImage) -> DepthFrame {
+        DepthFrame {
+            width: image.width(),
+            height: image.height(),
+            data: image.into_raw(),
+            timestamp: 0,
+        }
+    }
+
+    /// Saves the frame as a 16-bit greyscale PNG, which keeps the depth values unchanged
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::DepthFrame;
+    ///
+    /// let frame = DepthFrame { data: vec![0, 500, 1000, 2047, 9000, 65535], timestamp: 3, width: 3, height: 2 };
+    /// let path = std::env::temp_dir().join(format!("freenectrs-depth-{}.png", std::process::id()));
+    /// frame.save_png(&path).unwrap();
+    ///
+    /// let loaded = DepthFrame::load_png(&path).unwrap();
+    /// assert_eq!((loaded.width, loaded.height), (3, 2));
+    /// assert_eq!(loaded.data, frame.data);
+    /// std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.as_frame_ref().save_png(path)
+    }
+
+    /// Loads a depth map saved with [`save_png()`](#method.save_png). Fails for images which
+    /// aren't 16-bit greyscale, since their values can't be depth. The timestamp is 0.
+    pub fn load_png<P: AsRef<Path>>(path: P) -> Result<DepthFrame> {
+        let path = path.as_ref();
+        let image = image::open(path).map_err(|err| {
+            FreenectError::new(format!("Unable to load {}: {}", path.display(), err))
+        })?;
+        match image {
+            image::DynamicImage::ImageLuma16(image) => Ok(DepthFrame::from_image(image)),
+            _ => Err(FreenectError::new(format!(
+                "{} is no 16-bit greyscale image",
+                path.display()
+            ))),
+        }
+    }
+}
+
+impl<'a> DepthFrameRef<'a> {
+    /// Views the frame as an image without copying. Returns `None` if the size of `data`
+    /// doesn't match `width` and `height`.
+    pub fn as_image(&self) -> Option<ImageBuffer<Luma<u16>, &'a [u16]>> {
+        buffer(self.width, self.height, self.data)
+    }
+
+    /// Copies the frame into an image. Returns `None` if the size of `data` doesn't match `width` and `height`.
+    pub fn to_image(&self) -> Option<DepthImage> {
+        buffer(self.width, self.height, self.data.to_vec())
+    }
+
+    /// Saves the frame as a 16-bit greyscale PNG, see [`DepthFrame::save_png()`](struct.DepthFrame.html#method.save_png)
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let image = self
+            .as_image()
+            .ok_or_else(|| FreenectError::new("Depth frame has the wrong size"))?;
+        image
+            .save_with_format(path, image::ImageFormat::Png)
+            .map_err(|err| {
+                FreenectError::new(format!("Unable to save {}: {}", path.display(), err))
+            })
+    }
+}
+
+impl VideoFrame {
+    /// Turns an rgb frame into an image without copying. Returns `None` for other video formats.
+    pub fn into_rgb_image(self) -> Option<RgbImage> {
+        buffer(self.width, self.height, self.data)
+    }
+
+    /// Copies an rgb frame into an image. Returns `None` for other video formats.
+    pub fn to_rgb_image(&self) -> Option<RgbImage> {
+        self.as_frame_ref().to_rgb_image()
+    }
+
+    /// Turns an 8-bit infrared frame into an image without copying and converts rgb frames to grey.
+    /// Returns `None` for other video formats.
+    /// The frame doesn't know its video format, so the conversion goes by the data length: any
+    /// frame with one byte per pixel is taken as grey, including undemosaiced Bayer frames.
+    pub fn into_gray_image(self) -> Option<GrayImage> {
+        let pixels = self.width as usize * self.height as usize;
+        if self.data.len() == pixels {
+            buffer(self.width, self.height, self.data)
+        } else {
+            self.as_frame_ref().to_gray_image()
+        }
+    }
+
+    /// Copies an 8-bit infrared frame into an image or converts an rgb frame to grey.
+    /// Returns `None` for other video formats.
+    /// The frame doesn't know its video format, so the conversion goes by the data length: any
+    /// frame with one byte per pixel is taken as grey, including undemosaiced Bayer frames.
+    pub fn to_gray_image(&self) -> Option<GrayImage> {
+        self.as_frame_ref().to_gray_image()
+    }
+}
+
+impl<'a> VideoFrameRef<'a> {
+    /// Views an rgb frame as an image without copying. Returns `None` for other video formats.
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::VideoFrameRef;
+    ///
+    /// let data = [10, 20, 30, 40, 50, 60];
+    /// let frame = VideoFrameRef { data: &data, timestamp: 0, width: 2, height: 1 };
+    /// let image = frame.as_rgb_image().unwrap();
+    /// assert_eq!(image.get_pixel(1, 0).0, [40, 50, 60]);
+    /// // Two bytes per pixel is no rgb
+    /// assert!(VideoFrameRef { data: &data, timestamp: 0, width: 3, height: 1 }.as_rgb_image().is_none());
+    /// ```
+    pub fn as_rgb_image(&self) -> Option<ImageBuffer<Rgb<u8>, &'a [u8]>> {
+        buffer(self.width, self.height, self.data)
+    }
+
+    /// Copies an rgb frame into an image. Returns `None` for other video formats.
+    pub fn to_rgb_image(&self) -> Option<RgbImage> {
+        buffer(self.width, self.height, self.data.to_vec())
+    }
+
+    /// Copies an 8-bit infrared frame into an image or converts an rgb frame to grey.
+    /// Returns `None` for other video formats.
+    /// The frame doesn't know its video format, so the conversion goes by the data length: any
+    /// frame with one byte per pixel is taken as grey, including undemosaiced Bayer frames.
+    pub fn to_gray_image(&self) -> Option<GrayImage> {
+        let pixels = self.width as usize * self.height as usize;
+        let gray = if self.data.len() == pixels {
+            self.data.to_vec()
+        } else if self.data.len() == 3 * pixels {
+            self.data.chunks_exact(3).map(luma).collect()
+        } else {
+            return None;
+        };
+        buffer(self.width, self.height, gray)
+    }
+}
@@ -17,13 +17,73 @@
 //! // Start the main-loop-thread
 //! ctx.spawn_process_thread().unwrap();
 //! // Fetch the video and depth frames
-//! if let Ok((data, timestamp)) = dstream.receiver.try_recv() {
-//!        // ... handle depth data
+//! if let Ok(frame) = dstream.receiver.try_recv() {
+//!        // ... handle depth data in frame.data
 //! }
-//! if let Ok((data, timestamp)) = vstream.receiver.try_recv() {
-//!        // ... handle rgb data
+//! if let Ok(frame) = vstream.receiver.try_recv() {
+//!        // ... handle rgb data in frame.data
 //! }
 //! ctx.stop_process_thread().unwrap();
 //! ```
+//! # Features
+//! * `async`: Depth and video streams implement `futures_core::Stream`, so frames can be awaited.
+//!   [`FreenectSyncedStreams`](freenect/struct.FreenectSyncedStreams.html) pairs depth and rgb frames.
+//! * `log`: libfreenect's messages are forwarded to the `log` crate using the target `freenect`.
+//!   Use [`set_log_level()`](freenect/struct.FreenectContext.html#method.set_log_level) to choose how verbose they are.
+//! * `net`: [`NetServer`](net/struct.NetServer.html) streams frames over TCP to
+//!   [`NetClient`](net/struct.NetClient.html)s, with compressed depth and JPEG rgb. The
+//!   [`net`](net/index.html) module documents the protocol, its types are also available from `freenect`.
+//! * `http`: [`LiveView`](freenect/struct.LiveView.html) serves the streams as MJPEG and the device
+//!   status over a WebSocket to browsers, with REST endpoints to control tilt, LED and modes.
+//! * `tui`: [`TerminalViewer`](freenect/struct.TerminalViewer.html) shows depth, rgb and the device
+//!   status in a terminal using truecolor half blocks, also over SSH.
+//! * `image`: Converts frames to and from buffers of the `image` crate without copying where possible,
+//!   for example with [`VideoFrame::into_rgb_image()`](freenect/struct.VideoFrame.html#method.into_rgb_image),
+//!   and saves depth as 16-bit PNG with [`DepthFrame::save_png()`](freenect/struct.DepthFrame.html#method.save_png),
+//!   and [`DepthColorizer::colorize_image()`](freenect/struct.DepthColorizer.html#method.colorize_image) draws depth as an image.
+//! * `ndarray`: Depth and video frames can be viewed as `ndarray` arrays of their real dimensions,
+//!   see [`DepthFrame::as_array()`](freenect/struct.DepthFrame.html#method.as_array), and
+//!   [`stack_depth_frames()`](freenect/fn.stack_depth_frames.html) stacks frames for statistics over time.
+//! * `nalgebra`: Converts depth frames to point clouds of `nalgebra` points with
+//!   [`DepthFrame::to_points()`](freenect/struct.DepthFrame.html#method.to_points), optionally moved by
+//!   the camera's pose as an `Isometry3`, places blobs found by a
+//!   [`BackgroundModel`](freenect/struct.BackgroundModel.html) in space, computes surface normals with
+//!   [`DepthFrame::normals()`](freenect/struct.DepthFrame.html#method.normals), and turns the accelerometer to a vector with
+//!   [`roll_pitch()`](freenect/fn.roll_pitch.html) relative to gravity.
+//! * `firmware`: [`set_firmware()`](freenect/struct.FreenectContext.html#method.set_firmware) uploads
+//!   the audio firmware which model 1473 and Kinect for Windows devices need, once its SHA-256 hash
+//!   matches the expected one.
+//! * `cli`: Builds the `kinect` command-line tool, see `kinect help`. With `tui`, it also has a `view` command.
+#[cfg(feature = "ndarray")]
+mod arrays;
+#[cfg(feature = "async")]
+mod asynchronous;
+mod beamforming;
+mod buffers;
+mod channel;
+mod chunk;
+mod fakenect;
+mod filters;
+#[cfg(feature = "firmware")]
+mod firmware;
 pub mod freenect;
 mod freenect_ffi;
+#[cfg(feature = "nalgebra")]
+mod geometry;
+mod group;
+mod hotplug;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "image")]
+mod images;
+#[cfg(feature = "net")]
+pub mod net;
+#[cfg(feature = "nalgebra")]
+mod normals;
+mod segmentation;
+#[cfg(unix)]
+mod shm;
+mod temporal;
+#[cfg(feature = "tui")]
+mod tui;
+pub mod visualize;
@@ -0,0 +1,697 @@
+//! Streaming frames over TCP, enabled by the `net` feature.
+//!
+//! Each frame is sent as a 20-byte header followed by its payload. The header holds, in big-endian
+//! byte order, the kind of frame (`u8`, 1 for depth and 2 for video), its encoding (`u8`, 0 for raw,
+//! 1 for delta-compressed depth and 2 for JPEG), two reserved bytes, the timestamp, width, height
+//! and payload length (each `u32`). Raw depth values are little-endian `u16`.
+//!
+//! Clients only accept frames of up to 1280×1024 pixels, the largest mode of the Kinect, whose
+//! payload matches their size and whose depth values fit into a `u16`, and close the connection
+//! otherwise.
+//!
+//! The types of this module are also available from the [`freenect`](../freenect/index.html) module.
+use crate::channel::{check_policy, frame_channel, FrameSender};
+use crate::fakenect::FakenectDump;
+use crate::freenect::{
+    DeliveryPolicy, DepthFrame, DepthFrameRef, FrameReceiver, FreenectDepthCallback,
+    FreenectDevice, FreenectError, FreenectVideoCallback, Result, VideoFrame, VideoFrameRef,
+};
+use crate::group::Frame;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const HEADER_LEN: usize = 20;
+const KIND_DEPTH: u8 = 1;
+const KIND_VIDEO: u8 = 2;
+const ENCODING_RAW: u8 = 0;
+const ENCODING_DELTA: u8 = 1;
+const ENCODING_JPEG: u8 = 2;
+// The largest frames a client accepts, from the high resolution modes
+const MAX_PIXELS: usize = 1280 * 1024;
+// Raw rgb is the largest encoding, JPEG of noise may exceed it a little
+const MAX_PAYLOAD: usize = 4 * MAX_PIXELS;
+
+/// How a [`NetServer`](struct.NetServer.html) sends rgb frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoEncoding {
+    Raw,
+    /// JPEG with a quality from 1 to 100. Frames which aren't rgb are sent raw.
+    Jpeg {
+        quality: u8,
+    },
+}
+
+/// The settings of a [`NetServer`](struct.NetServer.html)
+#[derive(Clone, Copy, Debug)]
+pub struct ServerConfig {
+    /// Compresses depth frames losslessly by encoding the difference between neighbouring values
+    pub compress_depth: bool,
+    pub video_encoding: VideoEncoding,
+    /// Decides what happens with frames a client can't receive fast enough
+    pub policy: DeliveryPolicy,
+    /// How many clients are served at once, each by two threads. Further clients are disconnected
+    /// right away. A client which went away is only noticed when the next frame is sent to it.
+    pub max_clients: usize,
+}
+
+impl Default for ServerConfig {
+    /// Compressed depth, JPEG of quality 80, only the latest frame for slow clients and up to
+    /// 16 clients
+    fn default() -> ServerConfig {
+        ServerConfig {
+            compress_depth: true,
+            video_encoding: VideoEncoding::Jpeg { quality: 80 },
+            policy: DeliveryPolicy::KeepLatest,
+            max_clients: 16,
+        }
+    }
+}
+
+fn io_error(what: &str, err: io::Error) -> FreenectError {
+    FreenectError::new(format!("{}: {}", what, err))
+}
+
+/// Encodes the differences between consecutive values as zigzag varints
+fn compress_depth(data: &[u16]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut previous = 0i32;
+    for &value in data {
+        let delta = value as i32 - previous;
+        previous = value as i32;
+        let mut zigzag = ((delta << 1) ^ (delta >> 31)) as u32;
+        while zigzag >= 0x80 {
+            out.push(zigzag as u8 | 0x80);
+            zigzag >>= 7;
+        }
+        out.push(zigzag as u8);
+    }
+    out
+}
+
+fn decompress_depth(payload: &[u8], len: usize) -> Option<Vec<u16>> {
+    let mut data = Vec::with_capacity(len);
+    let mut previous = 0i32;
+    let mut bytes = payload.iter();
+    while data.len() < len {
+        let mut zigzag = 0u32;
+        let mut shift = 0;
+        loop {
+            let byte = *bytes.next()?;
+            zigzag |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+            if shift > 28 {
+                return None;
+            }
+        }
+        let delta = (zigzag >> 1) as i32 ^ -((zigzag & 1) as i32);
+        previous = previous.checked_add(delta)?;
+        if !(0..=u16::MAX as i32).contains(&previous) {
+            return None;
+        }
+        data.push(previous as u16);
+    }
+    Some(data)
+}
+
+fn message(
+    kind: u8,
+    encoding: u8,
+    timestamp: u32,
+    width: u32,
+    height: u32,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+    out.extend_from_slice(&[kind, encoding, 0, 0]);
+    out.extend_from_slice(&timestamp.to_be_bytes());
+    out.extend_from_slice(&width.to_be_bytes());
+    out.extend_from_slice(&height.to_be_bytes());
+    out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+/// A connected client. Depth and video frames have their own channel, so the delivery policy
+/// applies to each of them separately.
+struct Client {
+    depth: FrameSender<Arc<Vec<u8>>>,
+    video: FrameSender<Arc<Vec<u8>>>,
+    // Shut down to release a writer blocked on a slow connection
+    stream: TcpStream,
+}
+
+struct ServerShared {
+    config: ServerConfig,
+    clients: Mutex<Vec<Client>>,
+    stopped: AtomicBool,
+}
+
+impl ServerShared {
+    fn broadcast(&self, message: Vec<u8>) {
+        let message = Arc::new(message);
+        // Clients whose writer has ended are forgotten
+        self.clients.lock().unwrap().retain(|client| {
+            let sender = if message[0] == KIND_DEPTH {
+                &client.depth
+            } else {
+                &client.video
+            };
+            sender.send(message.clone()).is_ok()
+        });
+    }
+
+    fn publish_depth(&self, frame: &DepthFrameRef) {
+        let (encoding, payload) = if self.config.compress_depth {
+            (ENCODING_DELTA, compress_depth(frame.data))
+        } else {
+            let raw = frame.data.iter().flat_map(|v| v.to_le_bytes()).collect();
+            (ENCODING_RAW, raw)
+        };
+        self.broadcast(message(
+            KIND_DEPTH,
+            encoding,
+            frame.timestamp,
+            frame.width,
+            frame.height,
+            &payload,
+        ));
+    }
+
+    fn publish_video(&self, frame: &VideoFrameRef) -> Result<()> {
+        let is_rgb = frame.data.len() == 3 * frame.width as usize * frame.height as usize;
+        let msg = match self.config.video_encoding {
+            VideoEncoding::Jpeg { quality } if is_rgb => {
+                let mut jpeg = Vec::new();
+                image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, quality)
+                    .encode(
+                        frame.data,
+                        frame.width,
+                        frame.height,
+                        image::ColorType::Rgb8,
+                    )
+                    .map_err(|err| FreenectError::new(format!("Unable to encode JPEG: {}", err)))?;
+                message(
+                    KIND_VIDEO,
+                    ENCODING_JPEG,
+                    frame.timestamp,
+                    frame.width,
+                    frame.height,
+                    &jpeg,
+                )
+            }
+            _ => message(
+                KIND_VIDEO,
+                ENCODING_RAW,
+                frame.timestamp,
+                frame.width,
+                frame.height,
+                frame.data,
+            ),
+        };
+        self.broadcast(msg);
+        Ok(())
+    }
+}
+
+/// Serves depth and video frames to [`NetClient`](struct.NetClient.html)s connecting over TCP.
+///
+/// Frames come from a device, see [`serve_device()`](#method.serve_device), from a recording, see
+/// [`play_dump()`](#method.play_dump), or are published directly. Every client receives the frames
+/// published after it connected.
+/// # Examples
+/// ```rust
+/// use freenectrs::freenect::{DepthFrameRef, NetClient, NetServer, ServerConfig, VideoFrameRef};
+/// use freenectrs::freenect::DeliveryPolicy;
+/// use std::time::Duration;
+///
+/// let server = NetServer::bind("127.0.0.1:0", ServerConfig::default()).unwrap();
+/// let client = NetClient::connect(server.local_addr(), DeliveryPolicy::default()).unwrap();
+/// while server.clients() == 0 {
+///     std::thread::sleep(Duration::from_millis(1));
+/// }
+///
+/// let depth: Vec<u16> = (0..64 * 48).map(|i| (i % 2048) as u16).collect();
+/// server.publish_depth(&DepthFrameRef { data: &depth, timestamp: 1, width: 64, height: 48 });
+/// let rgb = vec![200u8; 64 * 48 * 3];
+/// server.publish_video(&VideoFrameRef { data: &rgb, timestamp: 2, width: 64, height: 48 }).unwrap();
+///
+/// let frame = client.depth.recv_timeout(Duration::from_secs(5)).unwrap();
+/// assert_eq!((frame.timestamp, frame.data), (1, depth));
+/// // JPEG is lossy
+/// let frame = client.video.recv_timeout(Duration::from_secs(5)).unwrap();
+/// assert_eq!(frame.data.len(), rgb.len());
+/// assert!(frame.data.iter().all(|&v| (v as i32 - 200).abs() <= 2));
+/// ```
+pub struct NetServer {
+    shared: Arc<ServerShared>,
+    local_addr: SocketAddr,
+    joiner: Option<thread::JoinHandle<()>>,
+}
+
+impl NetServer {
+    /// Listens on `addr` and accepts clients in a background thread
+    pub fn bind<A: ToSocketAddrs>(addr: A, config: ServerConfig) -> Result<NetServer> {
+        check_policy(config.policy)?;
+        let listener = TcpListener::bind(addr).map_err(|err| io_error("Unable to bind", err))?;
+        let local_addr = listener
+            .local_addr()
+            .map_err(|err| io_error("Unable to bind", err))?;
+        let shared = Arc::new(ServerShared {
+            config,
+            clients: Mutex::new(Vec::new()),
+            stopped: AtomicBool::new(false),
+        });
+        let accepting = shared.clone();
+        let joiner = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if accepting.stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                if accepting.clients.lock().unwrap().len() >= accepting.config.max_clients {
+                    let _ = stream.shutdown(Shutdown::Both);
+                    continue;
+                }
+                let shutdown = match stream.try_clone() {
+                    Ok(clone) => clone,
+                    Err(_) => continue,
+                };
+                let _ = stream.set_nodelay(true);
+                let writer = Arc::new(Mutex::new(stream));
+                let policy = accepting.config.policy;
+                // The policy was checked when binding
+                let (depth, video) = match (
+                    spawn_writer(writer.clone(), policy),
+                    spawn_writer(writer, policy),
+                ) {
+                    (Ok(depth), Ok(video)) => (depth, video),
+                    _ => continue,
+                };
+                accepting.clients.lock().unwrap().push(Client {
+                    depth,
+                    video,
+                    stream: shutdown,
+                });
+            }
+        });
+        Ok(NetServer {
+            shared,
+            local_addr,
+            joiner: Some(joiner),
+        })
+    }
+
+    /// Returns the address the server listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Returns the number of connected clients
+    pub fn clients(&self) -> usize {
+        self.shared.clients.lock().unwrap().len()
+    }
+
+    /// Sends a depth frame to all clients
+    pub fn publish_depth(&self, frame: &DepthFrameRef) {
+        self.shared.publish_depth(frame);
+    }
+
+    /// Sends a video frame to all clients. Fails if it can't be encoded.
+    pub fn publish_video(&self, frame: &VideoFrameRef) -> Result<()> {
+        self.shared.publish_video(frame)
+    }
+
+    /// Sends an owned frame to all clients
+    pub fn publish(&self, frame: &Frame) -> Result<()> {
+        match *frame {
+            Frame::Depth(ref depth) => {
+                self.publish_depth(&depth.as_frame_ref());
+                Ok(())
+            }
+            Frame::Video(ref video) => self.publish_video(&video.as_frame_ref()),
+        }
+    }
+
+    /// Starts the depth and/or video stream of `device` and sends its frames to all clients
+    /// until the returned object is dropped. The process thread must be spawned by the caller.
+    pub fn serve_device(
+        &self,
+        device: &FreenectDevice,
+        depth: bool,
+        video: bool,
+    ) -> Result<DeviceSource> {
+        let mut source = DeviceSource {
+            depth: None,
+            video: None,
+        };
+        if depth {
+            let shared = self.shared.clone();
+            source.depth = Some(device.on_depth(move |frame| shared.publish_depth(frame))?);
+        }
+        if video {
+            let shared = self.shared.clone();
+            source.video = Some(device.on_video(move |frame| {
+                let _ = shared.publish_video(frame);
+            })?);
+        }
+        Ok(source)
+    }
+
+    /// Sends the frames of a recording to all clients and returns once all are sent.
+    /// With `realtime`, frames are sent at the pace they were recorded at, otherwise as fast as possible.
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::{DeliveryPolicy, DepthFrameRef, FakenectDump, FakenectWriter};
+    /// use freenectrs::freenect::{NetClient, NetServer, ServerConfig};
+    /// use std::time::Duration;
+    ///
+    /// let dir = std::env::temp_dir().join(format!("freenectrs-net-{}", std::process::id()));
+    /// let mut writer = FakenectWriter::create(&dir).unwrap();
+    /// for timestamp in 0..3 {
+    ///     writer.write_depth(&DepthFrameRef { data: &[timestamp as u16; 4], timestamp, width: 2, height: 2 }).unwrap();
+    /// }
+    /// let dump = FakenectDump::open(&dir).unwrap();
+    ///
+    /// // No frame of the recording gets lost
+    /// let policy = DeliveryPolicy::Block { capacity: 4 };
+    /// let config = ServerConfig { policy, ..ServerConfig::default() };
+    /// let server = NetServer::bind("127.0.0.1:0", config).unwrap();
+    /// let client = NetClient::connect(server.local_addr(), policy).unwrap();
+    /// while server.clients() == 0 {
+    ///     std::thread::sleep(Duration::from_millis(1));
+    /// }
+    /// server.play_dump(&dump, false).unwrap();
+    /// for timestamp in 0..3 {
+    ///     let frame = client.depth.recv_timeout(Duration::from_secs(5)).unwrap();
+    ///     assert_eq!((frame.timestamp, frame.data), (timestamp, vec![timestamp as u16; 4]));
+    /// }
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn play_dump(&self, dump: &FakenectDump, realtime: bool) -> Result<()> {
+        let mut previous: Option<f64> = None;
+        for frame in dump.frames() {
+            let (time, frame) = frame?;
+            if let (true, Some(previous)) = (realtime, previous) {
+                if time > previous {
+                    thread::sleep(Duration::from_secs_f64(time - previous));
+                }
+            }
+            previous = Some(time);
+            self.publish(&frame)?;
+        }
+        Ok(())
+    }
+}
+
+/// Spawns a thread which writes the messages sent through the returned sender to `writer`.
+/// The thread ends when writing fails or the sender is dropped.
+fn spawn_writer(
+    writer: Arc<Mutex<TcpStream>>,
+    policy: DeliveryPolicy,
+) -> Result<FrameSender<Arc<Vec<u8>>>> {
+    let (sender, receiver) = frame_channel::<Arc<Vec<u8>>>(policy)?;
+    thread::spawn(move || {
+        while let Ok(message) = receiver.recv() {
+            if writer.lock().unwrap().write_all(&message).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(sender)
+}
+
+impl Drop for NetServer {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::SeqCst);
+        // Wakes up the accepting thread
+        let _ = TcpStream::connect(self.local_addr);
+        if let Some(joiner) = self.joiner.take() {
+            let _ = joiner.join();
+        }
+        for client in self.shared.clients.lock().unwrap().drain(..) {
+            let _ = client.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// Keeps a device serving a [`NetServer`](struct.NetServer.html). Dropping it stops its streams.
+pub struct DeviceSource {
+    depth: Option<FreenectDepthCallback>,
+    video: Option<FreenectVideoCallback>,
+}
+
+impl DeviceSource {
+    /// Returns whether depth and video frames are served
+    pub fn serves(&self) -> (bool, bool) {
+        (self.depth.is_some(), self.video.is_some())
+    }
+}
+
+/// Receives frames from a [`NetServer`](struct.NetServer.html).
+///
+/// Like with depth and video streams of a device, frames are delivered through
+/// [`FrameReceiver`](../freenect/struct.FrameReceiver.html)s. They are disconnected when the connection ends.
+pub struct NetClient {
+    pub depth: FrameReceiver<DepthFrame>,
+    pub video: FrameReceiver<VideoFrame>,
+    stream: TcpStream,
+    joiner: Option<thread::JoinHandle<()>>,
+}
+
+impl NetClient {
+    /// Connects to the server at `addr`. Frames are delivered according to `policy`.
+    pub fn connect<A: ToSocketAddrs>(addr: A, policy: DeliveryPolicy) -> Result<NetClient> {
+        let (depth_sender, depth) = frame_channel(policy)?;
+        let (video_sender, video) = frame_channel(policy)?;
+        let stream = TcpStream::connect(addr).map_err(|err| io_error("Unable to connect", err))?;
+        let _ = stream.set_nodelay(true);
+        let reader = stream
+            .try_clone()
+            .map_err(|err| io_error("Unable to connect", err))?;
+        let joiner = thread::spawn(move || {
+            let _ = receive(reader, depth_sender, video_sender);
+        });
+        Ok(NetClient {
+            depth,
+            video,
+            stream,
+            joiner: Some(joiner),
+        })
+    }
+}
+
+/// Reads messages until the connection ends or carries invalid data
+fn receive(
+    mut reader: TcpStream,
+    depth: FrameSender<DepthFrame>,
+    video: FrameSender<VideoFrame>,
+) -> Option<()> {
+    loop {
+        let mut header = [0u8; HEADER_LEN];
+        reader.read_exact(&mut header).ok()?;
+        let field =
+            |i: usize| u32::from_be_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+        let (timestamp, width, height, len) = (field(4), field(8), field(12), field(16));
+        if width as u64 * height as u64 > MAX_PIXELS as u64 || len as usize > MAX_PAYLOAD {
+            return None;
+        }
+        let (pixels, len) = (width as usize * height as usize, len as usize);
+        let mut payload = vec![0u8; len];
+        reader.read_exact(&mut payload).ok()?;
+        let sent = match (header[0], header[1]) {
+            (KIND_DEPTH, ENCODING_RAW) => {
+                if len != 2 * pixels {
+                    return None;
+                }
+                let data = payload
+                    .chunks_exact(2)
+                    .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                    .collect();
+                depth
+                    .send(DepthFrame {
+                        data,
+                        timestamp,
+                        width,
+                        height,
+                    })
+                    .is_ok()
+            }
+            (KIND_DEPTH, ENCODING_DELTA) => {
+                let data = decompress_depth(&payload, pixels)?;
+                depth
+                    .send(DepthFrame {
+                        data,
+                        timestamp,
+                        width,
+                        height,
+                    })
+                    .is_ok()
+            }
+            (KIND_VIDEO, ENCODING_RAW) => {
+                // Bayer and 8-bit IR have 1 byte per pixel, YUV and 10-bit IR 2 and rgb 3
+                if ![pixels, 2 * pixels, 3 * pixels].contains(&len) {
+                    return None;
+                }
+                video
+                    .send(VideoFrame {
+                        data: payload,
+                        timestamp,
+                        width,
+                        height,
+                    })
+                    .is_ok()
+            }
+            (KIND_VIDEO, ENCODING_JPEG) => {
+                // The image is only decoded if it has the size of the frame
+                let reader = || {
+                    image::io::Reader::with_format(
+                        io::Cursor::new(&payload),
+                        image::ImageFormat::Jpeg,
+                    )
+                };
+                if reader().into_dimensions().ok()? != (width, height) {
+                    return None;
+                }
+                let data = reader().decode().ok()?.to_rgb8().into_raw();
+                video
+                    .send(VideoFrame {
+                        data,
+                        timestamp,
+                        width,
+                        height,
+                    })
+                    .is_ok()
+            }
+            _ => return None,
+        };
+        if !sent {
+            // The client is being dropped
+            return Some(());
+        }
+    }
+}
+
+impl Drop for NetClient {
+    fn drop(&mut self) {
+        // Release the reading thread if it waits for space in a blocking channel
+        self.depth.close();
+        self.video.close();
+        let _ = self.stream.shutdown(Shutdown::Both);
+        if let Some(joiner) = self.joiner.take() {
+            let _ = joiner.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::RecvTimeoutError;
+
+    /// Connects a client to a fake server which sends `messages` and keeps the connection open
+    fn receive_messages(messages: &[Vec<u8>]) -> (NetClient, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client =
+            NetClient::connect(listener.local_addr().unwrap(), DeliveryPolicy::default()).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        for message in messages {
+            stream.write_all(message).unwrap();
+        }
+        (client, stream)
+    }
+
+    fn header(kind: u8, encoding: u8, width: u32, height: u32, len: u32) -> Vec<u8> {
+        let mut header = message(kind, encoding, 0, width, height, &[]);
+        header[16..20].copy_from_slice(&len.to_be_bytes());
+        header
+    }
+
+    /// Returns whether the client closed the connection before receiving a depth frame
+    fn refused(client: &NetClient) -> bool {
+        matches!(
+            client.depth.recv_timeout(Duration::from_secs(5)),
+            Err(RecvTimeoutError::Disconnected)
+        )
+    }
+
+    #[test]
+    fn oversized_frames_close_the_connection() {
+        let (client, _server) =
+            receive_messages(&[header(KIND_DEPTH, ENCODING_RAW, 2, 2, u32::MAX)]);
+        assert!(refused(&client));
+        let (client, _server) =
+            receive_messages(&[header(KIND_DEPTH, ENCODING_DELTA, 65535, 65535, 1), vec![0]]);
+        assert!(refused(&client));
+    }
+
+    #[test]
+    fn depth_beyond_u16_closes_the_connection() {
+        let depth = [0, 1000, 65535, 0];
+        let frame = message(KIND_DEPTH, ENCODING_DELTA, 0, 2, 2, &compress_depth(&depth));
+        let (client, _server) = receive_messages(&[frame]);
+        let received = client.depth.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(received.data, depth);
+
+        // -1 from the initial 0, +1 from 65535 and i32::MAX from 65535
+        let invalid: [&[u8]; 3] = [
+            &[1],
+            &[0xfe, 0xff, 0x07, 2],
+            &[0xfe, 0xff, 0x07, 0xfe, 0xff, 0xff, 0xff, 0x0f],
+        ];
+        for payload in invalid {
+            let frame = message(KIND_DEPTH, ENCODING_DELTA, 0, 2, 1, payload);
+            let (client, _server) = receive_messages(&[frame]);
+            assert!(refused(&client));
+        }
+    }
+
+    #[test]
+    fn clients_beyond_the_limit_are_disconnected() {
+        let config = ServerConfig {
+            max_clients: 1,
+            ..ServerConfig::default()
+        };
+        let server = NetServer::bind("127.0.0.1:0", config).unwrap();
+        let first = NetClient::connect(server.local_addr(), DeliveryPolicy::default()).unwrap();
+        while server.clients() == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let second = NetClient::connect(server.local_addr(), DeliveryPolicy::default()).unwrap();
+        assert!(refused(&second));
+        assert_eq!(server.clients(), 1);
+        server.publish_depth(&DepthFrameRef {
+            data: &[7; 4],
+            timestamp: 1,
+            width: 2,
+            height: 2,
+        });
+        let received = first.depth.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(received.data, vec![7; 4]);
+    }
+
+    #[test]
+    fn payloads_must_match_the_frame_size() {
+        let frame = message(KIND_DEPTH, ENCODING_RAW, 0, 2, 1, &[1, 0, 2, 0]);
+        let (client, _server) = receive_messages(std::slice::from_ref(&frame));
+        let received = client.depth.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(received.data, vec![1, 2]);
+
+        let short = message(KIND_DEPTH, ENCODING_RAW, 0, 2, 1, &[1, 0]);
+        let (client, _server) = receive_messages(&[short, frame]);
+        assert!(refused(&client));
+
+        let video = message(KIND_VIDEO, ENCODING_RAW, 0, 2, 1, &[0; 5]);
+        let (client, _server) = receive_messages(&[video]);
+        assert!(refused(&client));
+    }
+}
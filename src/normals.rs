@@ -0,0 +1,293 @@
+//! Surface normals of organized depth, enabled by the `nalgebra` feature.
+use crate::freenect::{
+    CameraIntrinsics, DepthFrame, DepthFrameRef, FreenectDepthFormat, FreenectError, Result,
+};
+use crate::visualize;
+#[cfg(feature = "image")]
+use image::RgbImage;
+use nalgebra::{Point3, Vector3};
+
+/// How a [`NormalMap`](struct.NormalMap.html) is computed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NormalMethod {
+    /// The cross product of the differences between the horizontal and the vertical neighbours
+    /// of each pixel. Fast and sharp at edges, but noisy.
+    CrossProduct,
+    /// The cross product of the neighbour differences averaged over a square of `radius` pixels
+    /// using integral images, which costs the same for any radius. Smooth on noisy depth.
+    IntegralImage { radius: u32 },
+}
+
+/// The settings of [`NormalMap::from_points()`](struct.NormalMap.html#method.from_points)
+#[derive(Clone, Copy, Debug)]
+pub struct NormalConfig {
+    pub method: NormalMethod,
+    /// Neighbours whose depth differs by more than this fraction of a pixel's depth lie across an
+    /// edge and are ignored
+    pub max_depth_change: f32,
+    /// Flips normals which point away from the camera
+    pub orient_to_camera: bool,
+}
+
+impl Default for NormalConfig {
+    /// Integral images over a radius of 4 pixels, edges at depth changes of 5 % and normals
+    /// oriented towards the camera
+    fn default() -> NormalConfig {
+        NormalConfig {
+            method: NormalMethod::IntegralImage { radius: 4 },
+            max_depth_change: 0.05,
+            orient_to_camera: true,
+        }
+    }
+}
+
+/// The unit normals of a frame, row by row. Pixels without a normal are the zero vector.
+///
+/// Normals are given in the camera frame: x points right, y down and z forward, so surfaces
+/// facing the camera have a negative z.
+/// # Examples
+/// ```rust
+/// use freenectrs::freenect::{
+///     CameraIntrinsics, DepthFrameRef, FreenectDepthFormat, NormalConfig, NormalMethod,
+/// };
+///
+/// // A wall 1 m in front of the camera, with a hole
+/// let mut data = [1000; 5 * 5];
+/// data[12] = 0;
+/// let frame = DepthFrameRef { data: &data, timestamp: 0, width: 5, height: 5 };
+/// let intrinsics = CameraIntrinsics { fx: 5.0, fy: 5.0, cx: 2.0, cy: 2.0 };
+/// for &method in &[NormalMethod::CrossProduct, NormalMethod::IntegralImage { radius: 1 }] {
+///     let config = NormalConfig { method, ..NormalConfig::default() };
+///     let normals = frame.normals(&intrinsics, FreenectDepthFormat::MM, &config).unwrap();
+///     let normal = normals.get(1, 1).unwrap();
+///     assert!((normal.z + 1.0).abs() < 1e-6);
+///     assert!(normals.get(2, 2).is_none());
+///     // The normal (0, 0, -1) is drawn as (128, 128, 0)
+///     assert_eq!(&normals.to_rgb()[3 * 6..3 * 7], &[128, 128, 0]);
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct NormalMap {
+    pub normals: Vec<Vector3<f32>>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Returns whether two neighbours lie on the same surface
+fn continuous(point: &Point3<f32>, neighbour: &Point3<f32>, max_depth_change: f32) -> bool {
+    (neighbour.z - point.z).abs() <= max_depth_change * point.z
+}
+
+/// Returns the difference of the neighbours before and after a pixel, or of the pixel and the one
+/// neighbour on its surface
+fn difference(
+    point: &Point3<f32>,
+    before: Option<&Point3<f32>>,
+    after: Option<&Point3<f32>>,
+    max_depth_change: f32,
+) -> Option<Vector3<f32>> {
+    let before = before.filter(|before| continuous(point, before, max_depth_change));
+    let after = after.filter(|after| continuous(point, after, max_depth_change));
+    visualize::difference(*point, before.copied(), after.copied())
+}
+
+/// Sums `values` and counts those which are set over all pixels above and left of each corner,
+/// in a table of (width + 1) × (height + 1) corners
+fn integral(
+    values: &[Option<Vector3<f32>>],
+    width: usize,
+    height: usize,
+) -> Vec<(Vector3<f64>, u32)> {
+    let mut table = vec![(Vector3::zeros(), 0); (width + 1) * (height + 1)];
+    for y in 0..height {
+        let (mut row_sum, mut row_count) = (Vector3::zeros(), 0);
+        for x in 0..width {
+            if let Some(value) = values[y * width + x] {
+                row_sum += value.cast::<f64>();
+                row_count += 1;
+            }
+            let above = table[y * (width + 1) + x + 1];
+            table[(y + 1) * (width + 1) + x + 1] = (above.0 + row_sum, above.1 + row_count);
+        }
+    }
+    table
+}
+
+/// Returns the mean of the values set within `radius` of `(x, y)`, using their integral table
+fn area_mean(
+    table: &[(Vector3<f64>, u32)],
+    (width, height): (usize, usize),
+    (x, y): (usize, usize),
+    radius: usize,
+) -> Option<Vector3<f32>> {
+    let (left, top) = (x.saturating_sub(radius), y.saturating_sub(radius));
+    let (right, bottom) = ((x + radius + 1).min(width), (y + radius + 1).min(height));
+    let corner = |x: usize, y: usize| table[y * (width + 1) + x];
+    let (a, b, c, d) = (
+        corner(left, top),
+        corner(right, top),
+        corner(left, bottom),
+        corner(right, bottom),
+    );
+    let count = d.1 + a.1 - b.1 - c.1;
+    if count == 0 {
+        return None;
+    }
+    Some(((d.0 - b.0 - c.0 + a.0) / count as f64).cast::<f32>())
+}
+
+impl NormalMap {
+    /// Computes the normals of organized points, as returned by
+    /// [`DepthFrameRef::to_organized_points()`](struct.DepthFrameRef.html#method.to_organized_points).
+    /// Pixels without a point or without neighbours on their surface get no normal.
+    /// Fails if the number of points doesn't match `width` and `height`.
+    pub fn from_points(
+        points: &[Option<Point3<f32>>],
+        width: u32,
+        height: u32,
+        config: &NormalConfig,
+    ) -> Result<NormalMap> {
+        let (w, h) = (width as usize, height as usize);
+        if points.len() != w * h {
+            return Err(FreenectError::new(
+                "Points don't match the size of the frame",
+            ));
+        }
+        let point = |x: usize, y: usize| points[y * w + x].as_ref();
+        // The tangents along rows and columns
+        let mut horizontal = vec![None; points.len()];
+        let mut vertical = vec![None; points.len()];
+        for y in 0..h {
+            for x in 0..w {
+                let center = match point(x, y) {
+                    Some(center) => center,
+                    None => continue,
+                };
+                let i = y * w + x;
+                horizontal[i] = difference(
+                    center,
+                    x.checked_sub(1).and_then(|x| point(x, y)),
+                    (x + 1 < w).then(|| point(x + 1, y)).flatten(),
+                    config.max_depth_change,
+                );
+                vertical[i] = difference(
+                    center,
+                    y.checked_sub(1).and_then(|y| point(x, y)),
+                    (y + 1 < h).then(|| point(x, y + 1)).flatten(),
+                    config.max_depth_change,
+                );
+            }
+        }
+        let sums = match config.method {
+            NormalMethod::CrossProduct => None,
+            NormalMethod::IntegralImage { radius } => Some((
+                radius as usize,
+                integral(&horizontal, w, h),
+                integral(&vertical, w, h),
+            )),
+        };
+        let mut normals = vec![Vector3::zeros(); points.len()];
+        for y in 0..h {
+            for x in 0..w {
+                let center = match point(x, y) {
+                    Some(center) => center,
+                    None => continue,
+                };
+                let i = y * w + x;
+                let tangents = match &sums {
+                    None => horizontal[i].zip(vertical[i]),
+                    Some((radius, horizontal_sums, vertical_sums)) => area_mean(
+                        horizontal_sums,
+                        (w, h),
+                        (x, y),
+                        *radius,
+                    )
+                    .zip(area_mean(vertical_sums, (w, h), (x, y), *radius)),
+                };
+                let (horizontal, vertical) = match tangents {
+                    Some(tangents) => tangents,
+                    None => continue,
+                };
+                // Rows go right and columns down, so this points towards the camera for surfaces seen from the front
+                let normal = match vertical.cross(&horizontal).try_normalize(f32::EPSILON) {
+                    Some(normal) => normal,
+                    None => continue,
+                };
+                normals[i] = if config.orient_to_camera && normal.dot(&center.coords) > 0.0 {
+                    -normal
+                } else {
+                    normal
+                };
+            }
+        }
+        Ok(NormalMap {
+            normals,
+            width,
+            height,
+        })
+    }
+
+    /// Returns the normal at pixel `(x, y)`, or `None` if the pixel has none or lies outside
+    pub fn get(&self, x: u32, y: u32) -> Option<Vector3<f32>> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let normal = *self.normals.get((y * self.width + x) as usize)?;
+        if normal == Vector3::zeros() {
+            None
+        } else {
+            Some(normal)
+        }
+    }
+
+    /// Draws the normals as 8-bit rgb, mapping each component from -1 to 1 onto 0 to 255.
+    /// Pixels without a normal are black.
+    pub fn to_rgb(&self) -> Vec<u8> {
+        let mut rgb = Vec::with_capacity(self.normals.len() * 3);
+        for normal in &self.normals {
+            if *normal == Vector3::zeros() {
+                rgb.extend_from_slice(&[0, 0, 0]);
+            } else {
+                rgb.extend(
+                    normal
+                        .iter()
+                        .map(|&component| ((component + 1.0) / 2.0 * 255.0).round() as u8),
+                );
+            }
+        }
+        rgb
+    }
+
+    /// Draws the normals as an image, see [`to_rgb()`](#method.to_rgb). Returns `None` if the
+    /// number of normals doesn't match `width` and `height`.
+    #[cfg(feature = "image")]
+    pub fn to_rgb_image(&self) -> Option<RgbImage> {
+        RgbImage::from_raw(self.width, self.height, self.to_rgb())
+    }
+}
+
+impl<'a> DepthFrameRef<'a> {
+    /// Computes the normals of the frame, see [`NormalMap`](struct.NormalMap.html).
+    /// Fails for formats [`to_points()`](#method.to_points) can't convert.
+    pub fn normals(
+        &self,
+        intrinsics: &CameraIntrinsics,
+        format: FreenectDepthFormat,
+        config: &NormalConfig,
+    ) -> Result<NormalMap> {
+        let points = self.to_organized_points(intrinsics, format)?;
+        NormalMap::from_points(&points, self.width, self.height, config)
+    }
+}
+
+impl DepthFrame {
+    /// Computes the normals of the frame, see [`DepthFrameRef::normals()`](struct.DepthFrameRef.html#method.normals)
+    pub fn normals(
+        &self,
+        intrinsics: &CameraIntrinsics,
+        format: FreenectDepthFormat,
+        config: &NormalConfig,
+    ) -> Result<NormalMap> {
+        self.as_frame_ref().normals(intrinsics, format, config)
+    }
+}
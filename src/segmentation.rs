@@ -0,0 +1,392 @@
+//! Separating foreground from a learned background and finding blobs in it.
+//!
+//! Everything here only depends on the frames fed in, so recorded or synthetic frames always
+//! give the same results.
+use crate::freenect::{DepthFrame, DepthFrameRef, FreenectDepthFormat, FreenectError, Result};
+
+/// The settings of a [`BackgroundModel`](struct.BackgroundModel.html)
+#[derive(Clone, Copy, Debug)]
+pub struct BackgroundConfig {
+    /// How many frames the background is averaged from. The scene should be empty meanwhile.
+    pub learning_frames: u32,
+    /// How far the background follows each later frame where it is visible, from 0 to 1.
+    /// 0 keeps the learned background, small values adapt to slow changes like moved furniture.
+    /// Pixels without a background take their first reading in any case.
+    pub adaptation: f32,
+    /// How much nearer than the background a reading has to be to count as foreground, in the
+    /// units of the frame
+    pub threshold: u16,
+    /// How much neighbouring foreground pixels may differ in depth to belong to the same blob,
+    /// which keeps touching people apart
+    pub max_step: u16,
+    /// The fewest pixels a blob has, smaller ones are dropped as noise
+    pub min_blob_pixels: usize,
+    /// The format of the frames, which tells readings apart from pixels without one
+    pub format: FreenectDepthFormat,
+}
+
+impl Default for BackgroundConfig {
+    /// For frames in millimeters, one second of learning at 30 frames per second, no adaptation,
+    /// a threshold of 100, steps of up to 50 and blobs of at least 50 pixels
+    fn default() -> BackgroundConfig {
+        BackgroundConfig {
+            learning_frames: 30,
+            adaptation: 0.0,
+            threshold: 100,
+            max_step: 50,
+            min_blob_pixels: 50,
+            format: FreenectDepthFormat::MM,
+        }
+    }
+}
+
+/// A connected area of foreground
+#[derive(Clone, Debug, PartialEq)]
+pub struct Blob {
+    /// The label of the blob's pixels in [`Segmentation::labels`](struct.Segmentation.html#structfield.labels)
+    pub label: u32,
+    /// The number of pixels
+    pub pixels: usize,
+    /// The mean position of the pixels
+    pub centroid: (f32, f32),
+    /// The mean depth of the pixels, in the units of the frame
+    pub depth: f32,
+    /// The top left corner of the bounding box
+    pub min: (u32, u32),
+    /// The bottom right corner of the bounding box, inclusive
+    pub max: (u32, u32),
+    /// The nearest and the farthest depth, which bound the blob along the optical axis
+    pub depth_range: (u16, u16),
+}
+
+/// The foreground of a frame and its blobs
+#[derive(Clone, Debug)]
+pub struct Segmentation {
+    /// Whether each pixel is foreground, row by row
+    pub mask: Vec<bool>,
+    /// The label of the blob each pixel belongs to, row by row. Background and pixels of dropped
+    /// blobs are 0.
+    pub labels: Vec<u32>,
+    /// The blobs, labeled from 1 in the order their first pixel appears row by row
+    pub blobs: Vec<Blob>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Learns the background of a static scene from depth frames and segments later frames into
+/// foreground blobs, for example people seen by an overhead Kinect.
+/// # Examples
+/// ```rust
+/// use freenectrs::freenect::{BackgroundConfig, BackgroundModel, DepthFrameRef};
+///
+/// let config = BackgroundConfig { learning_frames: 2, min_blob_pixels: 2, ..BackgroundConfig::default() };
+/// let mut model = BackgroundModel::new(config);
+/// let floor = [2000; 6 * 4];
+/// for _ in 0..2 {
+///     let frame = DepthFrameRef { data: &floor, timestamp: 0, width: 6, height: 4 };
+///     assert!(model.update(&frame).unwrap().is_none());
+/// }
+///
+/// // A person of 2×2 pixels and a speckle of noise
+/// let mut data = floor;
+/// for &i in &[7, 8, 13, 14] {
+///     data[i] = 1200;
+/// }
+/// data[22] = 1500;
+/// let frame = DepthFrameRef { data: &data, timestamp: 0, width: 6, height: 4 };
+/// let segmentation = model.update(&frame).unwrap().unwrap();
+/// assert_eq!(segmentation.mask.iter().filter(|&&foreground| foreground).count(), 5);
+/// assert_eq!(segmentation.blobs.len(), 1);
+/// let person = &segmentation.blobs[0];
+/// assert_eq!(person.pixels, 4);
+/// assert_eq!(person.centroid, (1.5, 1.5));
+/// assert_eq!((person.min, person.max), ((1, 1), (2, 2)));
+/// assert_eq!(person.depth, 1200.0);
+/// ```
+///
+/// Pixels which had no reading while learning have an unknown background. Their readings count as
+/// background, as nothing tells whether they are nearer than it, and the first one becomes the
+/// background of the pixel, so holes of the learned background never show up as blobs:
+/// ```rust
+/// use freenectrs::freenect::{BackgroundConfig, BackgroundModel, DepthFrameRef};
+///
+/// let config = BackgroundConfig { learning_frames: 1, min_blob_pixels: 1, ..BackgroundConfig::default() };
+/// let mut model = BackgroundModel::new(config);
+/// let mut floor = [2000; 4 * 4];
+/// // A shiny spot without readings
+/// floor[5] = 0;
+/// let frame = DepthFrameRef { data: &floor, timestamp: 0, width: 4, height: 4 };
+/// assert!(model.update(&frame).unwrap().is_none());
+/// assert_eq!(model.background().unwrap().data[5], 0);
+///
+/// // The spot reads the floor now
+/// let data = [2000; 4 * 4];
+/// let frame = DepthFrameRef { data: &data, timestamp: 0, width: 4, height: 4 };
+/// assert!(model.update(&frame).unwrap().unwrap().blobs.is_empty());
+/// assert_eq!(model.background().unwrap().data[5], 2000);
+///
+/// // And something in front of it is foreground
+/// let mut data = data;
+/// data[5] = 1000;
+/// let frame = DepthFrameRef { data: &data, timestamp: 0, width: 4, height: 4 };
+/// assert_eq!(model.update(&frame).unwrap().unwrap().blobs.len(), 1);
+/// ```
+pub struct BackgroundModel {
+    config: BackgroundConfig,
+    width: u32,
+    height: u32,
+    /// The frames learned from so far
+    learned: u32,
+    /// The sums and counts of readings while learning
+    sums: Vec<u64>,
+    counts: Vec<u32>,
+    /// The background depth of each pixel, 0 where it is unknown
+    background: Vec<f32>,
+}
+
+impl BackgroundModel {
+    pub fn new(config: BackgroundConfig) -> BackgroundModel {
+        BackgroundModel {
+            config,
+            width: 0,
+            height: 0,
+            learned: 0,
+            sums: Vec::new(),
+            counts: Vec::new(),
+            background: Vec::new(),
+        }
+    }
+
+    /// Returns the settings of the model
+    pub fn config(&self) -> BackgroundConfig {
+        self.config
+    }
+
+    /// Returns whether the background has been learned
+    pub fn is_learned(&self) -> bool {
+        self.learned >= self.config.learning_frames && !self.background.is_empty()
+    }
+
+    /// Returns the learned background, with 0 where it is unknown. Returns `None` while learning.
+    pub fn background(&self) -> Option<DepthFrame> {
+        if !self.is_learned() {
+            return None;
+        }
+        Some(DepthFrame {
+            data: self
+                .background
+                .iter()
+                .map(|&depth| depth.round() as u16)
+                .collect(),
+            timestamp: 0,
+            width: self.width,
+            height: self.height,
+        })
+    }
+
+    /// Forgets the background to learn it again
+    pub fn reset(&mut self) {
+        self.learned = 0;
+        self.sums.clear();
+        self.counts.clear();
+        self.background.clear();
+    }
+
+    /// Feeds the next frame. Returns `None` while the background is learned and the foreground
+    /// with its blobs afterwards. Fails for frames whose data doesn't match their size and for
+    /// frames of another size than the first one.
+    pub fn update(&mut self, frame: &DepthFrameRef) -> Result<Option<Segmentation>> {
+        let pixels = frame.width as usize * frame.height as usize;
+        if frame.data.len() != pixels {
+            return Err(FreenectError::new("Depth frame has the wrong size"));
+        }
+        if self.learned == 0 && self.background.is_empty() {
+            self.width = frame.width;
+            self.height = frame.height;
+            self.sums = vec![0; pixels];
+            self.counts = vec![0; pixels];
+        } else if (frame.width, frame.height) != (self.width, self.height) {
+            return Err(FreenectError::new(format!(
+                "Depth frame of {}×{} doesn't match the background of {}×{}",
+                frame.width, frame.height, self.width, self.height
+            )));
+        }
+        if !self.is_learned() {
+            self.learn(frame);
+            return Ok(None);
+        }
+        let mask: Vec<bool> = frame
+            .data
+            .iter()
+            .zip(&self.background)
+            .map(|(&value, &background)| self.is_foreground(value, background))
+            .collect();
+        self.adapt(frame, &mask);
+        let (labels, blobs) = label_blobs(
+            frame,
+            &mask,
+            self.config.max_step,
+            self.config.min_blob_pixels,
+        );
+        Ok(Some(Segmentation {
+            mask,
+            labels,
+            blobs,
+            width: frame.width,
+            height: frame.height,
+        }))
+    }
+
+    fn learn(&mut self, frame: &DepthFrameRef) {
+        for ((&value, sum), count) in frame.data.iter().zip(&mut self.sums).zip(&mut self.counts) {
+            if self.config.format.is_reading(value) {
+                *sum += value as u64;
+                *count += 1;
+            }
+        }
+        self.learned += 1;
+        if self.learned >= self.config.learning_frames {
+            self.background = self
+                .sums
+                .iter()
+                .zip(&self.counts)
+                .map(|(&sum, &count)| {
+                    if count == 0 {
+                        0.0
+                    } else {
+                        sum as f32 / count as f32
+                    }
+                })
+                .collect();
+            self.sums = Vec::new();
+            self.counts = Vec::new();
+        }
+    }
+
+    /// Readings nearer than the background are foreground. Where the background is unknown, a
+    /// reading can't be compared and counts as background.
+    fn is_foreground(&self, value: u16, background: f32) -> bool {
+        self.config.format.is_reading(value)
+            && background != 0.0
+            && background - value as f32 > self.config.threshold as f32
+    }
+
+    /// Learns the background of pixels which were unknown from their first reading and moves the
+    /// background towards the readings where it is visible
+    fn adapt(&mut self, frame: &DepthFrameRef, mask: &[bool]) {
+        let adaptation = self.config.adaptation;
+        for ((&value, background), &foreground) in
+            frame.data.iter().zip(&mut self.background).zip(mask)
+        {
+            if !self.config.format.is_reading(value) {
+                continue;
+            }
+            if *background == 0.0 {
+                *background = value as f32;
+            } else if adaptation > 0.0 && !foreground {
+                *background += adaptation * (value as f32 - *background);
+            }
+        }
+    }
+}
+
+/// Returns the root of `i` in the forest of `parents`, shortening the path on the way
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+/// Labels the 8-connected areas of `mask` whose neighbouring depths differ by at most `max_step`
+fn label_blobs(
+    frame: &DepthFrameRef,
+    mask: &[bool],
+    max_step: u16,
+    min_pixels: usize,
+) -> (Vec<u32>, Vec<Blob>) {
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let mut parents: Vec<usize> = (0..mask.len()).collect();
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            if !mask[i] {
+                continue;
+            }
+            // The neighbours which were visited before
+            let neighbours = [
+                (x > 0).then(|| i - 1),
+                (x > 0 && y > 0).then(|| i - width - 1),
+                (y > 0).then(|| i - width),
+                (x + 1 < width && y > 0).then(|| i - width + 1),
+            ];
+            for neighbour in neighbours.iter().flatten().copied() {
+                if mask[neighbour] && frame.data[neighbour].abs_diff(frame.data[i]) <= max_step {
+                    let (a, b) = (find(&mut parents, i), find(&mut parents, neighbour));
+                    // Joining under the smaller root keeps labels independent of the visiting order
+                    parents[a.max(b)] = a.min(b);
+                }
+            }
+        }
+    }
+
+    // Collects the blobs in the order of their first pixel
+    let mut blob_of_root = vec![usize::MAX; mask.len()];
+    let mut sums: Vec<(Blob, f64, f64, f64)> = Vec::new();
+    let mut indices = vec![usize::MAX; mask.len()];
+    for i in 0..mask.len() {
+        if !mask[i] {
+            continue;
+        }
+        let root = find(&mut parents, i);
+        if blob_of_root[root] == usize::MAX {
+            blob_of_root[root] = sums.len();
+            sums.push((
+                Blob {
+                    label: 0,
+                    pixels: 0,
+                    centroid: (0.0, 0.0),
+                    depth: 0.0,
+                    min: (u32::MAX, u32::MAX),
+                    max: (0, 0),
+                    depth_range: (u16::MAX, 0),
+                },
+                0.0,
+                0.0,
+                0.0,
+            ));
+        }
+        let index = blob_of_root[root];
+        indices[i] = index;
+        let (x, y, depth) = ((i % width) as u32, (i / width) as u32, frame.data[i]);
+        let (blob, sum_x, sum_y, sum_depth) = &mut sums[index];
+        blob.pixels += 1;
+        blob.min = (blob.min.0.min(x), blob.min.1.min(y));
+        blob.max = (blob.max.0.max(x), blob.max.1.max(y));
+        blob.depth_range = (blob.depth_range.0.min(depth), blob.depth_range.1.max(depth));
+        *sum_x += x as f64;
+        *sum_y += y as f64;
+        *sum_depth += depth as f64;
+    }
+
+    // Drops small blobs and labels the others from 1
+    let mut labels_of_index = vec![0; sums.len()];
+    let mut blobs = Vec::new();
+    for (index, (mut blob, sum_x, sum_y, sum_depth)) in sums.into_iter().enumerate() {
+        if blob.pixels < min_pixels {
+            continue;
+        }
+        let pixels = blob.pixels as f64;
+        blob.label = blobs.len() as u32 + 1;
+        blob.centroid = ((sum_x / pixels) as f32, (sum_y / pixels) as f32);
+        blob.depth = (sum_depth / pixels) as f32;
+        labels_of_index[index] = blob.label;
+        blobs.push(blob);
+    }
+    let labels = indices
+        .iter()
+        .map(|&index| labels_of_index.get(index).copied().unwrap_or(0))
+        .collect();
+    (labels, blobs)
+}
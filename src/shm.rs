@@ -0,0 +1,638 @@
+//! Sharing frames with other processes through POSIX shared memory.
+//!
+//! Subscribers either copy a frame, which tells whether it was overwritten meanwhile, or pin its
+//! slot to read the frame in place. The publisher skips pinned slots.
+use crate::freenect::{
+    DepthFrame, DepthFrameRef, FreenectError, Result, VideoFrame, VideoFrameRef,
+};
+use std::ffi::CString;
+use std::mem;
+use std::ops::Deref;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{fence, AtomicU32, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MAGIC: u32 = 0x4b4e_5348;
+const VERSION: u32 = 2;
+// Keeps the slots aligned, so depth data can be read as u16 in place
+const HEADER_SIZE: usize = 64;
+
+#[repr(C)]
+struct Header {
+    magic: AtomicU32,
+    version: AtomicU32,
+    slot_count: AtomicU32,
+    slot_size: AtomicU32,
+    // The sequence number of the last complete frame, 0 if there is none
+    latest: AtomicU64,
+}
+
+#[repr(C)]
+struct Slot {
+    // Odd while the slot is written
+    version: AtomicU64,
+    sequence: AtomicU64,
+    kind: AtomicU32,
+    timestamp: AtomicU32,
+    width: AtomicU32,
+    height: AtomicU32,
+    len: AtomicU32,
+    // The subscribers reading the slot in place, which the publisher doesn't overwrite
+    readers: AtomicU32,
+}
+
+/// What kind of frame a slot holds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShmFrameKind {
+    /// Depth values as native-endian `u16`
+    Depth,
+    /// Pixel bytes of a video frame
+    Video,
+}
+
+impl ShmFrameKind {
+    fn to_raw(self) -> u32 {
+        match self {
+            ShmFrameKind::Depth => 1,
+            ShmFrameKind::Video => 2,
+        }
+    }
+
+    fn from_raw(raw: u32) -> Option<ShmFrameKind> {
+        match raw {
+            1 => Some(ShmFrameKind::Depth),
+            2 => Some(ShmFrameKind::Video),
+            _ => None,
+        }
+    }
+}
+
+fn slot_stride(slot_size: usize) -> usize {
+    mem::size_of::<Slot>() + slot_size.next_multiple_of(8)
+}
+
+fn shm_name(name: &str) -> Result<CString> {
+    let name = if name.starts_with('/') {
+        name.to_owned()
+    } else {
+        format!("/{}", name)
+    };
+    CString::new(name).map_err(|_| FreenectError::new("Shared memory name contains a nul byte"))
+}
+
+fn os_error(what: &str) -> FreenectError {
+    FreenectError::new(format!("{}: {}", what, std::io::Error::last_os_error()))
+}
+
+// Frame data is copied in words of 8 bytes. Slot data starts at a multiple of 8 and is padded to one.
+const WORD: usize = mem::size_of::<u64>();
+
+/// Copies `src` into the slot data at `dst` with atomic stores, so readers never race with the copy
+unsafe fn store_shared(dst: *mut u8, src: &[u8]) {
+    let words = dst as *const AtomicU64;
+    for (i, chunk) in src.chunks(WORD).enumerate() {
+        let mut bytes = [0; WORD];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        (*words.add(i)).store(u64::from_ne_bytes(bytes), Ordering::Relaxed);
+    }
+}
+
+/// Copies the slot data at `src` into `dst` with atomic loads, so the copy never races with a
+/// publisher. The copy is only consistent if the slot's version didn't change meanwhile.
+unsafe fn load_shared(src: *const u8, dst: &mut [u8]) {
+    let words = src as *const AtomicU64;
+    for (i, chunk) in dst.chunks_mut(WORD).enumerate() {
+        let bytes = (*words.add(i)).load(Ordering::Relaxed).to_ne_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
+
+/// A mapped shared memory segment
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// The segment is only accessed through atomics and the seqlock protocol of the slots
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    fn header(&self) -> &Header {
+        unsafe { &*(self.ptr as *const Header) }
+    }
+
+    fn slot_count(&self) -> u64 {
+        self.header().slot_count.load(Ordering::Relaxed) as u64
+    }
+
+    fn slot_size(&self) -> usize {
+        self.header().slot_size.load(Ordering::Relaxed) as usize
+    }
+
+    /// Returns the slot which holds the frame with the given sequence number
+    fn slot(&self, sequence: u64) -> (&Slot, *mut u8) {
+        let index = ((sequence - 1) % self.slot_count()) as usize;
+        unsafe {
+            let slot = self
+                .ptr
+                .add(HEADER_SIZE + index * slot_stride(self.slot_size()));
+            (&*(slot as *const Slot), slot.add(mem::size_of::<Slot>()))
+        }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+/// Publishes frames into a ring of slots in POSIX shared memory, which
+/// [`ShmSubscriber`](struct.ShmSubscriber.html)s of other processes can read.
+///
+/// Every frame gets a sequence number, starting at 1. The publisher removes the segment when dropped;
+/// subscribers which have it opened can keep reading.
+///
+/// Slots pinned by subscribers are skipped together with their sequence number, so the numbers of
+/// published frames may have gaps. Publishing fails while all slots are pinned. A subscriber which
+/// crashed while pinning a slot keeps it pinned until the segment is created again.
+/// # Examples
+/// ```rust
+/// use freenectrs::freenect::{DepthFrameRef, ShmFrameKind, ShmPublisher, ShmSubscriber};
+///
+/// let name = format!("/freenectrs-doc-{}", std::process::id());
+/// let mut publisher = ShmPublisher::create(&name, 3, 8).unwrap();
+/// let subscriber = ShmSubscriber::open(&name).unwrap();
+/// assert!(subscriber.latest().is_none());
+///
+/// for timestamp in 0..4 {
+///     let data = [timestamp as u16; 4];
+///     let frame = DepthFrameRef { data: &data, timestamp, width: 2, height: 2 };
+///     publisher.publish_depth(&frame).unwrap();
+/// }
+/// let latest = subscriber.latest().unwrap();
+/// assert_eq!((latest.sequence, latest.kind, latest.timestamp), (4, ShmFrameKind::Depth, 3));
+/// let mut data = Vec::new();
+/// assert!(latest.copy_into(&mut data));
+/// assert_eq!(data, [3u16; 4].iter().flat_map(|v| v.to_ne_bytes()).collect::<Vec<u8>>());
+/// assert!(latest.is_valid());
+///
+/// // The ring holds the last three frames
+/// assert!(subscriber.get(1).is_none());
+/// let copy = subscriber.get(2).unwrap().to_owned().unwrap();
+/// assert_eq!(copy.to_depth_frame().unwrap().data, vec![1; 4]);
+///
+/// // Too large for a slot
+/// let frame = DepthFrameRef { data: &[0; 5], timestamp: 0, width: 5, height: 1 };
+/// assert!(publisher.publish_depth(&frame).is_err());
+/// ```
+pub struct ShmPublisher {
+    mapping: Mapping,
+    name: CString,
+    sequence: u64,
+}
+
+impl ShmPublisher {
+    /// Creates the segment `name` with `slot_count` slots, each holding a frame of up to `slot_size` bytes.
+    /// An existing segment of this name is replaced.
+    pub fn create(name: &str, slot_count: u32, slot_size: usize) -> Result<ShmPublisher> {
+        if slot_count == 0 || slot_size > u32::MAX as usize {
+            return Err(FreenectError::new("Invalid shared memory ring size"));
+        }
+        let name = shm_name(name)?;
+        let len = HEADER_SIZE + slot_count as usize * slot_stride(slot_size);
+        unsafe {
+            libc::shm_unlink(name.as_ptr());
+            let fd = libc::shm_open(
+                name.as_ptr(),
+                libc::O_CREAT | libc::O_EXCL | libc::O_RDWR,
+                0o600,
+            );
+            if fd < 0 {
+                return Err(os_error("Unable to create shared memory"));
+            }
+            if libc::ftruncate(fd, len as libc::off_t) < 0 {
+                let err = os_error("Unable to resize shared memory");
+                libc::close(fd);
+                libc::shm_unlink(name.as_ptr());
+                return Err(err);
+            }
+            let ptr = libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            );
+            libc::close(fd);
+            if ptr == libc::MAP_FAILED {
+                let err = os_error("Unable to map shared memory");
+                libc::shm_unlink(name.as_ptr());
+                return Err(err);
+            }
+            let mapping = Mapping {
+                ptr: ptr as *mut u8,
+                len,
+            };
+            // The segment starts zeroed, so the header is written last
+            let header = mapping.header();
+            header.slot_count.store(slot_count, Ordering::Relaxed);
+            header.slot_size.store(slot_size as u32, Ordering::Relaxed);
+            header.version.store(VERSION, Ordering::Relaxed);
+            header.magic.store(MAGIC, Ordering::Release);
+            Ok(ShmPublisher {
+                mapping,
+                name,
+                sequence: 0,
+            })
+        }
+    }
+
+    /// Writes a frame into the next slot which isn't pinned and returns its sequence number.
+    /// Fails if `data` is larger than a slot or all slots are pinned.
+    pub fn publish(
+        &mut self,
+        kind: ShmFrameKind,
+        timestamp: u32,
+        width: u32,
+        height: u32,
+        data: &[u8],
+    ) -> Result<u64> {
+        if data.len() > self.mapping.slot_size() {
+            return Err(FreenectError::new(format!(
+                "Frame of {} bytes doesn't fit into a slot of {} bytes",
+                data.len(),
+                self.mapping.slot_size()
+            )));
+        }
+        let mut sequence = self.sequence + 1;
+        let (slot, slot_data, version) = loop {
+            let (slot, slot_data) = self.mapping.slot(sequence);
+            let version = slot.version.load(Ordering::Relaxed);
+            // Either the publisher sees the reader, or the reader sees the changed version
+            slot.version.store(version + 1, Ordering::SeqCst);
+            if slot.readers.load(Ordering::SeqCst) == 0 {
+                break (slot, slot_data, version);
+            }
+            // The slot keeps its frame, which is still valid
+            slot.version.store(version, Ordering::Release);
+            if sequence - self.sequence >= self.mapping.slot_count() {
+                return Err(FreenectError::new("All slots are pinned by subscribers"));
+            }
+            sequence += 1;
+        };
+        fence(Ordering::Release);
+        slot.sequence.store(sequence, Ordering::Relaxed);
+        slot.kind.store(kind.to_raw(), Ordering::Relaxed);
+        slot.timestamp.store(timestamp, Ordering::Relaxed);
+        slot.width.store(width, Ordering::Relaxed);
+        slot.height.store(height, Ordering::Relaxed);
+        slot.len.store(data.len() as u32, Ordering::Relaxed);
+        unsafe {
+            store_shared(slot_data, data);
+        }
+        slot.version.store(version + 2, Ordering::Release);
+        self.mapping
+            .header()
+            .latest
+            .store(sequence, Ordering::Release);
+        self.sequence = sequence;
+        Ok(sequence)
+    }
+
+    /// Publishes a depth frame, for example from within an [`on_depth()`][on_depth] handler
+    ///
+    /// [on_depth]: struct.FreenectDevice.html#method.on_depth
+    pub fn publish_depth(&mut self, frame: &DepthFrameRef) -> Result<u64> {
+        let bytes = unsafe {
+            slice::from_raw_parts(frame.data.as_ptr() as *const u8, frame.data.len() * 2)
+        };
+        self.publish(
+            ShmFrameKind::Depth,
+            frame.timestamp,
+            frame.width,
+            frame.height,
+            bytes,
+        )
+    }
+
+    /// Publishes a video frame, for example from within an [`on_video()`][on_video] handler
+    ///
+    /// [on_video]: struct.FreenectDevice.html#method.on_video
+    pub fn publish_video(&mut self, frame: &VideoFrameRef) -> Result<u64> {
+        self.publish(
+            ShmFrameKind::Video,
+            frame.timestamp,
+            frame.width,
+            frame.height,
+            frame.data,
+        )
+    }
+}
+
+impl Drop for ShmPublisher {
+    fn drop(&mut self) {
+        unsafe {
+            libc::shm_unlink(self.name.as_ptr());
+        }
+    }
+}
+
+/// Reads the frames of a [`ShmPublisher`](struct.ShmPublisher.html), usually in another process.
+pub struct ShmSubscriber {
+    mapping: Mapping,
+}
+
+impl ShmSubscriber {
+    /// Opens the segment `name` created by a publisher. It is opened for writing, since subscribers
+    /// count themselves as readers of the slots they pin.
+    pub fn open(name: &str) -> Result<ShmSubscriber> {
+        let name = shm_name(name)?;
+        unsafe {
+            let fd = libc::shm_open(name.as_ptr(), libc::O_RDWR, 0);
+            if fd < 0 {
+                return Err(os_error("Unable to open shared memory"));
+            }
+            let mut stat: libc::stat = mem::zeroed();
+            if libc::fstat(fd, &mut stat) < 0 {
+                let err = os_error("Unable to query shared memory");
+                libc::close(fd);
+                return Err(err);
+            }
+            let len = stat.st_size as usize;
+            if len < HEADER_SIZE {
+                libc::close(fd);
+                return Err(FreenectError::new("Shared memory is not a frame ring"));
+            }
+            let ptr = libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            );
+            libc::close(fd);
+            if ptr == libc::MAP_FAILED {
+                return Err(os_error("Unable to map shared memory"));
+            }
+            let mapping = Mapping {
+                ptr: ptr as *mut u8,
+                len,
+            };
+            let header = mapping.header();
+            if header.magic.load(Ordering::Acquire) != MAGIC
+                || header.version.load(Ordering::Relaxed) != VERSION
+                || mapping.slot_count() == 0
+                || HEADER_SIZE + mapping.slot_count() as usize * slot_stride(mapping.slot_size())
+                    > len
+            {
+                return Err(FreenectError::new("Shared memory is not a frame ring"));
+            }
+            Ok(ShmSubscriber { mapping })
+        }
+    }
+
+    /// Returns the sequence number of the latest frame, 0 if none was published yet
+    pub fn latest_sequence(&self) -> u64 {
+        self.mapping.header().latest.load(Ordering::Acquire)
+    }
+
+    /// Returns the latest frame
+    pub fn latest(&self) -> Option<ShmFrameRef<'_>> {
+        self.get(self.latest_sequence())
+    }
+
+    /// Returns the frame with the given sequence number if it is still held by the ring
+    pub fn get(&self, sequence: u64) -> Option<ShmFrameRef<'_>> {
+        if sequence == 0 || sequence > self.latest_sequence() {
+            return None;
+        }
+        let (slot, slot_data) = self.mapping.slot(sequence);
+        let version = slot.version.load(Ordering::Acquire);
+        if version % 2 == 1 || slot.sequence.load(Ordering::Relaxed) != sequence {
+            return None;
+        }
+        let kind = ShmFrameKind::from_raw(slot.kind.load(Ordering::Relaxed))?;
+        let len = (slot.len.load(Ordering::Relaxed) as usize).min(self.mapping.slot_size());
+        let frame = ShmFrameRef {
+            slot,
+            version,
+            sequence,
+            kind,
+            timestamp: slot.timestamp.load(Ordering::Relaxed),
+            width: slot.width.load(Ordering::Relaxed),
+            height: slot.height.load(Ordering::Relaxed),
+            data: slot_data,
+            len,
+        };
+        if frame.is_valid() {
+            Some(frame)
+        } else {
+            None
+        }
+    }
+
+    /// Waits at most `timeout` for a frame newer than `sequence` and returns the latest sequence number
+    pub fn wait_newer(&self, sequence: u64, timeout: Duration) -> Option<u64> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let latest = self.latest_sequence();
+            if latest > sequence {
+                return Some(latest);
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+/// A frame in shared memory.
+///
+/// The publisher overwrites the slot once the ring wrapped around, so the data may change at any
+/// time. [`to_owned()`](#method.to_owned) and [`copy_into()`](#method.copy_into) copy it safely and
+/// tell whether the copy is consistent. [`pin()`](#method.pin) keeps the publisher from
+/// overwriting it, so it can be read without copying.
+#[derive(Clone, Copy)]
+pub struct ShmFrameRef<'a> {
+    slot: &'a Slot,
+    version: u64,
+    pub sequence: u64,
+    pub kind: ShmFrameKind,
+    pub timestamp: u32,
+    pub width: u32,
+    pub height: u32,
+    // The data within the slot
+    data: *const u8,
+    len: usize,
+}
+
+// The data is only read through atomics, or in place while its slot is pinned
+unsafe impl<'a> Send for ShmFrameRef<'a> {}
+unsafe impl<'a> Sync for ShmFrameRef<'a> {}
+
+impl<'a> ShmFrameRef<'a> {
+    /// Returns whether the slot still holds this frame, i.e. everything read so far is consistent
+    pub fn is_valid(&self) -> bool {
+        fence(Ordering::Acquire);
+        self.slot.version.load(Ordering::Relaxed) == self.version
+    }
+
+    /// Returns the size of the frame's data in bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the frame has no data
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Pins the slot of the frame, so the publisher skips it and the data can be read in place
+    /// until the returned guard is dropped. Returns `None` if the frame was overwritten meanwhile.
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::{DepthFrameRef, ShmPublisher, ShmSubscriber};
+    ///
+    /// let name = format!("/freenectrs-pin-{}", std::process::id());
+    /// let mut publisher = ShmPublisher::create(&name, 2, 8).unwrap();
+    /// let subscriber = ShmSubscriber::open(&name).unwrap();
+    /// let data = [1u16; 4];
+    /// let frame = DepthFrameRef { data: &data, timestamp: 0, width: 2, height: 2 };
+    ///
+    /// assert_eq!(publisher.publish_depth(&frame).unwrap(), 1);
+    /// let first = subscriber.latest().unwrap().pin().unwrap();
+    /// // The slot of frame 1 is skipped, together with the sequence number 3
+    /// assert_eq!(publisher.publish_depth(&frame).unwrap(), 2);
+    /// assert_eq!(publisher.publish_depth(&frame).unwrap(), 4);
+    /// let latest = subscriber.latest().unwrap().pin().unwrap();
+    /// assert!(publisher.publish_depth(&frame).is_err());
+    ///
+    /// assert_eq!((first.sequence, first.depth_data()), (1, Some(&data[..])));
+    /// assert_eq!(latest.sequence, 4);
+    /// drop(first);
+    /// assert_eq!(publisher.publish_depth(&frame).unwrap(), 5);
+    /// ```
+    pub fn pin(&self) -> Option<ShmFrameGuard<'a>> {
+        self.slot.readers.fetch_add(1, Ordering::SeqCst);
+        let guard = ShmFrameGuard { frame: *self };
+        if self.slot.version.load(Ordering::SeqCst) == self.version {
+            Some(guard)
+        } else {
+            None
+        }
+    }
+
+    /// Copies the data into `buffer`, resizing it to the size of the data. Returns whether the copy
+    /// is consistent, i.e. the frame wasn't overwritten meanwhile.
+    pub fn copy_into(&self, buffer: &mut Vec<u8>) -> bool {
+        buffer.resize(self.len, 0);
+        unsafe {
+            load_shared(self.data, buffer);
+        }
+        self.is_valid()
+    }
+
+    /// Copies the frame. Returns `None` if it was overwritten meanwhile.
+    pub fn to_owned(&self) -> Option<ShmFrame> {
+        let mut data = Vec::new();
+        if !self.copy_into(&mut data) {
+            return None;
+        }
+        Some(ShmFrame {
+            sequence: self.sequence,
+            kind: self.kind,
+            timestamp: self.timestamp,
+            width: self.width,
+            height: self.height,
+            data,
+        })
+    }
+}
+
+/// A frame copied out of shared memory
+#[derive(Clone, Debug)]
+pub struct ShmFrame {
+    pub sequence: u64,
+    pub kind: ShmFrameKind,
+    pub timestamp: u32,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl ShmFrame {
+    /// Converts a depth frame, returns `None` for a video frame
+    pub fn to_depth_frame(&self) -> Option<DepthFrame> {
+        if self.kind != ShmFrameKind::Depth {
+            return None;
+        }
+        Some(DepthFrame {
+            data: self
+                .data
+                .chunks_exact(2)
+                .map(|bytes| u16::from_ne_bytes([bytes[0], bytes[1]]))
+                .collect(),
+            timestamp: self.timestamp,
+            width: self.width,
+            height: self.height,
+        })
+    }
+
+    /// Converts a video frame, returns `None` for a depth frame
+    pub fn to_video_frame(&self) -> Option<VideoFrame> {
+        if self.kind != ShmFrameKind::Video {
+            return None;
+        }
+        Some(VideoFrame {
+            data: self.data.clone(),
+            timestamp: self.timestamp,
+            width: self.width,
+            height: self.height,
+        })
+    }
+}
+
+/// A frame whose slot is pinned, see [`ShmFrameRef::pin()`](struct.ShmFrameRef.html#method.pin).
+/// Dropping it lets the publisher overwrite the slot again.
+pub struct ShmFrameGuard<'a> {
+    frame: ShmFrameRef<'a>,
+}
+
+impl<'a> ShmFrameGuard<'a> {
+    /// Returns the data in place
+    pub fn data(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.frame.data, self.frame.len) }
+    }
+
+    /// Returns the data of a depth frame in place as depth values, `None` for a video frame
+    pub fn depth_data(&self) -> Option<&[u16]> {
+        if self.frame.kind != ShmFrameKind::Depth {
+            return None;
+        }
+        // The slots are aligned to 8 bytes
+        Some(unsafe { slice::from_raw_parts(self.frame.data as *const u16, self.frame.len / 2) })
+    }
+}
+
+impl<'a> Deref for ShmFrameGuard<'a> {
+    type Target = ShmFrameRef<'a>;
+
+    fn deref(&self) -> &ShmFrameRef<'a> {
+        &self.frame
+    }
+}
+
+impl Drop for ShmFrameGuard<'_> {
+    fn drop(&mut self) {
+        self.frame.slot.readers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
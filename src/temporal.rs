@@ -0,0 +1,213 @@
+//! Filtering depth over time, against pixels flickering between frames.
+use crate::freenect::{DepthFrame, DepthFrameRef, FreenectDepthFormat, FreenectDepthStream};
+use std::sync::mpsc::{RecvError, RecvTimeoutError, TryRecvError};
+use std::time::Duration;
+
+/// The settings of a [`TemporalFilter`](struct.TemporalFilter.html)
+#[derive(Clone, Copy, Debug)]
+pub struct TemporalConfig {
+    /// How far a new reading moves the average towards it, from 0 to 1. Smaller values smooth
+    /// more but follow changes later.
+    pub alpha: f32,
+    /// Readings further away from the average, in the units of the frame, restart the average,
+    /// so objects moving through the scene leave no trails
+    pub threshold: u16,
+    /// For how many frames a pixel without a reading keeps its last average
+    pub persistence: u32,
+    /// How many agreeing readings in a row make a pixel fully confident
+    pub stable_frames: u16,
+    /// The format of the frames, which tells readings apart from pixels without one.
+    /// [`FreenectDepthStream::temporal()`](struct.FreenectDepthStream.html#method.temporal) uses
+    /// the format the stream's device delivers instead.
+    pub format: FreenectDepthFormat,
+}
+
+impl Default for TemporalConfig {
+    /// For frames in millimeters, an alpha of 0.4, a threshold of 50, a persistence of 3 frames and
+    /// full confidence after 15 frames, half a second at 30 frames per second
+    fn default() -> TemporalConfig {
+        TemporalConfig {
+            alpha: 0.4,
+            threshold: 50,
+            persistence: 3,
+            stable_frames: 15,
+            format: FreenectDepthFormat::MM,
+        }
+    }
+}
+
+/// What the filter remembers about a pixel
+#[derive(Clone, Copy, Default)]
+struct PixelState {
+    /// The average of recent readings, `None` until the first reading or after the persistence ran out
+    average: Option<f32>,
+    /// The frames since the last reading
+    missing: u32,
+    /// The agreeing readings in a row
+    stable: u16,
+}
+
+/// Smooths depth over time with a per-pixel exponential moving average, holds the last value of
+/// pixels which lose their reading for a few frames and tracks how stable each pixel is.
+///
+/// It is fed one frame after another. Use [`TemporalDepthStream`](struct.TemporalDepthStream.html)
+/// to filter all frames of a depth stream.
+/// # Examples
+/// ```rust
+/// use freenectrs::freenect::{DepthFrameRef, TemporalConfig, TemporalFilter};
+///
+/// let config = TemporalConfig { alpha: 0.5, persistence: 1, stable_frames: 2, ..TemporalConfig::default() };
+/// let mut filter = TemporalFilter::new(config);
+/// let mut filter_frame = |data: &[u16]| {
+///     filter.filter(&DepthFrameRef { data, timestamp: 0, width: 3, height: 1 }).data
+/// };
+/// assert_eq!(filter_frame(&[1000, 1000, 1000]), vec![1000, 1000, 1000]);
+/// // Flicker is smoothed, a jump is followed at once and a missing reading is held
+/// assert_eq!(filter_frame(&[1010, 2000, 0]), vec![1005, 2000, 1000]);
+/// // Until the persistence runs out
+/// assert_eq!(filter_frame(&[1010, 2000, 0]), vec![1008, 2000, 0]);
+/// assert_eq!(filter.confidence(), vec![1.0, 0.5, 0.0]);
+/// ```
+pub struct TemporalFilter {
+    config: TemporalConfig,
+    pixels: Vec<PixelState>,
+}
+
+impl TemporalFilter {
+    pub fn new(config: TemporalConfig) -> TemporalFilter {
+        TemporalFilter {
+            config,
+            pixels: Vec::new(),
+        }
+    }
+
+    /// Returns the settings of the filter
+    pub fn config(&self) -> TemporalConfig {
+        self.config
+    }
+
+    /// Filters the next frame. A frame of another size than the previous one resets the filter.
+    pub fn filter(&mut self, frame: &DepthFrameRef) -> DepthFrame {
+        if self.pixels.len() != frame.data.len() {
+            self.reset();
+            self.pixels.resize(frame.data.len(), PixelState::default());
+        }
+        let config = self.config;
+        let data = frame
+            .data
+            .iter()
+            .zip(&mut self.pixels)
+            .map(|(&value, pixel)| {
+                if !config.format.is_reading(value) {
+                    pixel.missing = pixel.missing.saturating_add(1);
+                    pixel.stable = 0;
+                    if pixel.missing > config.persistence {
+                        pixel.average = None;
+                    }
+                    return pixel
+                        .average
+                        .map_or(value, |average| average.round() as u16);
+                }
+                pixel.missing = 0;
+                let average = match pixel.average {
+                    Some(average) if (value as f32 - average).abs() <= config.threshold as f32 => {
+                        pixel.stable = pixel.stable.saturating_add(1);
+                        average + config.alpha * (value as f32 - average)
+                    }
+                    _ => {
+                        pixel.stable = 0;
+                        value as f32
+                    }
+                };
+                pixel.average = Some(average);
+                average.round() as u16
+            })
+            .collect();
+        DepthFrame {
+            data,
+            timestamp: frame.timestamp,
+            width: frame.width,
+            height: frame.height,
+        }
+    }
+
+    /// Returns how stable each pixel has been, row by row: 0 for pixels which just changed or
+    /// lost their reading, rising to 1 after [`stable_frames`](struct.TemporalConfig.html#structfield.stable_frames)
+    /// agreeing readings in a row.
+    pub fn confidence(&self) -> Vec<f32> {
+        let stable_frames = self.config.stable_frames.max(1) as f32;
+        self.pixels
+            .iter()
+            .map(|pixel| (pixel.stable as f32 / stable_frames).min(1.0))
+            .collect()
+    }
+
+    /// Forgets all previous frames
+    pub fn reset(&mut self) {
+        self.pixels.clear();
+    }
+}
+
+/// A depth stream whose frames are filtered by a [`TemporalFilter`](struct.TemporalFilter.html),
+/// created with [`FreenectDepthStream::temporal()`](struct.FreenectDepthStream.html#method.temporal).
+///
+/// Its methods mirror those of the stream's receiver. With the `async` feature, it also
+/// implements `futures_core::Stream`.
+/// # Examples
+/// ```rust,no_run
+/// use freenectrs::freenect::{FreenectContext, TemporalConfig};
+///
+/// let ctx = FreenectContext::init_with_video().unwrap();
+/// let device = ctx.open_device(0).unwrap();
+/// let mut dstream = device.depth_stream().unwrap().temporal(TemporalConfig::default());
+/// ctx.spawn_process_thread().unwrap();
+/// while let Ok(frame) = dstream.recv() {
+///     let confidence = dstream.filter.confidence();
+///     // ... use the filtered frame, maybe only where the confidence is high
+/// }
+/// ```
+pub struct TemporalDepthStream {
+    pub stream: FreenectDepthStream,
+    pub filter: TemporalFilter,
+}
+
+impl TemporalDepthStream {
+    /// Waits for the next frame and filters it
+    pub fn recv(&mut self) -> Result<DepthFrame, RecvError> {
+        let frame = self.stream.receiver.recv()?;
+        Ok(self.filter.filter(&frame.as_frame_ref()))
+    }
+
+    /// Filters the next frame if one is available without waiting
+    pub fn try_recv(&mut self) -> Result<DepthFrame, TryRecvError> {
+        let frame = self.stream.receiver.try_recv()?;
+        Ok(self.filter.filter(&frame.as_frame_ref()))
+    }
+
+    /// Waits at most `timeout` for the next frame and filters it
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<DepthFrame, RecvTimeoutError> {
+        let frame = self.stream.receiver.recv_timeout(timeout)?;
+        Ok(self.filter.filter(&frame.as_frame_ref()))
+    }
+
+    /// Returns the unfiltered stream
+    pub fn into_inner(self) -> FreenectDepthStream {
+        self.stream
+    }
+}
+
+impl FreenectDepthStream {
+    /// Filters all further frames of the stream over time, see [`TemporalFilter`](struct.TemporalFilter.html).
+    /// Readings are told apart in the format the device delivers, which only changes by restarting
+    /// depth. The `format` of `config` is used if the device doesn't report it.
+    pub fn temporal(self, config: TemporalConfig) -> TemporalDepthStream {
+        let format = self
+            .device()
+            .current_depth_mode()
+            .map_or(config.format, |mode| mode.format);
+        TemporalDepthStream {
+            stream: self,
+            filter: TemporalFilter::new(TemporalConfig { format, ..config }),
+        }
+    }
+}
@@ -0,0 +1,397 @@
+//! A live viewer for terminals, enabled by the `tui` feature.
+//!
+//! Images are drawn with the upper half block `▀`, whose foreground and background colours show
+//! two pixels above each other, in 24-bit colour. This works in most terminal emulators and over SSH.
+use crate::fakenect::FakenectDump;
+use crate::freenect::{
+    DeliveryPolicy, DepthColorizer, DepthFrame, FreenectContext, FreenectDepthFormat,
+    FreenectDevice, FreenectError, FreenectTiltState, OverflowStrategy, Result, VideoFrame,
+};
+use crate::group::{DeviceGroup, Frame};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, queue, terminal};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+// The status is shown in the last lines of the terminal
+const STATUS_ROWS: u16 = 2;
+const STATUS_INTERVAL: Duration = Duration::from_millis(500);
+
+fn io_error(err: io::Error) -> FreenectError {
+    FreenectError::new(format!("Unable to draw to the terminal: {}", err))
+}
+
+/// Renders an rgb image into `rows` lines of `cols` terminal cells each, two pixels per cell.
+///
+/// The image is scaled to `cols` × `2 * rows` pixels by averaging. Each line holds the escape
+/// sequences for its colours and ends by resetting them.
+/// # Examples
+/// ```rust
+/// use freenectrs::freenect::half_blocks;
+///
+/// // A red pixel above a blue one, next to two white ones
+/// let rgb = [255, 0, 0, 255, 255, 255, 0, 0, 255, 255, 255, 255];
+/// let lines = half_blocks(&rgb, 2, 2, 2, 1);
+/// assert_eq!(
+///     lines,
+///     vec!["\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m▀\x1b[38;2;255;255;255m\x1b[48;2;255;255;255m▀\x1b[0m"]
+/// );
+/// ```
+pub fn half_blocks(rgb: &[u8], width: u32, height: u32, cols: u16, rows: u16) -> Vec<String> {
+    let (width, height) = (width as usize, height as usize);
+    if width == 0 || height == 0 || rgb.len() < 3 * width * height {
+        return vec![String::new(); rows as usize];
+    }
+    let (cols, pixel_rows) = (cols as usize, 2 * rows as usize);
+    // Averages the source pixels covered by one scaled pixel
+    let pixel = |x: usize, y: usize| -> [u8; 3] {
+        let (x0, x1) = (
+            x * width / cols,
+            ((x + 1) * width / cols).max(x * width / cols + 1),
+        );
+        let (y0, y1) = (
+            y * height / pixel_rows,
+            ((y + 1) * height / pixel_rows).max(y * height / pixel_rows + 1),
+        );
+        let mut sum = [0u32; 3];
+        for row in y0..y1.min(height) {
+            for col in x0..x1.min(width) {
+                let i = 3 * (row * width + col);
+                for (sum, &value) in sum.iter_mut().zip(&rgb[i..i + 3]) {
+                    *sum += value as u32;
+                }
+            }
+        }
+        let count = ((y1.min(height) - y0) * (x1.min(width) - x0)).max(1) as u32;
+        [
+            (sum[0] / count) as u8,
+            (sum[1] / count) as u8,
+            (sum[2] / count) as u8,
+        ]
+    };
+    (0..rows as usize)
+        .map(|row| {
+            let mut line = String::new();
+            let (mut fg, mut bg) = (None, None);
+            for col in 0..cols {
+                let (upper, lower) = (pixel(col, 2 * row), pixel(col, 2 * row + 1));
+                // Colours are only sent when they change
+                if fg != Some(upper) {
+                    let _ = write!(line, "\x1b[38;2;{};{};{}m", upper[0], upper[1], upper[2]);
+                    fg = Some(upper);
+                }
+                if bg != Some(lower) {
+                    let _ = write!(line, "\x1b[48;2;{};{};{}m", lower[0], lower[1], lower[2]);
+                    bg = Some(lower);
+                }
+                line.push('▀');
+            }
+            line.push_str("\x1b[0m");
+            line
+        })
+        .collect()
+}
+
+/// Returns the number of cells an image fits into, keeping its aspect ratio
+fn fit(width: u32, height: u32, cols: u16, rows: u16) -> (u16, u16) {
+    if width == 0 || height == 0 {
+        return (0, 0);
+    }
+    // Cells are about twice as high as wide, so a cell shows two square pixels
+    let cols = (cols as u64).min(2 * rows as u64 * width as u64 / height as u64);
+    let rows = cols * height as u64 / width as u64 / 2;
+    (cols as u16, rows as u16)
+}
+
+/// The settings of a [`TerminalViewer`](struct.TerminalViewer.html)
+#[derive(Clone, Copy, Debug)]
+pub struct ViewerConfig {
+    /// How far the Up and Down keys tilt the device, in degrees
+    pub tilt_step: f64,
+    /// How depth is drawn. Depth of a device is drawn in the format the device delivers.
+    pub depth_colors: DepthColorizer,
+    /// How long to wait between redraws
+    pub refresh_interval: Duration,
+}
+
+impl Default for ViewerConfig {
+    /// Tilt steps of 10 degrees like in the `kinect_live` example, the default depth colours and 15 redraws per second
+    fn default() -> ViewerConfig {
+        ViewerConfig {
+            tilt_step: 10.0,
+            depth_colors: DepthColorizer::default(),
+            refresh_interval: Duration::from_millis(66),
+        }
+    }
+}
+
+/// What is shown on the terminal
+#[derive(Default)]
+struct Screen {
+    depth: Option<DepthFrame>,
+    // The format of the device's depth frames, the colorizer's own format is used for recordings
+    depth_format: Option<FreenectDepthFormat>,
+    video: Option<VideoFrame>,
+    tilt: Option<FreenectTiltState>,
+    depth_fps: f64,
+    video_fps: f64,
+    dropped_frames: Option<u64>,
+    message: String,
+}
+
+impl Screen {
+    fn show(&mut self, frame: Frame) {
+        match frame {
+            Frame::Depth(frame) => self.depth = Some(frame),
+            Frame::Video(frame) => self.video = Some(frame),
+        }
+    }
+}
+
+/// Measures frame rates from frame counts
+struct RateMeter {
+    since: Instant,
+    depth_frames: u64,
+    video_frames: u64,
+}
+
+impl RateMeter {
+    fn new() -> RateMeter {
+        RateMeter {
+            since: Instant::now(),
+            depth_frames: 0,
+            video_frames: 0,
+        }
+    }
+
+    /// Updates the rates of `screen` from the total number of frames once per status interval
+    fn update(&mut self, screen: &mut Screen, depth_frames: u64, video_frames: u64) -> bool {
+        let elapsed = self.since.elapsed();
+        if elapsed < STATUS_INTERVAL {
+            return false;
+        }
+        let seconds = elapsed.as_secs_f64();
+        screen.depth_fps = depth_frames.saturating_sub(self.depth_frames) as f64 / seconds;
+        screen.video_fps = video_frames.saturating_sub(self.video_frames) as f64 / seconds;
+        *self = RateMeter {
+            since: Instant::now(),
+            depth_frames,
+            video_frames,
+        };
+        true
+    }
+}
+
+/// Switches the terminal to raw mode on an alternate screen and back when dropped
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter(out: &mut impl Write) -> io::Result<TerminalGuard> {
+        terminal::enable_raw_mode()?;
+        let guard = TerminalGuard;
+        crossterm::execute!(
+            out,
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            terminal::Clear(terminal::ClearType::All)
+        )?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = crossterm::execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// A key the viewer reacts to
+enum Key {
+    Up,
+    Down,
+    Quit,
+}
+
+/// Waits up to `timeout` for a key press
+fn wait_key(timeout: Duration) -> io::Result<Option<Key>> {
+    if !event::poll(timeout)? {
+        return Ok(None);
+    }
+    Ok(match event::read()? {
+        Event::Key(key) if key.kind != KeyEventKind::Release => match key.code {
+            KeyCode::Up => Some(Key::Up),
+            KeyCode::Down => Some(Key::Down),
+            KeyCode::Char('q') | KeyCode::Esc => Some(Key::Quit),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Some(Key::Quit),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// Shows depth and rgb frames side by side in the terminal, together with tilt, accelerometer,
+/// frame rates and dropped frames. Up and Down tilt the device, Q or Escape quit.
+/// # Examples
+/// ```rust,no_run
+/// use freenectrs::freenect::{FreenectContext, TerminalViewer, ViewerConfig};
+///
+/// let ctx = FreenectContext::init_with_video_motor().unwrap();
+/// let device = ctx.open_device(0).unwrap();
+/// TerminalViewer::new(ViewerConfig::default()).run_device(&ctx, &device).unwrap();
+/// ```
+pub struct TerminalViewer {
+    config: ViewerConfig,
+}
+
+impl TerminalViewer {
+    pub fn new(config: ViewerConfig) -> TerminalViewer {
+        TerminalViewer { config }
+    }
+
+    /// Shows the streams of `device` until the user quits. The process thread is spawned if it isn't running yet.
+    pub fn run_device(&self, ctx: &FreenectContext, device: &FreenectDevice) -> Result<()> {
+        let group = DeviceGroup::from_devices(ctx, vec![device.clone()]);
+        // Depth and rgb frames share the queue, so it must hold more than one frame
+        let policy = DeliveryPolicy::Queue {
+            capacity: 4,
+            overflow: OverflowStrategy::DropOldest,
+        };
+        let stream = group.start(true, true, policy)?;
+        let mut out = io::stdout();
+        let _guard = TerminalGuard::enter(&mut out).map_err(io_error)?;
+        let mut screen = Screen {
+            depth_format: device.current_depth_mode().map(|mode| mode.format),
+            ..Screen::default()
+        };
+        let mut rates = RateMeter::new();
+        loop {
+            while let Ok(tagged) = stream.receiver.try_recv() {
+                screen.show(tagged.frame);
+            }
+            let stats = group.stats().pop().unwrap_or_default();
+            if rates.update(&mut screen, stats.depth_frames, stats.video_frames) {
+                screen.tilt = device.get_tilt_state().ok();
+                screen.dropped_frames = Some(stats.dropped_frames);
+            }
+            self.draw(&mut out, &screen).map_err(io_error)?;
+            let step = match wait_key(self.config.refresh_interval).map_err(io_error)? {
+                Some(Key::Quit) => return Ok(()),
+                Some(Key::Up) => self.config.tilt_step,
+                Some(Key::Down) => -self.config.tilt_step,
+                None => continue,
+            };
+            let tilted = device
+                .get_tilt_degree()
+                .and_then(|degree| device.set_tilt_degree(degree + step));
+            screen.message = match tilted {
+                Ok(()) => String::new(),
+                Err(err) => err.to_string(),
+            };
+        }
+    }
+
+    /// Shows the frames of a recording at the pace they were recorded at, until it ends or the user quits
+    pub fn run_dump(&self, dump: &FakenectDump) -> Result<()> {
+        let mut out = io::stdout();
+        let _guard = TerminalGuard::enter(&mut out).map_err(io_error)?;
+        let mut screen = Screen::default();
+        let mut rates = RateMeter::new();
+        let (mut depth_frames, mut video_frames) = (0, 0);
+        let start = Instant::now();
+        let mut first: Option<f64> = None;
+        let mut frames = dump.frames().peekable();
+        loop {
+            // Shows all frames which are due
+            while let Some(Ok((time, _))) = frames.peek() {
+                let offset = time - *first.get_or_insert(*time);
+                if offset > start.elapsed().as_secs_f64() {
+                    break;
+                }
+                if let Some(Ok((_, frame))) = frames.next() {
+                    match frame {
+                        Frame::Depth(_) => depth_frames += 1,
+                        Frame::Video(_) => video_frames += 1,
+                    }
+                    screen.show(frame);
+                }
+            }
+            match frames.peek() {
+                Some(Err(_)) => return frames.next().unwrap().map(|_| ()),
+                None => screen.message = "End of recording".to_owned(),
+                _ => (),
+            }
+            rates.update(&mut screen, depth_frames, video_frames);
+            self.draw(&mut out, &screen).map_err(io_error)?;
+            if let Some(Key::Quit) = wait_key(self.config.refresh_interval).map_err(io_error)? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn draw(&self, out: &mut impl Write, screen: &Screen) -> io::Result<()> {
+        let (cols, rows) = terminal::size()?;
+        let image_rows = rows.saturating_sub(STATUS_ROWS);
+        // Depth on the left and rgb on the right, with a column between them
+        let image_cols = cols.saturating_sub(1) / 2;
+        let depth = screen.depth.as_ref().map(|frame| {
+            let (cols, rows) = fit(frame.width, frame.height, image_cols, image_rows);
+            let colors = DepthColorizer {
+                format: screen
+                    .depth_format
+                    .unwrap_or(self.config.depth_colors.format),
+                ..self.config.depth_colors
+            };
+            let rgb = colors.colorize(&frame.as_frame_ref());
+            half_blocks(&rgb, frame.width, frame.height, cols, rows)
+        });
+        let video = screen.video.as_ref().map(|frame| {
+            let (cols, rows) = fit(frame.width, frame.height, image_cols, image_rows);
+            half_blocks(&frame.data, frame.width, frame.height, cols, rows)
+        });
+        for row in 0..image_rows {
+            queue!(
+                out,
+                cursor::MoveTo(0, row),
+                terminal::Clear(terminal::ClearType::CurrentLine)
+            )?;
+            if let Some(line) = depth.as_ref().and_then(|lines| lines.get(row as usize)) {
+                out.write_all(line.as_bytes())?;
+            }
+            queue!(out, cursor::MoveTo(image_cols + 1, row))?;
+            if let Some(line) = video.as_ref().and_then(|lines| lines.get(row as usize)) {
+                out.write_all(line.as_bytes())?;
+            }
+        }
+
+        let tilt = match screen.tilt {
+            Some(state) => {
+                let [x, y, z] = state.accelerometer;
+                format!(
+                    "Tilt {:.1}° ({})  Accelerometer {:.2} {:.2} {:.2} m/s²",
+                    state.degree, state.status, x, y, z
+                )
+            }
+            None => "Tilt unknown".to_owned(),
+        };
+        let dropped = match screen.dropped_frames {
+            Some(dropped) => format!("  Dropped {}", dropped),
+            None => String::new(),
+        };
+        let rates = format!(
+            "Depth {:.1} fps  Rgb {:.1} fps{}  ↑/↓ tilt  q quit  {}",
+            screen.depth_fps, screen.video_fps, dropped, screen.message
+        );
+        for (row, text) in [(image_rows, tilt), (image_rows + 1, rates)] {
+            let text: String = text.chars().take(cols as usize).collect();
+            queue!(
+                out,
+                cursor::MoveTo(0, row),
+                terminal::Clear(terminal::ClearType::CurrentLine)
+            )?;
+            out.write_all(text.as_bytes())?;
+        }
+        out.flush()
+    }
+}
@@ -0,0 +1,340 @@
+//! Turning depth into colours for display.
+//!
+//! The types of this module are also available from the [`freenect`](../freenect/index.html) module.
+use crate::freenect::{DepthFrameRef, FreenectDepthFormat};
+#[cfg(feature = "image")]
+use image::RgbImage;
+use std::ops::{Div, Sub};
+
+// The approximate focal length of the depth camera at 640 pixels width, for the slopes of hill-shading
+const FOCAL_LENGTH: f32 = 580.0;
+
+// The direction light falls from for hill-shading: from the top left towards the scene
+const LIGHT: [f32; 3] = [-0.5, -0.5, std::f32::consts::FRAC_1_SQRT_2];
+
+/// A colour map from 0 to 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMap {
+    /// Google's rainbow map from dark blue over green to dark red, with even lightness steps
+    Turbo,
+    /// The perceptually uniform map of matplotlib from dark purple over teal to yellow
+    Viridis,
+    /// The classic map from dark blue over cyan and yellow to dark red
+    Jet,
+    /// Grey from black to white
+    InverseGrey,
+}
+
+impl ColorMap {
+    /// Returns the colour at `t`, which is clamped to 0 to 1
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::ColorMap;
+    ///
+    /// assert_eq!(ColorMap::InverseGrey.color(0.0), [0, 0, 0]);
+    /// assert_eq!(ColorMap::InverseGrey.color(2.0), [255, 255, 255]);
+    /// assert_eq!(ColorMap::Jet.color(0.5), [128, 255, 128]);
+    /// ```
+    pub fn color(self, t: f32) -> [u8; 3] {
+        let t = t.clamp(0.0, 1.0);
+        let rgb = match self {
+            ColorMap::Turbo => turbo(t),
+            ColorMap::Viridis => viridis(t),
+            ColorMap::Jet => [
+                1.5 - (4.0 * t - 3.0).abs(),
+                1.5 - (4.0 * t - 2.0).abs(),
+                1.5 - (4.0 * t - 1.0).abs(),
+            ],
+            ColorMap::InverseGrey => [t, t, t],
+        };
+        [byte(rgb[0]), byte(rgb[1]), byte(rgb[2])]
+    }
+}
+
+fn byte(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+// The polynomial approximation of Turbo published by Google
+fn turbo(t: f32) -> [f32; 3] {
+    let channel = |c: [f32; 6]| c[0] + t * (c[1] + t * (c[2] + t * (c[3] + t * (c[4] + t * c[5]))));
+    [
+        channel([
+            0.135_721_38,
+            4.615_392_6,
+            -42.660_323,
+            132.131_08,
+            -152.942_39,
+            59.286_38,
+        ]),
+        channel([
+            0.091_402_61,
+            2.194_188_4,
+            4.842_966_6,
+            -14.185_033,
+            4.277_298_6,
+            2.829_566,
+        ]),
+        channel([
+            0.106_673_3,
+            12.641_946,
+            -60.582_05,
+            110.362_77,
+            -89.903_11,
+            27.348_25,
+        ]),
+    ]
+}
+
+// A polynomial fit of matplotlib's viridis
+fn viridis(t: f32) -> [f32; 3] {
+    let channel = |c: [f32; 7]| {
+        c[0] + t * (c[1] + t * (c[2] + t * (c[3] + t * (c[4] + t * (c[5] + t * c[6])))))
+    };
+    [
+        channel([
+            0.277_727_33,
+            0.105_093_04,
+            -0.330_861_83,
+            -4.634_230_5,
+            6.228_27,
+            4.776_385,
+            -5.435_456,
+        ]),
+        channel([
+            0.005_407_344_5,
+            1.404_613_5,
+            0.214_847_56,
+            -5.799_101,
+            14.179_933,
+            -13.745_145,
+            4.645_852_6,
+        ]),
+        channel([
+            0.334_099_8,
+            1.384_590_1,
+            0.095_095_165,
+            -19.332_441,
+            56.690_55,
+            -65.353_03,
+            26.312_435,
+        ]),
+    ]
+}
+
+/// Which depth values a [`DepthColorizer`](struct.DepthColorizer.html) spreads its colour map over
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DepthRange {
+    /// From the nearest to the farthest valid value of each frame
+    Auto,
+    /// Between two percentiles of the valid values of each frame, from 0 to 100, which ignores
+    /// outliers. They may be given in either order.
+    Percentiles(f32, f32),
+    /// Between fixed near and far values, which keeps colours steady while the scene changes.
+    /// They may be given in either order.
+    Fixed(u16, u16),
+}
+
+/// Draws depth frames with a colour map
+///
+/// Near values take the warm or bright end of the map and far values the cold or dark end.
+/// Values outside the range are clamped to its ends.
+/// # Examples
+/// ```rust
+/// use freenectrs::freenect::{ColorMap, DepthColorizer, DepthFrameRef, DepthRange, FreenectDepthFormat};
+///
+/// let colorizer = DepthColorizer {
+///     color_map: ColorMap::InverseGrey,
+///     range: DepthRange::Fixed(1000, 2000),
+///     invalid: [255, 0, 255],
+///     format: FreenectDepthFormat::MM,
+///     ..DepthColorizer::default()
+/// };
+/// let data = [500, 1000, 1500, 2000, 0];
+/// let frame = DepthFrameRef { data: &data, timestamp: 0, width: 5, height: 1 };
+/// let rgb = colorizer.colorize(&frame);
+/// assert_eq!(&rgb[0..3], &[255, 255, 255]);
+/// assert_eq!(&rgb[3..6], &[255, 255, 255]);
+/// assert_eq!(&rgb[6..9], &[127, 127, 127]);
+/// assert_eq!(&rgb[9..12], &[0, 0, 0]);
+/// // No reading
+/// assert_eq!(&rgb[12..15], &[255, 0, 255]);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthColorizer {
+    /// The colour map of valid values
+    pub color_map: ColorMap,
+    /// The values the colour map is spread over
+    pub range: DepthRange,
+    /// The colour of pixels without a reading
+    pub invalid: [u8; 3],
+    /// The format of the frames, which tells readings apart from pixels without one
+    pub format: FreenectDepthFormat,
+    /// How strongly to shade surfaces lit from the top left, from 0 to 1. Shading shows the shape
+    /// of surfaces which have nearly the same colour. `None` turns it off.
+    pub hill_shading: Option<f32>,
+}
+
+impl Default for DepthColorizer {
+    /// Turbo between the 2nd and 98th percentile, black pixels without a reading, no shading
+    /// and 11-bit depth, which devices deliver unless their depth mode is changed
+    fn default() -> DepthColorizer {
+        DepthColorizer {
+            color_map: ColorMap::Turbo,
+            range: DepthRange::Percentiles(2.0, 98.0),
+            invalid: [0, 0, 0],
+            format: FreenectDepthFormat::Bit11,
+            hill_shading: None,
+        }
+    }
+}
+
+/// Returns the value at `percentile` of the sorted `values`
+fn percentile(values: &mut [u16], percentile: f32) -> u16 {
+    let last = values.len() - 1;
+    let index = ((percentile.clamp(0.0, 100.0) / 100.0) * last as f32).round() as usize;
+    *values.select_nth_unstable(index).1
+}
+
+impl DepthColorizer {
+    /// Returns the near and far value of `data` the colour map is spread over, or `None` if there
+    /// are no valid values. The near value is never larger than the far one.
+    /// # Examples
+    /// ```rust
+    /// use freenectrs::freenect::{DepthColorizer, DepthRange, FreenectDepthFormat};
+    ///
+    /// let data: Vec<u16> = (1..=100).chain(vec![0, 2047, 9000]).collect();
+    /// let colorizer = DepthColorizer { range: DepthRange::Percentiles(10.0, 90.0), ..DepthColorizer::default() };
+    /// assert_eq!(colorizer.range_of(&data), Some((11, 91)));
+    /// let colorizer = DepthColorizer { range: DepthRange::Percentiles(90.0, 10.0), ..colorizer };
+    /// assert_eq!(colorizer.range_of(&data), Some((11, 91)));
+    /// let colorizer = DepthColorizer { range: DepthRange::Fixed(2000, 1000), ..colorizer };
+    /// assert_eq!(colorizer.range_of(&data), Some((1000, 2000)));
+    /// let colorizer = DepthColorizer { range: DepthRange::Auto, ..colorizer };
+    /// assert_eq!(colorizer.range_of(&data), Some((1, 9000)));
+    /// // 2047 marks pixels without a reading in 11-bit depth only
+    /// let colorizer = DepthColorizer { format: FreenectDepthFormat::MM, ..colorizer };
+    /// assert_eq!(colorizer.range_of(&[2047, 9000]), Some((2047, 9000)));
+    /// ```
+    pub fn range_of(&self, data: &[u16]) -> Option<(u16, u16)> {
+        match self.range {
+            DepthRange::Fixed(near, far) => Some((near.min(far), near.max(far))),
+            DepthRange::Auto => data
+                .iter()
+                .copied()
+                .filter(|&value| self.format.is_reading(value))
+                .fold(None, |range, value| match range {
+                    None => Some((value, value)),
+                    Some((near, far)) => Some((value.min(near), value.max(far))),
+                }),
+            DepthRange::Percentiles(low, high) => {
+                let mut values: Vec<u16> = data
+                    .iter()
+                    .copied()
+                    .filter(|&value| self.format.is_reading(value))
+                    .collect();
+                if values.is_empty() {
+                    return None;
+                }
+                let (near, far) = (percentile(&mut values, low), percentile(&mut values, high));
+                Some((near.min(far), near.max(far)))
+            }
+        }
+    }
+
+    /// Draws a frame as 8-bit rgb, 3 bytes per pixel
+    pub fn colorize(&self, frame: &DepthFrameRef) -> Vec<u8> {
+        let data = frame.data;
+        let (near, far) = match self.range_of(data) {
+            Some(range) => range,
+            None => return self.invalid.repeat(data.len()),
+        };
+        let span = far.saturating_sub(near).max(1) as f32;
+        // Evaluating the map once per shade keeps large frames fast
+        let lut: Vec<[u8; 3]> = (0..256)
+            .map(|i| self.color_map.color(1.0 - i as f32 / 255.0))
+            .collect();
+        let mut rgb = Vec::with_capacity(data.len() * 3);
+        for &value in data {
+            if !self.format.is_reading(value) {
+                rgb.extend_from_slice(&self.invalid);
+            } else {
+                let t = (value.clamp(near, far) - near) as f32 / span;
+                rgb.extend_from_slice(&lut[(t * 255.0).round() as usize]);
+            }
+        }
+        if let Some(strength) = self.hill_shading {
+            shade(&mut rgb, frame, self.format, strength.clamp(0.0, 1.0));
+        }
+        rgb
+    }
+
+    /// Draws a frame as an image. Returns `None` if the size of `data` doesn't match `width` and `height`.
+    #[cfg(feature = "image")]
+    pub fn colorize_image(&self, frame: &DepthFrameRef) -> Option<RgbImage> {
+        RgbImage::from_raw(frame.width, frame.height, self.colorize(frame))
+    }
+}
+
+/// Returns the difference of the neighbours before and after a pixel, or of the pixel and its one
+/// neighbour. The slopes of hill-shading and the surface normals both use it.
+pub(crate) fn difference<P, D>(value: P, before: Option<P>, after: Option<P>) -> Option<D>
+where
+    P: Sub<Output = D>,
+    D: Div<f32, Output = D>,
+{
+    match (before, after) {
+        (Some(before), Some(after)) => Some((after - before) / 2.0),
+        (Some(before), None) => Some(value - before),
+        (None, Some(after)) => Some(after - value),
+        (None, None) => None,
+    }
+}
+
+/// Darkens the pixels of surfaces facing away from the light. Flat surfaces facing the camera keep
+/// their colour.
+fn shade(rgb: &mut [u8], frame: &DepthFrameRef, format: FreenectDepthFormat, strength: f32) {
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    if frame.data.len() != width * height {
+        return;
+    }
+    let focal_length = FOCAL_LENGTH * width as f32 / 640.0;
+    let depth = |x: usize, y: usize| {
+        let value = frame.data[y * width + x];
+        if format.is_reading(value) {
+            Some(value as f32)
+        } else {
+            None
+        }
+    };
+    // The change of depth between two neighbours, relative to the size of a pixel at that depth
+    let slope = |z: f32, before: Option<f32>, after: Option<f32>| {
+        difference(z, before, after).map_or(0.0, |change: f32| change * focal_length / z)
+    };
+    for y in 0..height {
+        for x in 0..width {
+            let z = match depth(x, y) {
+                Some(z) => z,
+                None => continue,
+            };
+            let dx = slope(
+                z,
+                x.checked_sub(1).and_then(|x| depth(x, y)),
+                (x + 1 < width).then(|| depth(x + 1, y)).flatten(),
+            );
+            let dy = slope(
+                z,
+                y.checked_sub(1).and_then(|y| depth(x, y)),
+                (y + 1 < height).then(|| depth(x, y + 1)).flatten(),
+            );
+            // The normal (-dx, -dy, 1) points towards the camera
+            let norm = (dx * dx + dy * dy + 1.0).sqrt();
+            let light = (-dx * LIGHT[0] - dy * LIGHT[1] + LIGHT[2]) / norm;
+            let shade = (light / LIGHT[2]).clamp(0.0, 1.0);
+            let factor = 1.0 - strength + strength * shade;
+            for channel in &mut rgb[(y * width + x) * 3..][..3] {
+                *channel = (*channel as f32 * factor) as u8;
+            }
+        }
+    }
+}